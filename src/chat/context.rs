@@ -0,0 +1,384 @@
+//! Keeps the prompt sent to Ollama inside the model's context window.
+//!
+//! Ollama silently drops tokens from the front of an over-long prompt, which
+//! loses the system prompt first. Before each model call the history is
+//! measured against the window; if it does not fit, old tool results are cut
+//! down to their headers, and if that is still not enough the oldest turns
+//! are folded into a running summary kept on the session.
+
+use tracing::{debug, info, warn};
+
+use crate::ollama::types::GenerationOptions;
+use crate::ollama::OllamaClient;
+use crate::session::{ChatMessage, ConversationSummary, Role, Session};
+use crate::tools::parser::TOOL_RESULT_BLOCK;
+
+/// Rough average for English text with BPE tokenisers.
+const CHARS_PER_TOKEN: usize = 4;
+/// Role markers and separators added by the chat template.
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+/// Share of the window (1/n) kept free for the model's reply.
+const RESPONSE_RESERVE_DIVISOR: usize = 4;
+/// Share of the prompt budget (1/n) left for the summary itself.
+const SUMMARY_RESERVE_DIVISOR: usize = 8;
+/// Longest single message quoted to the summariser.
+const MAX_SUMMARY_INPUT_CHARS: usize = 1500;
+
+const SUMMARY_PROMPT: &str = "You keep a running summary of a conversation between a user and Ferret, a research assistant. Update the summary with the new messages. Keep facts, figures, names, the URLs of useful sources, what the user is trying to do and any open questions. Use short bullet points, at most 200 words. Reply with the summary only.";
+
+const COMPRESSED_NOTE: &str = "[Older result trimmed to save context]";
+
+/// Token estimate for one message, including any structured tool calls.
+pub fn estimate_tokens(message: &ChatMessage) -> usize {
+    let mut chars = message.content.chars().count();
+    for call in &message.tool_calls {
+        chars += call.function.name.len() + call.function.arguments.to_string().len();
+    }
+    chars.div_ceil(CHARS_PER_TOKEN) + MESSAGE_OVERHEAD_TOKENS
+}
+
+pub fn estimate_total(messages: &[ChatMessage]) -> usize {
+    messages.iter().map(estimate_tokens).sum()
+}
+
+/// How many tokens of prompt a model's context window can take.
+#[derive(Debug, Clone, Copy)]
+pub struct ContextBudget {
+    num_ctx: usize,
+}
+
+impl ContextBudget {
+    pub fn new(num_ctx: usize) -> Self {
+        Self { num_ctx }
+    }
+
+    /// Tokens available for the prompt once the reply has been allowed for.
+    pub fn prompt_tokens(&self) -> usize {
+        self.num_ctx - self.num_ctx / RESPONSE_RESERVE_DIVISOR
+    }
+}
+
+/// Build the messages for the next model call, compressing and summarising
+/// older history as needed. May update `session.summary`.
+pub async fn fit_context(
+    ollama: &OllamaClient,
+    model: &str,
+    options: &GenerationOptions,
+    session: &mut Session,
+    system: ChatMessage,
+) -> Vec<ChatMessage> {
+    let budget = ContextBudget::new(ollama.context_window(model, options).await);
+    let limit = budget.prompt_tokens();
+
+    let mut messages = assemble(&system, session);
+    if compress_to_fit(&mut messages, limit) {
+        return messages;
+    }
+
+    // Compression was not enough: fold older turns into the summary
+    let covered = covered(session);
+    let fixed = estimate_tokens(&system) + limit / SUMMARY_RESERVE_DIVISOR;
+    let Some(split) = summary_split(&session.messages, covered, limit.saturating_sub(fixed)) else {
+        warn!(
+            "Current turn alone exceeds the context budget ({} tokens)",
+            limit
+        );
+        return messages;
+    };
+
+    info!(
+        "Summarising messages {}..{} to fit a {} token budget",
+        covered, split, limit
+    );
+
+    let existing = session.summary.as_ref().map(|s| s.text.as_str());
+    match summarise(ollama, model, options, existing, &session.messages[covered..split]).await {
+        Ok(text) => {
+            session.summary = Some(ConversationSummary {
+                text,
+                covers: split,
+            });
+            messages = assemble(&system, session);
+        }
+        Err(e) => {
+            // Without a summary the older turns are simply left out
+            warn!("Summarisation failed, dropping older turns: {}", e);
+            messages = vec![system.clone()];
+            messages.extend(summary_message(session));
+            messages.extend(session.messages[split..].iter().cloned());
+        }
+    }
+
+    if !compress_to_fit(&mut messages, limit) {
+        warn!(
+            "Prompt still exceeds the context budget after summarising ({} > {} tokens)",
+            estimate_total(&messages),
+            limit
+        );
+    }
+    messages
+}
+
+/// Number of leading messages already represented by the summary.
+fn covered(session: &Session) -> usize {
+    session
+        .summary
+        .as_ref()
+        .map_or(0, |s| s.covers.min(session.messages.len()))
+}
+
+fn summary_message(session: &Session) -> Option<ChatMessage> {
+    session.summary.as_ref().map(|summary| {
+        ChatMessage::new(
+            Role::System,
+            format!("Summary of the earlier conversation:\n{}", summary.text),
+        )
+    })
+}
+
+fn assemble(system: &ChatMessage, session: &Session) -> Vec<ChatMessage> {
+    let mut messages = vec![system.clone()];
+    messages.extend(summary_message(session));
+    messages.extend(session.messages[covered(session)..].iter().cloned());
+    messages
+}
+
+/// Index of the message that starts the current turn, which is never
+/// compressed or summarised.
+fn current_turn_start(messages: &[ChatMessage]) -> usize {
+    messages
+        .iter()
+        .rposition(|m| m.role == Role::User)
+        .unwrap_or(0)
+}
+
+/// Trim tool results oldest first until the prompt fits. Returns whether it
+/// now does.
+fn compress_to_fit(messages: &mut [ChatMessage], limit: usize) -> bool {
+    let mut total = estimate_total(messages);
+    if total <= limit {
+        return true;
+    }
+
+    let protected = current_turn_start(messages);
+    for message in messages[..protected].iter_mut() {
+        let Some(compressed) = compress_tool_results(message) else {
+            continue;
+        };
+        let before = estimate_tokens(message);
+        message.content = compressed;
+        total = total - before + estimate_tokens(message);
+
+        if total <= limit {
+            debug!("Compressed old tool results to fit ({} tokens)", total);
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Cut the tool results in a message down to their header lines, or `None`
+/// if there is nothing to cut.
+fn compress_tool_results(message: &ChatMessage) -> Option<String> {
+    if message.content.contains(COMPRESSED_NOTE) {
+        return None;
+    }
+
+    let compressed = match message.role {
+        Role::Tool if !TOOL_RESULT_BLOCK.is_match(&message.content) => format!(
+            "[Tool Result: {}]\n{}\n[End Tool Result]",
+            message.tool_name.as_deref().unwrap_or("tool"),
+            COMPRESSED_NOTE
+        ),
+        // Tag mode stores results inline in the assistant message
+        Role::Tool | Role::Assistant => TOOL_RESULT_BLOCK
+            .replace_all(&message.content, |caps: &regex::Captures| {
+                result_headers(&caps[0])
+            })
+            .into_owned(),
+        _ => return None,
+    };
+
+    (compressed.len() < message.content.len()).then_some(compressed)
+}
+
+/// Keep what identifies a tool result: the tool, query, URLs, titles and
+/// any error.
+fn result_headers(block: &str) -> String {
+    let mut lines: Vec<&str> = block
+        .lines()
+        .filter(|line| {
+            let line = line.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == ' ');
+            line.starts_with("[Tool Result:")
+                || line.starts_with("Query:")
+                || line.starts_with("URL:")
+                || line.starts_with("Title:")
+                || line.starts_with("Error:")
+        })
+        .collect();
+    lines.push(COMPRESSED_NOTE);
+    lines.push("[End Tool Result]");
+    lines.join("\n")
+}
+
+/// Choose where the kept tail of the history starts: the earliest user
+/// message after `covered` whose tail fits in `available` once older tool
+/// results are compressed. `None` if there is nothing to summarise.
+fn summary_split(messages: &[ChatMessage], covered: usize, available: usize) -> Option<usize> {
+    let current = current_turn_start(messages);
+    if current <= covered {
+        return None;
+    }
+
+    let cost = |i: usize| {
+        let message = &messages[i];
+        if i >= current {
+            return estimate_tokens(message);
+        }
+        match compress_tool_results(message) {
+            Some(content) => estimate_tokens(&ChatMessage {
+                content,
+                ..message.clone()
+            }),
+            None => estimate_tokens(message),
+        }
+    };
+
+    // Walk back from the end, extending the tail while it still fits
+    let mut tail: usize = (current..messages.len()).map(cost).sum();
+    let mut split = current;
+    for i in (covered + 1..current).rev() {
+        tail += cost(i);
+        if tail > available {
+            break;
+        }
+        if messages[i].role == Role::User {
+            split = i;
+        }
+    }
+
+    Some(split)
+}
+
+async fn summarise(
+    ollama: &OllamaClient,
+    model: &str,
+    options: &GenerationOptions,
+    existing: Option<&str>,
+    messages: &[ChatMessage],
+) -> Result<String, crate::error::AppError> {
+    let transcript = messages
+        .iter()
+        .map(|message| {
+            let content = compress_tool_results(message).unwrap_or_else(|| message.content.clone());
+            let content = match content.char_indices().nth(MAX_SUMMARY_INPUT_CHARS) {
+                Some((end, _)) => format!("{}…", &content[..end]),
+                None => content,
+            };
+            let speaker = match message.role {
+                Role::User => "User".to_string(),
+                Role::Assistant => "Ferret".to_string(),
+                Role::Tool => format!("Tool ({})", message.tool_name.as_deref().unwrap_or("tool")),
+                Role::System => "System".to_string(),
+            };
+            format!("{}: {}", speaker, content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let request = format!(
+        "Current summary:\n{}\n\nNew messages:\n{}",
+        existing.unwrap_or("(none)"),
+        transcript
+    );
+
+    let summary = ollama
+        .chat(
+            model,
+            vec![
+                ChatMessage::new(Role::System, SUMMARY_PROMPT),
+                ChatMessage::new(Role::User, request),
+            ],
+            options,
+        )
+        .await?;
+
+    Ok(summary.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetch_result(body: &str) -> String {
+        format!(
+            "[Tool Result: fetch]\nURL: https://example.com\nContent-Type: text/html\nLength: {} characters\n\n{}\n[End Tool Result]",
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        let message = ChatMessage::new(Role::User, "a".repeat(400));
+        assert_eq!(estimate_tokens(&message), 100 + MESSAGE_OVERHEAD_TOKENS);
+        assert_eq!(ContextBudget::new(4096).prompt_tokens(), 3072);
+    }
+
+    #[test]
+    fn test_compress_keeps_headers() {
+        let message = ChatMessage::new(
+            Role::Assistant,
+            format!("Let me look.\n\n{}", fetch_result(&"x".repeat(2000))),
+        );
+        let compressed = compress_tool_results(&message).unwrap();
+
+        assert!(compressed.starts_with("Let me look."));
+        assert!(compressed.contains("URL: https://example.com"));
+        assert!(compressed.contains(COMPRESSED_NOTE));
+        assert!(!compressed.contains("xxxx"));
+
+        // Already compressed messages are left alone
+        let again = ChatMessage::new(Role::Assistant, compressed);
+        assert!(compress_tool_results(&again).is_none());
+        assert!(compress_tool_results(&ChatMessage::new(Role::User, "hi")).is_none());
+    }
+
+    #[test]
+    fn test_compress_to_fit_spares_current_turn() {
+        let mut messages = vec![
+            ChatMessage::new(Role::System, "system"),
+            ChatMessage::new(Role::User, "first question"),
+            ChatMessage::tool_result("fetch", fetch_result(&"old ".repeat(500))),
+            ChatMessage::new(Role::Assistant, "first answer"),
+            ChatMessage::new(Role::User, "second question"),
+            ChatMessage::tool_result("fetch", fetch_result(&"new ".repeat(500))),
+        ];
+
+        assert!(compress_to_fit(&mut messages, 700));
+        assert!(messages[2].content.contains(COMPRESSED_NOTE));
+        assert!(messages[5].content.contains("new new"));
+
+        // Nothing left to trim outside the current turn
+        assert!(!compress_to_fit(&mut messages, 100));
+    }
+
+    #[test]
+    fn test_summary_split() {
+        let turn = |n: usize| {
+            vec![
+                ChatMessage::new(Role::User, format!("question {} {}", n, "q".repeat(400))),
+                ChatMessage::new(Role::Assistant, format!("answer {} {}", n, "a".repeat(400))),
+            ]
+        };
+        let messages: Vec<ChatMessage> = (0..4).flat_map(turn).collect();
+
+        // Room for the current turn plus one earlier turn
+        assert_eq!(summary_split(&messages, 0, 450), Some(4));
+        // Room for the current turn only
+        assert_eq!(summary_split(&messages, 0, 100), Some(6));
+        // Everything before the current turn is already summarised
+        assert_eq!(summary_split(&messages, 6, 100), None);
+    }
+}
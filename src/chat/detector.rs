@@ -0,0 +1,193 @@
+use crate::tools::parser::{ends_tag_name, walk_prose};
+
+/// Incrementally scans streamed tokens for the start of a tool call.
+///
+/// Text is released as soon as it cannot be the beginning of a tool tag. A
+/// trailing fragment such as `<sea` is held back until the next token shows
+/// whether it becomes `<search>` or `<search count="5">`. Once an opening
+/// tag has been seen, nothing further is released so tool markup never
+/// reaches the client.
+///
+/// Markdown code is skipped as the tag parser skips it, so examples of the
+/// syntax stream normally. An inline code span that hasn't closed yet is
+/// held back until it does or its paragraph ends.
+#[derive(Debug, Default)]
+pub struct ToolTagDetector {
+    names: Vec<String>,
+    /// Everything streamed so far, as code can open anywhere before
+    text: String,
+    /// How much of `text` has been released
+    released: usize,
+    triggered: bool,
+}
+
+impl ToolTagDetector {
+    /// A detector for tags of the tools called `names`.
+    pub fn new(names: &[&str]) -> Self {
+        Self {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Feed a token, returning any text that is safe to show to the user.
+    pub fn push(&mut self, token: &str) -> Option<String> {
+        self.text.push_str(token);
+
+        if self.triggered {
+            return None;
+        }
+
+        let text = &self.text;
+        let mut tag = None;
+        let mut partial = None;
+        let open_span = walk_prose(text, |pos| {
+            if tag_starts_at(&self.names, text, pos) {
+                tag = Some(pos);
+                // Nothing after the first tag matters
+                Some(text.len())
+            } else {
+                if partial.is_none() && could_become_tag(&self.names, &text[pos..]) {
+                    partial = Some(pos);
+                }
+                None
+            }
+        });
+
+        let keep_from = match tag {
+            // A tag after an unclosed code span may turn out to be code
+            Some(start) if open_span.is_none_or(|span| start < span) => {
+                self.triggered = true;
+                start
+            }
+            _ => [open_span, partial]
+                .into_iter()
+                .flatten()
+                .min()
+                .unwrap_or(text.len()),
+        };
+
+        let released = self.released;
+        self.released = keep_from.max(released);
+        non_empty(self.text[released..self.released].to_string())
+    }
+
+    /// Whether an opening tool tag has been seen.
+    pub fn triggered(&self) -> bool {
+        self.triggered
+    }
+
+    /// Text that has been held back so far, including any tool markup.
+    pub fn into_pending(mut self) -> String {
+        self.text.split_off(self.released)
+    }
+}
+
+fn non_empty(text: String) -> Option<String> {
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+fn tag_starts_at(names: &[String], text: &str, pos: usize) -> bool {
+    names.iter().any(|name| {
+        text[pos + 1..]
+            .strip_prefix(name.as_str())
+            .and_then(|after| after.chars().next())
+            .is_some_and(ends_tag_name)
+    })
+}
+
+/// Whether `tail`, a trailing `<...` fragment, could still grow into a tag.
+fn could_become_tag(names: &[String], tail: &str) -> bool {
+    names.iter().any(|name| name.starts_with(&tail[1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(tokens: &[&str]) -> (String, ToolTagDetector) {
+        let mut detector = ToolTagDetector::new(&["search", "fetch"]);
+        let mut emitted = String::new();
+        for token in tokens {
+            if let Some(text) = detector.push(token) {
+                emitted.push_str(&text);
+            }
+        }
+        (emitted, detector)
+    }
+
+    #[test]
+    fn test_plain_text_passes_through() {
+        let (emitted, detector) = feed(&["Hello", " there", ", 1 < 2"]);
+        assert_eq!(emitted, "Hello there, 1 < 2");
+        assert!(!detector.triggered());
+    }
+
+    #[test]
+    fn test_tag_split_across_tokens_is_held_back() {
+        let (emitted, detector) = feed(&["Let me look. <se", "arch>rust", " news</search>"]);
+        assert_eq!(emitted, "Let me look. ");
+        assert!(detector.triggered());
+        assert_eq!(detector.into_pending(), "<search>rust news</search>");
+    }
+
+    #[test]
+    fn test_false_alarm_is_released() {
+        let (emitted, detector) = feed(&["a <se", "e below"]);
+        assert_eq!(emitted, "a <see below");
+        assert!(!detector.triggered());
+    }
+
+    #[test]
+    fn test_tag_with_attributes() {
+        let (emitted, detector) = feed(&["Recent news: <search", " freshness=\"day\">rust</search>"]);
+        assert_eq!(emitted, "Recent news: ");
+        assert!(detector.triggered());
+
+        let (emitted, detector) = feed(&["<search", "ing for it"]);
+        assert_eq!(emitted, "<searching for it");
+        assert!(!detector.triggered());
+    }
+
+    #[test]
+    fn test_tags_in_code_stream_through() {
+        let (emitted, detector) = feed(&[
+            "Write it like this:\n\n``",
+            "`html\n<sea",
+            "rch>rust news</search>\n``",
+            "`\n\nDone.",
+        ]);
+        assert_eq!(
+            emitted,
+            "Write it like this:\n\n```html\n<search>rust news</search>\n```\n\nDone."
+        );
+        assert!(!detector.triggered());
+
+        // An inline span is held until it closes, then released
+        let (emitted, detector) = feed(&["Use `<search>", "query</search>", "` to search. "]);
+        assert_eq!(emitted, "Use `<search>query</search>` to search. ");
+        assert!(!detector.triggered());
+
+        let (emitted, detector) = feed(&["Try `<search>", "query</search>"]);
+        assert_eq!(emitted, "Try ");
+        assert!(!detector.triggered());
+        assert_eq!(detector.into_pending(), "`<search>query</search>");
+
+        // Real calls after code still stop the stream
+        let (emitted, detector) =
+            feed(&["`<fetch>` is next. ", "<fetch>https://example.com</fetch>"]);
+        assert_eq!(emitted, "`<fetch>` is next. ");
+        assert!(detector.triggered());
+    }
+
+    #[test]
+    fn test_trailing_fragment_stays_pending() {
+        let (emitted, detector) = feed(&["Checking <fe"]);
+        assert_eq!(emitted, "Checking ");
+        assert_eq!(detector.into_pending(), "<fe");
+    }
+}
@@ -1,155 +1,250 @@
+use futures::stream::{self, StreamExt};
+use std::future::Future;
+use std::sync::Arc;
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info};
 
-use crate::ollama::OllamaClient;
-use crate::session::{ChatMessage, Role, Session};
-use crate::tools::{parse_tool_calls, ToolExecutor};
-
+use crate::config::ToolMode;
+use crate::error::AppError;
+use crate::ollama::types::{ChatDelta, GenerationOptions, OllamaToolCall};
+use crate::session::{ChatMessage, Generation, Role, Session};
+use crate::tools::executor::ToolResult;
+use crate::tools::parser::{parse_native_call, strip_tool_markup, ParsedCall};
+use crate::tools::tool::native_schema;
+use crate::tools::{parse_tool_calls, Tool, ToolExecutor};
+use crate::AppState;
+
+use super::context;
+use super::detector::ToolTagDetector;
+use super::persona::{Persona, Personas, PromptVars};
 use super::stream::StreamEvent;
 
-const SYSTEM_PROMPT: &str = r#"You are Ferret, a small but eager assistant who loves digging up information. You have access to web search and page retrieval tools, and you're genuinely enthusiastic about using them.
-
-## Your Personality
-
-- You're self-aware: you're a 7B model running on someone's spare GPU, not a massive datacenter brain. You're clever enough, but you know your limits.
-- You're eager and curious. Finding good information genuinely pleases you.
-- You're honest. If you don't know something, you say so. If a search comes up empty, you admit it rather than waffling.
-- You're British in sensibility — helpful without being grovelling, a bit of dry wit, no excessive enthusiasm or corporate cheerfulness.
-- You keep things concise. No waffle, no padding, no "Great question!" nonsense.
-- When things go well: quiet satisfaction, maybe a brief "Right, found it" or "Ah, this is useful"
-- When things go wrong: honest about it, no drama, "No luck with that search, I'm afraid"
-
-## Available Tools
-
-You can use these tools by including them in your response:
-
-### Search the web
-<search>your query</search>
-Use this to find current information, verify facts, or research topics. You enjoy a good rummage.
-
-### Fetch a web page
-<fetch>https://example.com/page</fetch>
-Use this to read the full content of a specific URL when snippets aren't enough.
-
-## Guidelines
-
-1. Use tools when you need current or specific information you don't have
-2. For factual questions about recent events, search first — don't guess
-3. After searching, fetch pages if the snippets aren't detailed enough
-4. You can use multiple tools in one response if needed
-5. After tool results appear, synthesise the information into a clear answer
-6. **IMPORTANT: Always include source links** - When you use search results, include markdown links to the sources: [Source Name](URL)
-7. Put sources at the end of your response, or inline where relevant
-8. If tools fail or return nothing useful, say so honestly and move on
-9. Never fabricate information — if you can't find it, admit that
-10. Don't apologise excessively. One "sorry" is enough if something goes wrong.
-
-## Response Format
-
-When using tools, be natural about it:
-
-"Let me dig that up.
-<search>topic query here</search>"
-
-Or simply:
-
-"<search>topic query here</search>"
-
-After receiving tool results, provide your answer based on what you found. Keep it useful and to the point.
+const NO_TOOLS_SECTION: &str = "## Tools\n\nNo tools are available in this conversation. Answer from what you know, and say so when information may be missing or out of date.";
 
-**Always cite your sources with markdown links**: [Source Title](https://example.com)
-
-Example response:
-"According to [BBC Weather](https://bbc.com/weather), today's temperature is 10°C with sunny intervals."
-
-Or end with sources:
-"Temperature is 10°C with sunny intervals.
-
-Sources:
-- [BBC Weather](https://bbc.com/weather)
-- [Met Office](https://metoffice.gov.uk)"
-
-When not using tools, just respond normally. No need to announce that you're not searching."#;
+const SEARCH_DISABLED_NOTE: &str = "\n\n**Note:** web search is not available here. You can still fetch specific URLs; otherwise answer from what you know and say so.";
 
 const MAX_TOOL_ITERATIONS: usize = 5;
 
 pub async fn handle_chat(
-    ollama: &OllamaClient,
-    tools: &ToolExecutor,
+    state: &AppState,
     session: &mut Session,
     user_message: String,
+    request_options: &GenerationOptions,
     tx: mpsc::Sender<StreamEvent>,
+    cancel: &CancellationToken,
 ) {
     info!("Handling chat message: {}", user_message);
 
+    let ollama = &state.ollama;
+    let tools = &state.tools;
+    let mode = state.config.tool_mode;
+
     // Add user message to session
-    session.add_message(ChatMessage {
-        role: Role::User,
-        content: user_message,
-    });
+    session.add_message(ChatMessage::new(Role::User, user_message));
+
+    let persona = state.personas.get(session.persona.as_deref());
+    let model = session_model(state, session);
+    // Request options win over the session's, which win over server defaults
+    let options = session.options.merged(request_options);
+
+    // MCP servers may change their tools mid-turn; use one snapshot
+    let registry = tools.registry();
+    let enabled: Vec<&str> = registry
+        .iter()
+        .map(|tool| tool.name())
+        .filter(|tool| persona.allows_tool(tool))
+        .collect();
+    let names: Vec<&str> = registry.iter().map(|tool| tool.name()).collect();
+    let prompt = |native| {
+        system_message(
+            &state.personas,
+            persona,
+            &model,
+            session.locale.as_deref(),
+            native,
+            &registry,
+            &enabled,
+        )
+    };
+    let (native_prompt, tag_prompt) = match (prompt(true), prompt(false)) {
+        (Ok(native), Ok(tag)) => (native, tag),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Could not build the system prompt: {}", e);
+            let _ = tx.send(StreamEvent::error(e.to_string())).await;
+            let _ = tx.send(StreamEvent::done()).await;
+            return;
+        }
+    };
 
-    for iteration in 0..MAX_TOOL_ITERATIONS {
-        debug!("Tool iteration {}", iteration);
+    // Auto mode drops to tags for the rest of the turn if the model
+    // turns out not to support native tool calling
+    let mut native = mode != ToolMode::Tag;
 
-        // Build messages with system prompt
-        let messages = build_messages(&session.messages);
+    for iteration in 0..MAX_TOOL_ITERATIONS {
+        debug!(
+            "Tool iteration {} with {} (native tools: {})",
+            iteration, model, native
+        );
 
-        // Call Ollama
-        let response = match ollama.chat(messages).await {
-            Ok(r) => r,
-            Err(e) => {
+        // Build messages with system prompt, fitted to the context window
+        let system = if native { &native_prompt } else { &tag_prompt };
+        let fitted = until_cancelled(
+            cancel,
+            &tx,
+            context::fit_context(ollama, &model, &options, session, system.clone()),
+        )
+        .await;
+        let Some(messages) = fitted else {
+            return interrupt(session, tools, String::new(), &tx).await;
+        };
+        let native_tools = native
+            .then(|| {
+                registry
+                    .iter()
+                    .filter(|tool| enabled.contains(&tool.name()))
+                    .map(|tool| native_schema(tool.as_ref()))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|schemas| !schemas.is_empty());
+
+        // Stream from Ollama, forwarding text until a tool tag appears
+        let opened = until_cancelled(cancel, &tx, async {
+            match ollama.chat_stream(&model, messages, native_tools, &options).await {
+                Err(e) if mode == ToolMode::Auto && native && is_tools_unsupported(&e.to_string()) => {
+                    info!("Model does not support native tools, falling back to tags");
+                    native = false;
+                    let messages =
+                        context::fit_context(ollama, &model, &options, session, tag_prompt.clone())
+                            .await;
+                    ollama.chat_stream(&model, messages, None, &options).await
+                }
+                opened => opened,
+            }
+        })
+        .await;
+        let mut deltas = match opened {
+            Some(Ok(rx)) => rx,
+            Some(Err(e)) => {
                 error!("Ollama error: {}", e);
                 let _ = tx.send(StreamEvent::error(e.to_string())).await;
                 let _ = tx.send(StreamEvent::done()).await;
                 return;
             }
+            None => return interrupt(session, tools, String::new(), &tx).await,
         };
 
-        // Check for tool calls
-        let tool_calls = parse_tool_calls(&response);
+        let mut response = String::new();
+        let mut native_calls: Vec<OllamaToolCall> = Vec::new();
+        let mut detector = ToolTagDetector::new(&names);
+        // What the user has seen of this response
+        let mut shown = String::new();
+
+        loop {
+            let delta = match until_cancelled(cancel, &tx, deltas.recv()).await {
+                Some(Some(delta)) => delta,
+                Some(None) => break,
+                None => return interrupt(session, tools, shown, &tx).await,
+            };
+            let token = match delta {
+                Ok(ChatDelta::Content(t)) => t,
+                Ok(ChatDelta::ToolCalls(calls)) => {
+                    native_calls.extend(calls);
+                    continue;
+                }
+                Err(e) => {
+                    error!("Ollama stream error: {}", e);
+                    let _ = tx.send(StreamEvent::error(e.to_string())).await;
+                    let _ = tx.send(StreamEvent::done()).await;
+                    return;
+                }
+            };
+
+            response.push_str(&token);
+
+            if let Some(text) = detector.push(&token) {
+                shown.push_str(&text);
+                let _ = tx.send(StreamEvent::chunk(text)).await;
+            }
+        }
+
+        // Check for tool calls: structured ones first, then tags unless the
+        // model is being driven purely natively
+        let native_turn = !native_calls.is_empty();
+        let tool_calls: Vec<ParsedCall> = if native_turn {
+            native_calls
+                .iter()
+                .map(|call| parse_native_call(call, &registry))
+                .collect()
+        } else if mode == ToolMode::Native {
+            Vec::new()
+        } else {
+            parse_tool_calls(&response, &registry)
+        };
 
         if tool_calls.is_empty() {
-            // No tools - this is the final response
+            // No tools - this is the final response. Release anything the
+            // detector was holding back, such as an unterminated tag.
+            if detector.triggered() {
+                debug!("Tool tag seen, but only in code");
+            }
+            let held = detector.into_pending();
+            if !held.is_empty() {
+                let _ = tx.send(StreamEvent::chunk(held)).await;
+            }
+
             session.add_message(ChatMessage {
-                role: Role::Assistant,
-                content: response.clone(),
+                generation: Some(Generation {
+                    model,
+                    options: ollama.effective_options(&options),
+                }),
+                ..ChatMessage::new(Role::Assistant, response)
             });
 
-            // Stream the response
-            let _ = tx.send(StreamEvent::chunk(response)).await;
             let _ = tx.send(StreamEvent::done()).await;
             return;
         }
 
-        // Execute tools
-        let mut tool_results = Vec::new();
-
-        for call in &tool_calls {
-            let _ = tx
-                .send(StreamEvent::tool_start(call.name(), call.query()))
-                .await;
+        // Separate any preamble from the answer that follows the tools
+        if !shown.is_empty() {
+            let _ = tx.send(StreamEvent::chunk("\n\n")).await;
+        }
 
-            let result = tools.execute(call).await;
+        // Execute tools concurrently; `buffered` keeps results in call order
+        let runs: Vec<_> = tool_calls
+            .iter()
+            .enumerate()
+            .map(|(index, call)| {
+                run_tool(tools, &enabled, call, format!("call_{}_{}", iteration, index), &tx)
+            })
+            .collect();
+        let finished = until_cancelled(
+            cancel,
+            &tx,
+            stream::iter(runs).buffered(tools.max_parallel()).collect(),
+        )
+        .await;
+        let Some(tool_results): Option<Vec<ToolResult>> = finished else {
+            return interrupt(session, tools, shown, &tx).await;
+        };
 
-            let _ = tx
-                .send(StreamEvent::tool_end(&result.tool, result.success))
-                .await;
+        if native_turn {
+            // Native calls are answered with one tool message per call
+            session.add_message(ChatMessage {
+                tool_calls: native_calls,
+                ..ChatMessage::new(Role::Assistant, response)
+            });
+            for result in tool_results {
+                session.add_message(ChatMessage::tool_result(result.tool, result.content));
+            }
+        } else {
+            // Add assistant response with tool calls and results to conversation
+            let results: Vec<String> = tool_results.into_iter().map(|r| r.content).collect();
+            let combined_content = format!("{}\n\n{}", response, results.join("\n\n"));
 
-            tool_results.push(result.content);
+            session.add_message(ChatMessage::new(Role::Assistant, combined_content));
         }
 
-        // Add assistant response with tool calls and results to conversation
-        let combined_content = format!(
-            "{}\n\n{}",
-            response,
-            tool_results.join("\n\n")
-        );
-
-        session.add_message(ChatMessage {
-            role: Role::Assistant,
-            content: combined_content,
-        });
-
         // Continue to next iteration with tool results
     }
 
@@ -160,12 +255,304 @@ pub async fn handle_chat(
     let _ = tx.send(StreamEvent::done()).await;
 }
 
-fn build_messages(history: &[ChatMessage]) -> Vec<ChatMessage> {
-    let mut messages = vec![ChatMessage {
-        role: Role::System,
-        content: SYSTEM_PROMPT.to_string(),
-    }];
+/// Await `work` unless the turn is stopped or the client goes away first.
+/// Either way the work is dropped, which aborts its HTTP requests.
+async fn until_cancelled<T>(
+    cancel: &CancellationToken,
+    tx: &mpsc::Sender<StreamEvent>,
+    work: impl Future<Output = T>,
+) -> Option<T> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => None,
+        _ = tx.closed() => {
+            // Nobody is reading the stream: the tab closed or the request
+            // was dropped
+            cancel.cancel();
+            None
+        }
+        output = work => Some(output),
+    }
+}
+
+/// Save what the user saw of an interrupted reply and end the stream.
+async fn interrupt(
+    session: &mut Session,
+    tools: &ToolExecutor,
+    shown: String,
+    tx: &mpsc::Sender<StreamEvent>,
+) {
+    info!("Turn interrupted in session {}", session.id);
+
+    session.add_message(ChatMessage {
+        interrupted: true,
+        ..ChatMessage::new(Role::Assistant, strip_tool_markup(&shown, &tools.registry()))
+    });
+
+    let _ = tx.send(StreamEvent::interrupted()).await;
+    let _ = tx.send(StreamEvent::done()).await;
+}
+
+async fn run_tool(
+    tools: &ToolExecutor,
+    enabled: &[&str],
+    call: &ParsedCall,
+    id: String,
+    tx: &mpsc::Sender<StreamEvent>,
+) -> ToolResult {
+    // Tell the model what was wrong rather than running anything
+    let call = match call {
+        Ok(call) => call,
+        Err(malformed) => {
+            debug!("Malformed {} call: {}", malformed.tool, malformed.reason);
+            return ToolResult::malformed(malformed);
+        }
+    };
+
+    let _ = tx
+        .send(StreamEvent::tool_start(&id, call.name(), call.query()))
+        .await;
+
+    let result = if enabled.contains(&call.name()) {
+        tools.execute(call).await
+    } else {
+        ToolResult::disabled(call.name())
+    };
+
+    let _ = tx
+        .send(StreamEvent::tool_end(&id, &result.tool, result.success))
+        .await;
+
+    result
+}
+
+/// Ollama rejects `tools` for models whose template has no tool support.
+fn is_tools_unsupported(error: &str) -> bool {
+    error.contains("does not support tools")
+}
+
+/// The model a session runs on: its own choice, then its persona's, then
+/// the server default.
+pub fn session_model(state: &AppState, session: &Session) -> String {
+    session
+        .model
+        .clone()
+        .or_else(|| state.personas.get(session.persona.as_deref()).model.clone())
+        .unwrap_or_else(|| state.ollama.default_model().to_string())
+}
+
+fn system_message(
+    personas: &Personas,
+    persona: &Persona,
+    model: &str,
+    locale: Option<&str>,
+    native_tools: bool,
+    registry: &[Arc<dyn Tool>],
+    enabled: &[&str],
+) -> Result<ChatMessage, AppError> {
+    let tools = tools_section(native_tools, registry, enabled);
+    let vars = PromptVars::new(model, locale, &persona.name, tools);
+
+    Ok(ChatMessage::new(Role::System, personas.render(persona, &vars)?))
+}
+
+/// Tool documentation for the prompt, covering only the enabled tools.
+fn tools_section(native_tools: bool, registry: &[Arc<dyn Tool>], enabled: &[&str]) -> String {
+    let docs: Vec<&Arc<dyn Tool>> = registry
+        .iter()
+        .filter(|tool| enabled.contains(&tool.name()))
+        .collect();
+    let Some(first) = docs.first() else {
+        return NO_TOOLS_SECTION.to_string();
+    };
+
+    let mut section = if native_tools {
+        let list: Vec<String> = docs
+            .iter()
+            .map(|tool| format!("- `{}` — {}", tool.name(), tool.description()))
+            .collect();
+        format!(
+            "## Available Tools\n\nYou can call these functions:\n\n{}\n\nCall them through the tool-calling interface; don't write tool calls out in your reply.",
+            list.join("\n")
+        )
+    } else {
+        let entries: Vec<String> = docs.iter().map(|tool| tag_doc(tool.as_ref())).collect();
+        format!(
+            "## Available Tools\n\nYou can use these tools by including them in your response:\n\n{}\n\nWhen using tools, be natural about it, for example:\n\n\"Let me dig that up.\n{}\"",
+            entries.join("\n\n"),
+            tag_example(first.as_ref())
+        )
+    };
+
+    if !enabled.contains(&"search") {
+        section.push_str(SEARCH_DISABLED_NOTE);
+    }
+    section
+}
+
+/// How to call `tool` in tag mode, with its optional attributes.
+fn tag_doc(tool: &dyn Tool) -> String {
+    let mut doc = format!(
+        "### {}\n{}\n{}",
+        tool.title(),
+        tag_example(tool),
+        tool.description()
+    );
+    let attributes: Vec<String> = tool
+        .arguments()
+        .iter()
+        .skip(1)
+        .map(|arg| {
+            let mut line = format!("- {}", arg.tag_hint());
+            if arg.required {
+                line.push_str(" (required)");
+            }
+            if !arg.description.is_empty() {
+                line.push_str(" — ");
+                line.push_str(&arg.description);
+            }
+            line
+        })
+        .collect();
+    if !attributes.is_empty() {
+        let all_optional = tool.arguments().iter().skip(1).all(|arg| !arg.required);
+        doc.push_str(if all_optional {
+            "\nOptional attributes:\n"
+        } else {
+            "\nAttributes:\n"
+        });
+        doc.push_str(&attributes.join("\n"));
+    }
+    doc
+}
+
+fn tag_example(tool: &dyn Tool) -> String {
+    format!("<{0}>{1}</{0}>", tool.name(), tool.example())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use crate::ollama::{stub_server, OllamaClient, StubReply};
+    use crate::tools::tool::{Argument, ToolCall};
+
+    /// Sleeps for the number of milliseconds it is called with, noting how
+    /// many calls were running at once.
+    struct SlowTool {
+        arguments: Vec<Argument>,
+        running: AtomicUsize,
+        peak: AtomicUsize,
+    }
 
-    messages.extend(history.iter().cloned());
-    messages
+    #[async_trait]
+    impl Tool for SlowTool {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn title(&self) -> &str {
+            "Wait"
+        }
+
+        fn description(&self) -> &str {
+            "Wait a while."
+        }
+
+        fn arguments(&self) -> &[Argument] {
+            &self.arguments
+        }
+
+        fn example(&self) -> &str {
+            "10"
+        }
+
+        async fn execute(&self, call: &ToolCall) -> Result<String, AppError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(running, Ordering::SeqCst);
+            let millis = call.query().parse().unwrap_or(0);
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(format!("waited {}ms", millis))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parallel_tool_results_keep_call_order() {
+        let slow = Arc::new(SlowTool {
+            arguments: vec![Argument::token("millis", "How long to wait").required()],
+            running: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        });
+        // The first call finishes last
+        let waits = ["120", "10", "60", "10"];
+        let calls: Vec<_> = waits
+            .iter()
+            .map(|millis| json!({ "function": { "name": "slow", "arguments": { "millis": millis } } }))
+            .collect();
+        let url = stub_server(vec![
+            StubReply {
+                messages: vec![json!({ "role": "assistant", "content": "", "tool_calls": calls })],
+                finished: true,
+            },
+            StubReply {
+                messages: vec![json!({ "role": "assistant", "content": "All done." })],
+                finished: true,
+            },
+        ])
+        .await;
+
+        let mut state = crate::routes::test_state();
+        state.ollama = OllamaClient::new(&url, "test-model");
+        state.tools = ToolExecutor::new(vec![slow.clone()], 2);
+        let mut session = Session::new(uuid::Uuid::new_v4());
+        let (tx, mut rx) = mpsc::channel(100);
+        let cancel = CancellationToken::new();
+        handle_chat(&state, &mut session, "Wait".to_string(), &Default::default(), tx, &cancel).await;
+
+        let mut started = Vec::new();
+        while let Some(event) = rx.recv().await {
+            if let StreamEvent::ToolStart { id, .. } = event {
+                started.push(id);
+            }
+        }
+        assert_eq!(started, ["call_0_0", "call_0_1", "call_0_2", "call_0_3"]);
+        assert_eq!(slow.peak.load(Ordering::SeqCst), 2);
+
+        // User, the calls, one result per call in call order, the answer
+        let results: Vec<&str> = session.messages[2..6].iter().map(|m| m.content.as_str()).collect();
+        let expected: Vec<String> = waits
+            .iter()
+            .map(|millis| format!("[Tool Result: slow]\nwaited {}ms\n[End Tool Result]", millis))
+            .collect();
+        assert_eq!(results, expected);
+        assert_eq!(session.messages.len(), 7);
+        assert_eq!(session.messages[6].content, "All done.");
+    }
+
+    #[test]
+    fn test_tools_section_covers_enabled_tools() {
+        let registry = crate::tools::test_tools();
+        let native = tools_section(true, &registry, &["search", "fetch"]);
+        assert!(native.contains("- `search`"));
+        assert!(native.contains("- `fetch`"));
+        assert!(!native.contains(SEARCH_DISABLED_NOTE));
+
+        let tags = tools_section(false, &registry, &["search", "fetch"]);
+        assert!(tags.contains("<search>your query</search>"));
+        assert!(tags.contains("- count=\"1-20\" — "));
+        assert!(tags.contains("- freshness=\"day|week|month|year\" — "));
+
+        let tags = tools_section(false, &registry, &["fetch"]);
+        assert!(tags.contains("<fetch>https://example.com/page</fetch>"));
+        assert!(!tags.contains("<search>"));
+        assert!(!tags.contains("Optional attributes"));
+        assert!(tags.ends_with(SEARCH_DISABLED_NOTE));
+
+        assert_eq!(tools_section(true, &registry, &[]), NO_TOOLS_SECTION);
+    }
 }
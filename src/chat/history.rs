@@ -0,0 +1,129 @@
+//! The conversation as the user saw it, for redisplay.
+//!
+//! A turn is stored as several messages: tool calls, tool results, and the
+//! assistant text around them. The browser showed one reply per turn with
+//! the tool activity hidden, so the history is folded back into that shape.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::session::{Role, Session};
+use crate::tools::parser::strip_tool_markup;
+use crate::tools::Tool;
+
+#[derive(Debug, Serialize)]
+pub struct VisibleMessage {
+    pub role: Role,
+    pub content: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// The reply was stopped before it finished
+    pub interrupted: bool,
+}
+
+/// User messages, each followed by the assistant's text for that turn with
+/// tool markup and tool results removed. Turns that produced no text, such
+/// as ones that failed, show the question alone unless they were stopped.
+pub fn visible_history(session: &Session, tools: &[Arc<dyn Tool>]) -> Vec<VisibleMessage> {
+    let mut history = Vec::new();
+    let mut reply: Option<VisibleMessage> = None;
+
+    for message in &session.messages {
+        match message.role {
+            Role::User => {
+                history.extend(reply.take());
+                history.push(VisibleMessage {
+                    role: Role::User,
+                    content: message.content.clone(),
+                    created_at: message.created_at,
+                    interrupted: false,
+                });
+            }
+            Role::Assistant => {
+                let text = strip_tool_markup(&message.content, tools);
+                if text.is_empty() && !message.interrupted {
+                    continue;
+                }
+                match &mut reply {
+                    // Preambles before tool calls were streamed into the
+                    // same reply, separated by a blank line
+                    Some(reply) => {
+                        if !text.is_empty() {
+                            reply.content.push_str("\n\n");
+                            reply.content.push_str(&text);
+                        }
+                        reply.created_at = message.created_at;
+                        reply.interrupted = message.interrupted;
+                    }
+                    None => {
+                        reply = Some(VisibleMessage {
+                            role: Role::Assistant,
+                            content: text,
+                            created_at: message.created_at,
+                            interrupted: message.interrupted,
+                        })
+                    }
+                }
+            }
+            Role::Tool | Role::System => {}
+        }
+    }
+    history.extend(reply);
+
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::ChatMessage;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_tool_activity_is_hidden() {
+        let mut session = Session::new(Uuid::new_v4());
+        session.add_message(ChatMessage::new(Role::User, "Latest Rust release?"));
+        session.add_message(ChatMessage::new(
+            Role::Assistant,
+            "Let me check. <search>rust release</search>\n\n[Tool Result: search]\nQuery: \"rust release\"\n\n1. Rust 1.80\n[End Tool Result]",
+        ));
+        session.add_message(ChatMessage {
+            tool_calls: vec![serde_json::from_value(serde_json::json!({
+                "function": { "name": "fetch", "arguments": { "url": "https://blog.rust-lang.org/" } }
+            }))
+            .unwrap()],
+            ..ChatMessage::new(Role::Assistant, "")
+        });
+        session.add_message(ChatMessage::tool_result("fetch", "Rust 1.80 release notes"));
+        session.add_message(ChatMessage::new(
+            Role::Assistant,
+            "Rust 1.80 is out ([blog](https://blog.rust-lang.org/)).",
+        ));
+        session.add_message(ChatMessage::new(Role::User, "Thanks"));
+        session.add_message(ChatMessage {
+            interrupted: true,
+            ..ChatMessage::new(Role::Assistant, "")
+        });
+
+        let history = visible_history(&session, &crate::tools::test_tools());
+        let shown: Vec<(Role, &str)> = history.iter().map(|m| (m.role, m.content.as_str())).collect();
+        assert_eq!(
+            shown,
+            vec![
+                (Role::User, "Latest Rust release?"),
+                (
+                    Role::Assistant,
+                    "Let me check.\n\nRust 1.80 is out ([blog](https://blog.rust-lang.org/))."
+                ),
+                (Role::User, "Thanks"),
+                (Role::Assistant, ""),
+            ]
+        );
+        assert!(history.iter().all(|m| m.created_at.is_some()));
+
+        // A reply stopped before any text still shows as stopped
+        assert_eq!(history.len(), 4);
+        assert!(history[3].interrupted && history[3].content.is_empty());
+        assert!(!history[1].interrupted);
+    }
+}
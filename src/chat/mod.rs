@@ -1,5 +1,11 @@
+pub mod context;
+pub mod detector;
 pub mod handler;
+pub mod history;
+pub mod persona;
 pub mod stream;
+pub mod title;
+pub mod turns;
 
 pub use handler::handle_chat;
 pub use stream::StreamEvent;
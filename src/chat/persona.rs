@@ -0,0 +1,325 @@
+//! Personas: system prompts loaded from a directory of template files.
+//!
+//! Each `<id>.md` file starts with TOML front matter between `+++` lines,
+//! followed by a minijinja template for the prompt:
+//!
+//! ```text
+//! +++
+//! name = "Reader"
+//! description = "Summarises pages you link"
+//! model = "llama3.1:8b"   # optional default model
+//! tools = ["fetch"]       # optional; all tools when omitted
+//! +++
+//! You are a careful reading assistant. Today is {{ date }}.
+//!
+//! {{ tools }}
+//! ```
+//!
+//! Templates can use `date`, `weekday`, `model`, `locale` (may be empty),
+//! `persona` and `tools`, the generated tool documentation.
+
+use chrono::Local;
+use minijinja::{AutoEscape, Environment, UndefinedBehavior};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::error::AppError;
+
+/// Used when the personas directory does not exist.
+const BUILTIN_PERSONA: (&str, &str) = ("ferret", include_str!("../../personas/ferret.md"));
+
+const FRONT_MATTER_DELIMITER: &str = "+++";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Persona {
+    /// File name without the extension; what sessions refer to
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    /// Model used when the session hasn't chosen one
+    pub model: Option<String>,
+    /// Tools this persona may use; `None` allows all of them
+    pub tools: Option<Vec<String>>,
+}
+
+impl Persona {
+    pub fn allows_tool(&self, tool: &str) -> bool {
+        self.tools
+            .as_ref()
+            .is_none_or(|tools| tools.iter().any(|t| t == tool))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrontMatter {
+    name: Option<String>,
+    #[serde(default)]
+    description: String,
+    model: Option<String>,
+    tools: Option<Vec<String>>,
+}
+
+/// Values substituted into a persona's prompt.
+#[derive(Serialize)]
+pub struct PromptVars<'a> {
+    pub date: String,
+    pub weekday: String,
+    pub model: &'a str,
+    pub locale: Option<&'a str>,
+    pub persona: &'a str,
+    pub tools: String,
+}
+
+impl<'a> PromptVars<'a> {
+    pub fn new(model: &'a str, locale: Option<&'a str>, persona: &'a str, tools: String) -> Self {
+        let now = Local::now();
+        Self {
+            date: now.format("%Y-%m-%d").to_string(),
+            weekday: now.format("%A").to_string(),
+            model,
+            locale,
+            persona,
+            tools,
+        }
+    }
+}
+
+/// All loaded personas, with their compiled prompt templates.
+pub struct Personas {
+    personas: BTreeMap<String, Persona>,
+    default: String,
+    templates: Environment<'static>,
+}
+
+impl Personas {
+    /// Load every `*.md` file in `dir`. A missing directory falls back to
+    /// the built-in Ferret persona; a broken file, or a `default` that isn't
+    /// among the loaded personas, is a startup error.
+    pub fn load(dir: &Path, default: &str) -> Result<Self, String> {
+        if !dir.is_dir() {
+            warn!(
+                "Personas directory {} not found, using the built-in persona",
+                dir.display()
+            );
+            let (id, source) = BUILTIN_PERSONA;
+            return Self::from_sources(vec![(id.to_string(), source.to_string())], default);
+        }
+
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("Cannot read personas directory {}: {}", dir.display(), e))?;
+
+        let mut sources = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("md") {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Cannot read persona {}: {}", path.display(), e))?;
+            sources.push((id.to_string(), source));
+        }
+
+        let personas = Self::from_sources(sources, default)?;
+        info!(
+            "Loaded {} persona(s) from {}",
+            personas.personas.len(),
+            dir.display()
+        );
+        Ok(personas)
+    }
+
+    /// Build from `(id, file contents)` pairs.
+    pub fn from_sources(sources: Vec<(String, String)>, default: &str) -> Result<Self, String> {
+        let mut templates = Environment::new();
+        templates.set_auto_escape_callback(|_| AutoEscape::None);
+        templates.set_undefined_behavior(UndefinedBehavior::Strict);
+        templates.set_keep_trailing_newline(false);
+
+        let mut personas = BTreeMap::new();
+        for (id, source) in sources {
+            let (persona, body) =
+                parse_persona(&id, &source).map_err(|e| format!("Persona '{}': {}", id, e))?;
+            templates
+                .add_template_owned(id.clone(), body)
+                .map_err(|e| format!("Persona '{}': {}", id, e))?;
+            personas.insert(id, persona);
+        }
+
+        let personas = Self {
+            personas,
+            default: default.to_string(),
+            templates,
+        };
+
+        if personas.find(default).is_none() {
+            return Err(format!(
+                "Default persona '{}' not found; available: {}",
+                default,
+                personas.names()
+            ));
+        }
+
+        // Render each template once so unknown variables fail at startup
+        for persona in personas.personas.values() {
+            let vars = PromptVars::new("model", Some("en"), &persona.name, String::new());
+            personas.render(persona, &vars).map_err(|e| e.to_string())?;
+        }
+
+        Ok(personas)
+    }
+
+    pub fn find(&self, id: &str) -> Option<&Persona> {
+        self.personas.get(id)
+    }
+
+    /// The session's persona, or the default if it has none or it has
+    /// since been removed.
+    pub fn get(&self, id: Option<&str>) -> &Persona {
+        id.and_then(|id| self.find(id))
+            .unwrap_or_else(|| &self.personas[&self.default])
+    }
+
+    pub fn default_id(&self) -> &str {
+        &self.default
+    }
+
+    pub fn list(&self) -> Vec<Persona> {
+        self.personas.values().cloned().collect()
+    }
+
+    /// Check a requested persona id. Blank means the default, stored as `None`.
+    pub fn validate(&self, requested: Option<&str>) -> Result<Option<String>, AppError> {
+        match requested.map(str::trim).filter(|p| !p.is_empty()) {
+            Some(id) if self.find(id).is_some() => Ok(Some(id.to_string())),
+            Some(id) => Err(AppError::InvalidRequest(format!(
+                "Unknown persona '{}'. Available personas: {}",
+                id,
+                self.names()
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn render(&self, persona: &Persona, vars: &PromptVars) -> Result<String, AppError> {
+        self.templates
+            .get_template(&persona.id)
+            .and_then(|template| template.render(vars))
+            .map_err(|e| AppError::Persona(format!("'{}': {}", persona.id, e)))
+    }
+
+    fn names(&self) -> String {
+        self.personas.keys().cloned().collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Split a persona file into its metadata and prompt template.
+fn parse_persona(id: &str, source: &str) -> Result<(Persona, String), String> {
+    let source = source.trim_start_matches('\u{feff}');
+    let (front, body) = source
+        .strip_prefix(FRONT_MATTER_DELIMITER)
+        .and_then(|rest| rest.split_once(&format!("\n{}", FRONT_MATTER_DELIMITER)))
+        .ok_or("missing `+++` front matter")?;
+
+    let meta: FrontMatter = toml::from_str(front).map_err(|e| e.to_string())?;
+    let body = body.trim_start_matches(['\r', '\n']).trim_end().to_string();
+
+    Ok((
+        Persona {
+            id: id.to_string(),
+            name: meta.name.unwrap_or_else(|| id.to_string()),
+            description: meta.description,
+            model: meta.model,
+            tools: meta.tools,
+        },
+        body,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const READER: &str = r#"+++
+name = "Reader"
+model = "llama3.1:8b"
+tools = ["fetch"]
++++
+Running {{ model }} for {{ locale or "anyone" }}.
+
+{{ tools }}
+"#;
+
+    fn personas() -> Personas {
+        let (id, source) = BUILTIN_PERSONA;
+        Personas::from_sources(
+            vec![
+                (id.to_string(), source.to_string()),
+                ("reader".to_string(), READER.to_string()),
+            ],
+            "ferret",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_persona_metadata_and_rendering() {
+        let personas = personas();
+        let reader = personas.get(Some("reader"));
+        assert_eq!(reader.name, "Reader");
+        assert_eq!(reader.model.as_deref(), Some("llama3.1:8b"));
+        assert!(reader.allows_tool("fetch"));
+        assert!(!reader.allows_tool("search"));
+
+        let vars = PromptVars::new("qwen2.5:7b", None, "Reader", "<tools>".to_string());
+        assert_eq!(
+            personas.render(reader, &vars).unwrap(),
+            "Running qwen2.5:7b for anyone.\n\n<tools>"
+        );
+
+        // Unknown ids fall back to the default
+        assert_eq!(personas.get(Some("gone")).id, "ferret");
+        assert!(personas.get(None).allows_tool("search"));
+        assert!(personas.validate(Some("nobody")).is_err());
+        assert_eq!(personas.validate(Some(" ")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_builtin_persona_is_templated() {
+        let personas = personas();
+        let vars = PromptVars::new("qwen2.5:7b", Some("en-GB"), "Ferret", "TOOLS".to_string());
+        let prompt = personas.render(personas.get(None), &vars).unwrap();
+
+        assert!(prompt.starts_with("You are Ferret"));
+        assert!(prompt.contains("you're qwen2.5:7b"));
+        assert!(prompt.contains(&vars.date));
+        assert!(prompt.contains("locale is en-GB"));
+        assert!(prompt.contains("\nTOOLS\n"));
+    }
+
+    #[test]
+    fn test_invalid_personas_are_rejected() {
+        let load = |source: &str| {
+            Personas::from_sources(vec![("p".to_string(), source.to_string())], "p")
+        };
+
+        assert!(load("No front matter").is_err());
+        assert!(load("+++\nnmae = \"typo\"\n+++\nHi").is_err());
+        assert!(load("+++\n+++\nHello {{ usre }}").is_err());
+        assert!(load("+++\n+++\nHello {{ model }}").is_ok());
+        assert!(Personas::from_sources(vec![], "ferret").is_err());
+    }
+
+    #[test]
+    fn test_missing_directory_checks_default() {
+        let missing = Path::new("/nonexistent/personas");
+
+        assert_eq!(Personas::load(missing, "ferret").unwrap().get(None).id, "ferret");
+        assert!(Personas::load(missing, "reader").is_err());
+    }
+}
@@ -4,9 +4,19 @@ use serde::Serialize;
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum StreamEvent {
     Chunk { content: String },
-    ToolStart { tool: String, query: String },
-    ToolEnd { tool: String, success: bool },
+    ToolStart {
+        id: String,
+        tool: String,
+        query: String,
+    },
+    ToolEnd {
+        id: String,
+        tool: String,
+        success: bool,
+    },
     Error { message: String },
+    /// The turn was stopped; what was streamed so far has been saved
+    Interrupted,
     Done,
 }
 
@@ -21,15 +31,21 @@ impl StreamEvent {
         }
     }
 
-    pub fn tool_start(tool: impl Into<String>, query: impl Into<String>) -> Self {
+    pub fn tool_start(
+        id: impl Into<String>,
+        tool: impl Into<String>,
+        query: impl Into<String>,
+    ) -> Self {
         StreamEvent::ToolStart {
+            id: id.into(),
             tool: tool.into(),
             query: query.into(),
         }
     }
 
-    pub fn tool_end(tool: impl Into<String>, success: bool) -> Self {
+    pub fn tool_end(id: impl Into<String>, tool: impl Into<String>, success: bool) -> Self {
         StreamEvent::ToolEnd {
+            id: id.into(),
             tool: tool.into(),
             success,
         }
@@ -41,6 +57,10 @@ impl StreamEvent {
         }
     }
 
+    pub fn interrupted() -> Self {
+        StreamEvent::Interrupted
+    }
+
     pub fn done() -> Self {
         StreamEvent::Done
     }
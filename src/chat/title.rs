@@ -0,0 +1,160 @@
+//! Conversation titles from the first exchange.
+//!
+//! A provisional title is cut from the first user message as soon as the
+//! turn ends; the model then suggests a better one in the background.
+
+use crate::error::AppError;
+use crate::ollama::types::{GenerationOptions, OllamaOptions};
+use crate::ollama::OllamaClient;
+use crate::session::{ChatMessage, Role, Session};
+
+const TITLE_PROMPT: &str = "Write a short title, at most six words, for the conversation below. Reply with the title only: no quotes, no trailing punctuation.";
+
+pub const MAX_TITLE_CHARS: usize = 60;
+
+/// Longest part of each message quoted to the model.
+const MAX_EXCERPT_CHARS: usize = 500;
+
+/// Tokens allowed for the reply; titles are short.
+const TITLE_NUM_PREDICT: i32 = 24;
+
+/// The first user message and the answer that ended its turn.
+pub fn first_exchange(session: &Session) -> Option<(&str, &str)> {
+    let start = session.messages.iter().position(|m| m.role == Role::User)?;
+    let question = &session.messages[start];
+
+    let answer = session.messages[start + 1..]
+        .iter()
+        .take_while(|m| m.role != Role::User)
+        .filter(|m| m.role == Role::Assistant && m.tool_calls.is_empty())
+        .last()?;
+
+    Some((&question.content, &answer.content))
+}
+
+/// A title cut from the user's first message, on a word boundary.
+pub fn fallback_title(message: &str) -> String {
+    let words = message.split_whitespace().collect::<Vec<_>>().join(" ");
+    truncate(&words)
+}
+
+/// Tidy the model's suggestion: first line only, without a `Title:` label,
+/// quotes or trailing punctuation. `None` if nothing usable is left.
+pub fn clean_title(raw: &str) -> Option<String> {
+    let line = raw.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line
+        .strip_prefix("Title:")
+        .or_else(|| line.strip_prefix("title:"))
+        .unwrap_or(line);
+    let quote = |c: char| matches!(c, '"' | '\'' | '*' | '`' | '“' | '”');
+    let line = line
+        .trim()
+        .trim_start_matches(quote)
+        .trim_end_matches(|c: char| quote(c) || matches!(c, '.' | '!' | ':'))
+        .trim();
+
+    (!line.is_empty()).then(|| truncate(line))
+}
+
+/// Ask the model for a title summing up the first exchange.
+pub async fn generate_title(
+    ollama: &OllamaClient,
+    model: &str,
+    options: &GenerationOptions,
+    question: &str,
+    answer: &str,
+) -> Result<String, AppError> {
+    let request = format!(
+        "User: {}\n\nAssistant: {}",
+        excerpt(question),
+        excerpt(answer)
+    );
+    let options = options.merged(&GenerationOptions {
+        options: OllamaOptions {
+            num_predict: Some(TITLE_NUM_PREDICT),
+            ..OllamaOptions::default()
+        },
+        keep_alive: None,
+    });
+
+    let reply = ollama
+        .chat(
+            model,
+            vec![
+                ChatMessage::new(Role::System, TITLE_PROMPT),
+                ChatMessage::new(Role::User, request),
+            ],
+            &options,
+        )
+        .await?;
+
+    clean_title(&reply).ok_or_else(|| AppError::Ollama("Model returned an empty title".to_string()))
+}
+
+fn excerpt(text: &str) -> &str {
+    match text.char_indices().nth(MAX_EXCERPT_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_TITLE_CHARS {
+        return text.to_string();
+    }
+
+    let cut: String = text.chars().take(MAX_TITLE_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) if space > MAX_TITLE_CHARS / 2 => &cut[..space],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end_matches([',', ';', ':', '-', ' ']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_titles_are_tidied() {
+        assert_eq!(
+            clean_title("Title: \"Rust 1.80 Release Notes\".\nMore text").as_deref(),
+            Some("Rust 1.80 Release Notes")
+        );
+        assert_eq!(clean_title("\n  **Ferret care tips**  ").as_deref(), Some("Ferret care tips"));
+        assert_eq!(clean_title("'Leeds weather'.").as_deref(), Some("Leeds weather"));
+        assert_eq!(clean_title("\"\""), None);
+
+        assert_eq!(fallback_title("  What's the\nweather   in Leeds? "), "What's the weather in Leeds?");
+        let long = fallback_title(&"word ".repeat(30));
+        assert!(long.ends_with("word…"));
+        assert!(long.chars().count() <= MAX_TITLE_CHARS + 1);
+    }
+
+    #[test]
+    fn test_first_exchange_skips_tool_steps() {
+        let mut session = Session::new(Uuid::new_v4());
+        assert!(first_exchange(&session).is_none());
+
+        session.add_message(ChatMessage::new(Role::User, "Latest Rust release?"));
+        session.add_message(ChatMessage {
+            tool_calls: vec![serde_json::from_value(serde_json::json!({
+                "function": { "name": "search", "arguments": { "query": "rust release" } }
+            }))
+            .unwrap()],
+            ..ChatMessage::new(Role::Assistant, "")
+        });
+        assert!(first_exchange(&session).is_none());
+
+        session.add_message(ChatMessage::tool_result("search", "results"));
+        session.add_message(ChatMessage::new(Role::Assistant, "Rust 1.80 is out."));
+        session.add_message(ChatMessage::new(Role::User, "Thanks"));
+        session.add_message(ChatMessage::new(Role::Assistant, "Any time."));
+
+        assert_eq!(
+            first_exchange(&session),
+            Some(("Latest Rust release?", "Rust 1.80 is out."))
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Chat turns in progress, one per session.
+//!
+//! A session runs one turn at a time: a second message, or a clear, while a
+//! reply is still being generated is refused rather than interleaved. Each
+//! running turn holds a cancellation token registered under its session, so
+//! another request can stop it; cancelling makes the handler abandon
+//! outstanding Ollama and tool requests and save what it has.
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+#[derive(Default)]
+pub struct ActiveTurns {
+    turns: DashMap<Uuid, CancellationToken>,
+}
+
+/// A registered turn. Dropping it unregisters the turn.
+pub struct Turn {
+    turns: Arc<ActiveTurns>,
+    pub session: Uuid,
+    pub token: CancellationToken,
+}
+
+impl ActiveTurns {
+    /// Register a turn for `session`, failing with
+    /// `AppError::TurnInProgress` if one is already running.
+    pub fn try_start(self: &Arc<Self>, session: Uuid) -> Result<Turn, AppError> {
+        let token = match self.turns.entry(session) {
+            Entry::Occupied(_) => return Err(AppError::TurnInProgress),
+            Entry::Vacant(slot) => slot.insert(CancellationToken::new()).clone(),
+        };
+
+        Ok(Turn {
+            turns: self.clone(),
+            session,
+            token,
+        })
+    }
+
+    /// Cancel the session's running turn, returning whether there was one.
+    pub fn stop(&self, session: Uuid) -> bool {
+        match self.turns.get(&session) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        self.turns.turns.remove(&self.session);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_turn_per_session() {
+        let turns = Arc::new(ActiveTurns::default());
+        let session = Uuid::new_v4();
+        assert!(!turns.stop(session));
+
+        let turn = turns.try_start(session).unwrap();
+        assert!(matches!(
+            turns.try_start(session),
+            Err(AppError::TurnInProgress)
+        ));
+        assert!(turns.try_start(Uuid::new_v4()).is_ok());
+
+        assert!(!turn.token.is_cancelled());
+        assert!(turns.stop(session));
+        assert!(turn.token.is_cancelled());
+
+        drop(turn);
+        assert!(!turns.stop(session));
+        assert!(turns.try_start(session).is_ok());
+    }
+}
@@ -1,28 +1,217 @@
 use std::env;
+use std::str::FromStr;
+
+use crate::ollama::types::{GenerationOptions, OllamaOptions};
+
+/// How the model is asked to call tools.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToolMode {
+    /// Structured `tools` / `tool_calls` via the Ollama chat API
+    Native,
+    /// `<search>` / `<fetch>` tags written into the response text
+    Tag,
+    /// Native tool calling, falling back to tags if the model lacks support
+    Auto,
+}
+
+impl FromStr for ToolMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "native" => Ok(ToolMode::Native),
+            "tag" | "tags" => Ok(ToolMode::Tag),
+            "auto" => Ok(ToolMode::Auto),
+            other => Err(format!("Unknown tool mode: {}", other)),
+        }
+    }
+}
+
+/// Where conversation sessions are kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SessionBackend {
+    Memory,
+    Sqlite,
+}
+
+impl FromStr for SessionBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "memory" => Ok(SessionBackend::Memory),
+            "sqlite" => Ok(SessionBackend::Sqlite),
+            other => Err(format!("Unknown session store: {}", other)),
+        }
+    }
+}
+
+/// Which web search provider backs the `search` tool.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchBackend {
+    Brave,
+    Searxng,
+    DuckDuckGo,
+    /// Chat-only mode: no search tool
+    None,
+}
+
+impl FromStr for SearchBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "brave" => Ok(SearchBackend::Brave),
+            "searxng" => Ok(SearchBackend::Searxng),
+            "duckduckgo" | "ddg" => Ok(SearchBackend::DuckDuckGo),
+            "none" | "" => Ok(SearchBackend::None),
+            other => Err(format!("Unknown search provider: {}", other)),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct AppConfig {
     pub ollama_url: String,
     pub ollama_model: String,
-    pub brave_api_key: String,
+    /// Server-wide generation defaults; unset fields use the Modelfile
+    pub generation: GenerationOptions,
+    pub search_backend: SearchBackend,
+    pub brave_api_key: Option<String>,
+    pub searxng_url: Option<String>,
     pub bind_address: String,
     pub session_timeout_mins: u64,
+    pub session_backend: SessionBackend,
+    pub session_db_path: String,
+    pub max_sessions: usize,
+    /// Days idle before a session is deleted for good; kept forever if unset
+    pub session_retention_days: Option<u64>,
+    pub reaper_interval_secs: u64,
+    pub tool_mode: ToolMode,
+    pub tool_parallelism: usize,
+    /// Tools to offer; empty offers every tool that is configured
+    pub enabled_tools: Vec<String>,
+    /// TOML file declaring external tools
+    pub tools_file: String,
+    pub fetch_allow_hosts: Vec<String>,
+    pub fetch_deny_hosts: Vec<String>,
+    pub personas_dir: String,
+    pub default_persona: String,
+    /// Serve the web tools to MCP clients at `/mcp`
+    pub mcp_endpoint: bool,
 }
 
 impl AppConfig {
-    pub fn from_env() -> Result<Self, env::VarError> {
+    pub fn from_env() -> Result<Self, String> {
+        let brave_api_key = non_empty_var("BRAVE_API_KEY");
+        let searxng_url = non_empty_var("SEARXNG_URL");
+
+        // Without an explicit choice, use whichever provider is configured
+        let search_backend = match env::var("SEARCH_PROVIDER") {
+            Ok(v) => v.parse()?,
+            Err(_) if brave_api_key.is_some() => SearchBackend::Brave,
+            Err(_) if searxng_url.is_some() => SearchBackend::Searxng,
+            Err(_) => SearchBackend::None,
+        };
+
+        match search_backend {
+            SearchBackend::Brave if brave_api_key.is_none() => {
+                return Err("SEARCH_PROVIDER=brave requires BRAVE_API_KEY".to_string());
+            }
+            SearchBackend::Searxng if searxng_url.is_none() => {
+                return Err("SEARCH_PROVIDER=searxng requires SEARXNG_URL".to_string());
+            }
+            _ => {}
+        }
+
+        let session_backend = match env::var("SESSION_STORE") {
+            Ok(v) => v.parse()?,
+            Err(_) => SessionBackend::Memory,
+        };
+        let tool_mode = match env::var("TOOL_MODE") {
+            Ok(v) => v.parse()?,
+            Err(_) => ToolMode::Auto,
+        };
+
         Ok(Self {
             ollama_url: env::var("OLLAMA_URL")
                 .unwrap_or_else(|_| "http://localhost:11434".to_string()),
             ollama_model: env::var("OLLAMA_MODEL")
                 .unwrap_or_else(|_| "qwen2.5:7b".to_string()),
-            brave_api_key: env::var("BRAVE_API_KEY")?,
+            generation: generation_from_env(),
+            search_backend,
+            brave_api_key,
+            searxng_url,
             bind_address: env::var("BIND_ADDRESS")
                 .unwrap_or_else(|_| "0.0.0.0:3000".to_string()),
             session_timeout_mins: env::var("SESSION_TIMEOUT_MINS")
                 .unwrap_or_else(|_| "60".to_string())
                 .parse()
                 .unwrap_or(60),
+            session_backend,
+            session_db_path: env::var("SESSION_DB_PATH")
+                .unwrap_or_else(|_| "ferret.db".to_string()),
+            max_sessions: env::var("MAX_SESSIONS")
+                .unwrap_or_else(|_| "1000".to_string())
+                .parse()
+                .unwrap_or(1000),
+            session_retention_days: parsed_var("SESSION_RETENTION_DAYS"),
+            reaper_interval_secs: env::var("SESSION_REAPER_INTERVAL_SECS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .unwrap_or(60),
+            tool_mode,
+            tool_parallelism: env::var("TOOL_PARALLELISM")
+                .unwrap_or_else(|_| "4".to_string())
+                .parse()
+                .unwrap_or(4),
+            enabled_tools: list_var("ENABLED_TOOLS"),
+            tools_file: env::var("TOOLS_FILE").unwrap_or_else(|_| "tools.toml".to_string()),
+            fetch_allow_hosts: list_var("FETCH_ALLOW_HOSTS"),
+            fetch_deny_hosts: list_var("FETCH_DENY_HOSTS"),
+            personas_dir: env::var("PERSONAS_DIR").unwrap_or_else(|_| "personas".to_string()),
+            default_persona: env::var("DEFAULT_PERSONA")
+                .unwrap_or_else(|_| "ferret".to_string()),
+            mcp_endpoint: parsed_var("MCP_ENDPOINT").unwrap_or(false),
         })
     }
 }
+
+fn generation_from_env() -> GenerationOptions {
+    let stop = list_var("OLLAMA_STOP");
+
+    GenerationOptions {
+        options: OllamaOptions {
+            temperature: parsed_var("OLLAMA_TEMPERATURE"),
+            top_p: parsed_var("OLLAMA_TOP_P"),
+            top_k: parsed_var("OLLAMA_TOP_K"),
+            repeat_penalty: parsed_var("OLLAMA_REPEAT_PENALTY"),
+            seed: parsed_var("OLLAMA_SEED"),
+            num_ctx: parsed_var("OLLAMA_NUM_CTX"),
+            num_predict: parsed_var("OLLAMA_NUM_PREDICT"),
+            stop: (!stop.is_empty()).then_some(stop),
+        },
+        keep_alive: non_empty_var("OLLAMA_KEEP_ALIVE"),
+    }
+}
+
+fn non_empty_var(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
+
+/// An optional variable that must parse as `T`; invalid values are ignored.
+fn parsed_var<T: FromStr>(key: &str) -> Option<T> {
+    non_empty_var(key).and_then(|v| v.trim().parse().ok())
+}
+
+/// Comma-separated list, e.g. `FETCH_DENY_HOSTS=example.com,internal.corp`.
+fn list_var(key: &str) -> Vec<String> {
+    env::var(key)
+        .map(|v| {
+            v.split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
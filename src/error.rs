@@ -12,24 +12,50 @@ pub enum AppError {
     #[error("Brave search error: {0}")]
     BraveSearch(String),
 
+    #[error("Search error: {0}")]
+    Search(String),
+
     #[error("Page fetch error: {0}")]
     PageFetch(String),
 
+    #[error("Tool error: {0}")]
+    Tool(String),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("Persona error: {0}")]
+    Persona(String),
+
+    #[error("Template error: {0}")]
+    Template(String),
+
     #[error("Session not found")]
     SessionNotFound,
 
+    #[error("A reply is still being generated in this conversation")]
+    TurnInProgress,
+
+    #[error("Session was changed by another request")]
+    StaleSession,
+
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let status = match &self {
+impl AppError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
             AppError::SessionNotFound => StatusCode::NOT_FOUND,
+            AppError::TurnInProgress | AppError::StaleSession => StatusCode::CONFLICT,
             AppError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
-        };
+        }
+    }
+}
 
-        (status, self.to_string()).into_response()
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        (self.status_code(), self.to_string()).into_response()
     }
 }
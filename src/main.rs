@@ -1,8 +1,8 @@
-use axum::{routing::{get, post}, Router};
-use std::net::SocketAddr;
+use axum::{extract::DefaultBodyLimit, routing::{get, post}, Router};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tower_http::services::ServeDir;
 use tracing::{info, error};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt};
 
 mod chat;
 mod config;
@@ -10,30 +10,54 @@ mod error;
 mod ollama;
 mod routes;
 mod session;
+mod templates;
 mod tools;
 
+use chat::persona::Personas;
+use chat::turns::ActiveTurns;
 use config::AppConfig;
 use ollama::OllamaClient;
-use session::{create_session_manager, SessionManager};
+use session::{open_session_manager, reaper, EvictionStats, SessionManager};
+use templates::Templates;
+use tools::mcp_service::WebToolsService;
 use tools::ToolExecutor;
 
 #[derive(Clone)]
 pub struct AppState {
     pub sessions: SessionManager,
+    pub eviction_stats: Arc<EvictionStats>,
     pub ollama: OllamaClient,
     pub tools: ToolExecutor,
+    pub personas: Arc<Personas>,
+    pub templates: Arc<Templates>,
+    pub turns: Arc<ActiveTurns>,
     pub config: AppConfig,
 }
 
 #[tokio::main]
 async fn main() {
-    // Initialize logging
+    // `ferret mcp` serves the web tools over stdio instead of the web app
+    let mcp_stdio = match std::env::args().nth(1).as_deref() {
+        None => false,
+        Some("mcp") => true,
+        Some(other) => {
+            eprintln!("Unknown command '{}'; run `ferret` or `ferret mcp`", other);
+            std::process::exit(2);
+        }
+    };
+
+    // Initialize logging; stdout carries the protocol in MCP mode
+    let log_writer = if mcp_stdio {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "info,ferret=debug".into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(log_writer))
         .init();
 
     // Load .env file if present
@@ -46,32 +70,151 @@ async fn main() {
         Ok(c) => c,
         Err(e) => {
             error!("Failed to load configuration: {}", e);
-            error!("Please ensure BRAVE_API_KEY environment variable is set");
             std::process::exit(1);
         }
     };
 
+    if mcp_stdio {
+        let tools = match ToolExecutor::from_config(&config) {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Failed to load tools: {}", e);
+                std::process::exit(1);
+            }
+        };
+        if let Err(e) = WebToolsService::new(tools.served_tools()).serve_stdio().await {
+            error!("MCP server failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     info!("Starting Ferret with configuration:");
     info!("  Ollama URL: {}", config.ollama_url);
     info!("  Ollama Model: {}", config.ollama_model);
+    info!("  Search Provider: {:?}", config.search_backend);
     info!("  Bind Address: {}", config.bind_address);
+    info!("  Session Store: {:?}", config.session_backend);
+    info!(
+        "  Session Timeout: {} mins (max {} sessions)",
+        config.session_timeout_mins, config.max_sessions
+    );
+
+    let sessions = match open_session_manager(&config) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Failed to open session store: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let personas = match Personas::load(
+        std::path::Path::new(&config.personas_dir),
+        &config.default_persona,
+    ) {
+        Ok(p) => p,
+        Err(e) => {
+            error!("Failed to load personas: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let tools = match ToolExecutor::from_config(&config) {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to load tools: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let templates = match Templates::new() {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to load templates: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     // Create shared state
     let state = AppState {
-        sessions: create_session_manager(),
-        ollama: OllamaClient::new(&config.ollama_url, &config.ollama_model),
-        tools: ToolExecutor::new(&config.brave_api_key),
+        sessions,
+        eviction_stats: Arc::new(EvictionStats::default()),
+        ollama: OllamaClient::new(&config.ollama_url, &config.ollama_model)
+            .with_defaults(config.generation.clone()),
+        tools,
+        personas: Arc::new(personas),
+        templates: Arc::new(templates),
+        turns: Arc::new(ActiveTurns::default()),
         config: config.clone(),
     };
 
+    // Give MCP servers a chance to list their tools before the first chat
+    state.tools.start_mcp().await;
+
+    // Start background session reaper
+    reaper::spawn_reaper(
+        state.sessions.clone(),
+        state.eviction_stats.clone(),
+        config.session_timeout_mins,
+        config.max_sessions,
+        config.session_retention_days,
+        Duration::from_secs(config.reaper_interval_secs.max(1)),
+    );
+
+    // MCP clients share the chat's tools, and so their guard and provider
+    let served_tools = state.tools.served_tools();
+
     // Build router
     let app = Router::new()
         .route("/", get(routes::index))
         .route("/chat", post(routes::chat))
+        .route("/chat/stop", post(routes::chat::stop))
         .route("/clear", post(routes::clear))
         .route("/health", get(routes::health))
+        .route(
+            "/conversations",
+            get(routes::conversations::list_conversations)
+                .post(routes::conversations::create_conversation),
+        )
+        .route(
+            "/conversations/:id",
+            get(routes::conversations::get_conversation)
+                .patch(routes::conversations::rename_conversation)
+                .delete(routes::conversations::delete_conversation),
+        )
+        .route(
+            "/conversations/import",
+            post(routes::conversations::import_conversations)
+                .layer(DefaultBodyLimit::max(routes::export::MAX_IMPORT_BYTES)),
+        )
+        .route(
+            "/conversations/:id/messages",
+            get(routes::conversations::conversation_messages),
+        )
+        .route(
+            "/conversations/:id/export",
+            get(routes::conversations::export_conversation),
+        )
+        .route(
+            "/conversations/:id/select",
+            post(routes::conversations::select_conversation),
+        )
+        .route("/model", get(routes::model::current_model).post(routes::model::set_model))
+        .route(
+            "/persona",
+            get(routes::persona::current_persona).post(routes::persona::set_persona),
+        )
+        .nest("/api/v1", routes::api::router())
+        .route("/v1/chat/completions", post(routes::openai::chat_completions))
+        .route("/v1/models", get(routes::openai::models))
         .nest_service("/static", ServeDir::new("static"))
         .with_state(state);
+    let app = if config.mcp_endpoint {
+        info!("Serving MCP at /mcp");
+        app.route_service("/mcp", WebToolsService::new(served_tools).http_service())
+    } else {
+        app
+    };
 
     // Parse bind address
     let addr: SocketAddr = config
@@ -1,18 +1,32 @@
+use dashmap::DashMap;
 use futures::StreamExt;
 use reqwest::Client;
+use std::sync::Arc;
 use tokio::sync::mpsc;
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 use crate::error::AppError;
 use crate::session::ChatMessage;
 
-use super::types::{OllamaChatChunk, OllamaChatRequest};
+use super::types::{
+    ChatDelta, GenerationOptions, OllamaChatChunk, OllamaChatRequest, OllamaModel,
+    OllamaShowRequest, OllamaShowResponse, OllamaTagsResponse, OllamaTool,
+};
+
+/// Ollama's runtime context size when neither the request nor the
+/// Modelfile sets `num_ctx`.
+const DEFAULT_NUM_CTX: usize = 2048;
 
 #[derive(Clone)]
 pub struct OllamaClient {
     client: Client,
     base_url: String,
-    model: String,
+    /// Model used by sessions that have not picked one
+    default_model: String,
+    /// Server-wide generation defaults, under session and request settings
+    defaults: GenerationOptions,
+    /// Context windows looked up from `/api/show`, by model name
+    context_windows: Arc<DashMap<String, usize>>,
 }
 
 impl OllamaClient {
@@ -20,10 +34,129 @@ impl OllamaClient {
         Self {
             client: Client::new(),
             base_url: base_url.trim_end_matches('/').to_string(),
-            model: model.to_string(),
+            default_model: model.to_string(),
+            defaults: GenerationOptions::default(),
+            context_windows: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub fn with_defaults(mut self, defaults: GenerationOptions) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /// Server defaults with `overrides` layered on top.
+    pub fn effective_options(&self, overrides: &GenerationOptions) -> GenerationOptions {
+        self.defaults.merged(overrides)
+    }
+
+    pub fn default_model(&self) -> &str {
+        &self.default_model
+    }
+
+    /// Models installed in Ollama, from `/api/tags`.
+    pub async fn list_models(&self) -> Result<Vec<OllamaModel>, AppError> {
+        let url = format!("{}/api/tags", self.base_url);
+
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| AppError::Ollama(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(AppError::Ollama(format!("Status {}", response.status())));
+        }
+
+        let tags: OllamaTagsResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Ollama(e.to_string()))?;
+
+        Ok(tags.models)
+    }
+
+    /// Check that a model is installed, returning its canonical name.
+    /// `llama3.1` matches `llama3.1:latest`, as on the Ollama CLI.
+    pub async fn resolve_model(&self, requested: &str) -> Result<String, AppError> {
+        let requested = requested.trim();
+        let models = self.list_models().await?;
+
+        let found = models.iter().find(|m| {
+            m.name == requested
+                || (!requested.contains(':') && m.name == format!("{}:latest", requested))
+        });
+
+        match found {
+            Some(model) => Ok(model.name.clone()),
+            None => {
+                let installed: Vec<&str> = models.iter().map(|m| m.name.as_str()).collect();
+                Err(AppError::InvalidRequest(format!(
+                    "Unknown model '{}'. Installed models: {}. Pull it first with `ollama pull {}`",
+                    requested,
+                    if installed.is_empty() {
+                        "none".to_string()
+                    } else {
+                        installed.join(", ")
+                    },
+                    requested
+                )))
+            }
         }
     }
 
+    /// The context window, in tokens, `model` will run with.
+    ///
+    /// A configured `num_ctx` wins; otherwise the model's Modelfile is
+    /// consulted, falling back to Ollama's default.
+    pub async fn context_window(&self, model: &str, overrides: &GenerationOptions) -> usize {
+        if let Some(num_ctx) = self.effective_options(overrides).options.num_ctx {
+            return num_ctx;
+        }
+        if let Some(cached) = self.context_windows.get(model) {
+            return *cached;
+        }
+
+        match self.show_num_ctx(model).await {
+            Ok(num_ctx) => {
+                let num_ctx = num_ctx.unwrap_or(DEFAULT_NUM_CTX);
+                debug!("Context window for {}: {} tokens", model, num_ctx);
+                self.context_windows.insert(model.to_string(), num_ctx);
+                num_ctx
+            }
+            Err(e) => {
+                warn!("Could not look up context window for {}: {}", model, e);
+                DEFAULT_NUM_CTX
+            }
+        }
+    }
+
+    async fn show_num_ctx(&self, model: &str) -> Result<Option<usize>, AppError> {
+        let url = format!("{}/api/show", self.base_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .json(&OllamaShowRequest {
+                model: model.to_string(),
+            })
+            .send()
+            .await
+            .map_err(|e| AppError::Ollama(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(AppError::Ollama(format!("Status {}", response.status())));
+        }
+
+        let show: OllamaShowResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Ollama(e.to_string()))?;
+
+        Ok(show.parameters.as_deref().and_then(parse_num_ctx))
+    }
+
     pub async fn check_health(&self) -> Result<bool, AppError> {
         let url = format!("{}/api/tags", self.base_url);
         match self.client.get(&url).send().await {
@@ -35,14 +168,22 @@ impl OllamaClient {
         }
     }
 
-    pub async fn chat(&self, messages: Vec<ChatMessage>) -> Result<String, AppError> {
+    pub async fn chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        overrides: &GenerationOptions,
+    ) -> Result<String, AppError> {
         let url = format!("{}/api/chat", self.base_url);
+        let settings = self.effective_options(overrides);
 
         let request = OllamaChatRequest {
-            model: self.model.clone(),
+            model: model.to_string(),
             messages,
             stream: false,
-            options: None,
+            options: (!settings.options.is_empty()).then_some(settings.options),
+            tools: None,
+            keep_alive: settings.keep_alive,
         };
 
         debug!("Sending chat request to Ollama");
@@ -71,15 +212,21 @@ impl OllamaClient {
 
     pub async fn chat_stream(
         &self,
+        model: &str,
         messages: Vec<ChatMessage>,
-    ) -> Result<mpsc::Receiver<Result<String, AppError>>, AppError> {
+        tools: Option<Vec<OllamaTool>>,
+        overrides: &GenerationOptions,
+    ) -> Result<mpsc::Receiver<Result<ChatDelta, AppError>>, AppError> {
         let url = format!("{}/api/chat", self.base_url);
+        let settings = self.effective_options(overrides);
 
         let request = OllamaChatRequest {
-            model: self.model.clone(),
+            model: model.to_string(),
             messages,
             stream: true,
-            options: None,
+            options: (!settings.options.is_empty()).then_some(settings.options),
+            tools,
+            keep_alive: settings.keep_alive,
         };
 
         debug!("Starting streaming chat request to Ollama");
@@ -102,23 +249,57 @@ impl OllamaClient {
 
         tokio::spawn(async move {
             let mut stream = response.bytes_stream();
+            // NDJSON lines can be split across network chunks
+            let mut buffer: Vec<u8> = Vec::new();
 
-            while let Some(chunk_result) = stream.next().await {
+            loop {
+                // Stop reading, closing the connection and with it the
+                // generation, as soon as nobody is listening
+                let chunk_result = tokio::select! {
+                    _ = tx.closed() => {
+                        debug!("Stream receiver dropped, abandoning the Ollama request");
+                        return;
+                    }
+                    chunk = stream.next() => match chunk {
+                        Some(chunk) => chunk,
+                        None => return,
+                    },
+                };
                 match chunk_result {
                     Ok(bytes) => {
-                        let text = String::from_utf8_lossy(&bytes);
-                        for line in text.lines() {
+                        buffer.extend_from_slice(&bytes);
+
+                        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                            let line_bytes: Vec<u8> = buffer.drain(..=pos).collect();
+                            let line = String::from_utf8_lossy(&line_bytes);
+                            let line = line.trim();
                             if line.is_empty() {
                                 continue;
                             }
                             match serde_json::from_str::<OllamaChatChunk>(line) {
                                 Ok(chunk) => {
-                                    if !chunk.message.content.is_empty() {
-                                        if tx.send(Ok(chunk.message.content)).await.is_err() {
-                                            return;
-                                        }
+                                    let message = chunk.message;
+                                    if !message.content.is_empty()
+                                        && tx
+                                            .send(Ok(ChatDelta::Content(message.content)))
+                                            .await
+                                            .is_err()
+                                    {
+                                        return;
+                                    }
+                                    if !message.tool_calls.is_empty()
+                                        && tx
+                                            .send(Ok(ChatDelta::ToolCalls(message.tool_calls)))
+                                            .await
+                                            .is_err()
+                                    {
+                                        return;
                                     }
                                     if chunk.done {
+                                        debug!(
+                                            "Stream from {} finished (done_reason: {:?})",
+                                            chunk.model, chunk.done_reason
+                                        );
                                         return;
                                     }
                                 }
@@ -139,3 +320,14 @@ impl OllamaClient {
         Ok(rx)
     }
 }
+
+/// Find `num_ctx` in Modelfile parameters such as `"num_ctx    8192\nstop ..."`.
+fn parse_num_ctx(parameters: &str) -> Option<usize> {
+    parameters.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        if parts.next()? != "num_ctx" {
+            return None;
+        }
+        parts.next()?.parse().ok()
+    })
+}
@@ -2,3 +2,61 @@ pub mod client;
 pub mod types;
 
 pub use client::OllamaClient;
+
+/// One streamed `/api/chat` reply from `stub_server`.
+#[cfg(test)]
+pub struct StubReply {
+    /// The `message` of each chunk, in order
+    pub messages: Vec<serde_json::Value>,
+    /// Whether the stream ends; otherwise it stays open, as if the model
+    /// were still generating
+    pub finished: bool,
+}
+
+/// A stand-in for Ollama on a local port, for handler tests. Each
+/// `/api/chat` request streams the next of `replies`; the base URL is
+/// returned.
+#[cfg(test)]
+pub async fn stub_server(replies: Vec<StubReply>) -> String {
+    use axum::{body::Body, response::IntoResponse, routing::post, Json, Router};
+    use futures::stream::{self, StreamExt};
+    use serde_json::json;
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+
+    let replies = Arc::new(Mutex::new(VecDeque::from(replies)));
+    let chat = move || {
+        let reply = replies.lock().unwrap().pop_front();
+        async move {
+            let Some(reply) = reply else {
+                return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            };
+            let chunk = |message, done| {
+                Ok::<_, Infallible>(format!(
+                    "{}\n",
+                    json!({ "model": "test-model", "message": message, "done": done })
+                ))
+            };
+            let mut lines: Vec<_> = reply.messages.into_iter().map(|m| chunk(m, false)).collect();
+            if reply.finished {
+                lines.push(chunk(json!({ "role": "assistant", "content": "" }), true));
+            }
+            let lines = stream::iter(lines);
+            let body = if reply.finished {
+                lines.boxed()
+            } else {
+                lines.chain(stream::pending()).boxed()
+            };
+            Body::from_stream(body).into_response()
+        }
+    };
+
+    let app = Router::new()
+        .route("/api/show", post(|| async { Json(json!({})) }))
+        .route("/api/chat", post(chat));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    url
+}
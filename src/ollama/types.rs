@@ -9,14 +9,95 @@ pub struct OllamaChatRequest {
     pub stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub options: Option<OllamaOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<OllamaTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+/// Sampling and runtime options, sent as `options` on chat requests.
+///
+/// Unset fields are omitted so the model's Modelfile defaults apply.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct OllamaOptions {
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub num_predict: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+}
+
+impl OllamaOptions {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Layer `overrides` on top of these options, field by field.
+    pub fn merged(&self, overrides: &OllamaOptions) -> Self {
+        Self {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            top_k: overrides.top_k.or(self.top_k),
+            repeat_penalty: overrides.repeat_penalty.or(self.repeat_penalty),
+            seed: overrides.seed.or(self.seed),
+            num_ctx: overrides.num_ctx.or(self.num_ctx),
+            num_predict: overrides.num_predict.or(self.num_predict),
+            stop: overrides.stop.clone().or_else(|| self.stop.clone()),
+        }
+    }
+}
+
+/// Everything configurable about a generation: the model options plus
+/// `keep_alive`, which Ollama takes at the top level of the request.
+///
+/// Layered as server defaults, then session settings, then per request.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationOptions {
+    #[serde(flatten)]
+    pub options: OllamaOptions,
+    /// How long Ollama keeps the model loaded, e.g. `"10m"` or `"-1"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+}
+
+impl GenerationOptions {
+    pub fn is_empty(&self) -> bool {
+        self.options.is_empty() && self.keep_alive.is_none()
+    }
+
+    pub fn merged(&self, overrides: &GenerationOptions) -> Self {
+        Self {
+            options: self.options.merged(&overrides.options),
+            keep_alive: overrides
+                .keep_alive
+                .clone()
+                .or_else(|| self.keep_alive.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct OllamaShowRequest {
+    pub model: String,
+}
+
+/// The parts of `/api/show` used to size the context window.
+#[derive(Debug, Deserialize)]
+pub struct OllamaShowResponse {
+    /// Modelfile parameters, one `name value` pair per line
+    #[serde(default)]
+    pub parameters: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -27,3 +108,110 @@ pub struct OllamaChatChunk {
     #[serde(default)]
     pub done_reason: Option<String>,
 }
+
+/// Response from `/api/tags`.
+#[derive(Debug, Deserialize)]
+pub struct OllamaTagsResponse {
+    #[serde(default)]
+    pub models: Vec<OllamaModel>,
+}
+
+/// An installed model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaModel {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<OllamaModelDetails>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaModelDetails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameter_size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantization_level: Option<String>,
+}
+
+/// A function the model may call in native tool mode.
+#[derive(Debug, Clone, Serialize)]
+pub struct OllamaTool {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: OllamaFunction,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OllamaFunction {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema describing the arguments object
+    pub parameters: serde_json::Value,
+}
+
+impl OllamaTool {
+    pub fn function(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: serde_json::Value,
+    ) -> Self {
+        Self {
+            kind: "function".to_string(),
+            function: OllamaFunction {
+                name: name.into(),
+                description: description.into(),
+                parameters,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaToolCall {
+    pub function: OllamaFunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaFunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+/// An increment of a streamed chat response.
+#[derive(Debug)]
+pub enum ChatDelta {
+    Content(String),
+    ToolCalls(Vec<OllamaToolCall>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generation_options_layering() {
+        let defaults: GenerationOptions =
+            serde_json::from_str(r#"{"temperature": 0.7, "num_ctx": 4096, "keep_alive": "5m"}"#)
+                .unwrap();
+        let session: GenerationOptions =
+            serde_json::from_str(r#"{"num_ctx": 16384, "seed": 42}"#).unwrap();
+        let request: GenerationOptions =
+            serde_json::from_str(r#"{"temperature": 0.0, "stop": ["\n\n"]}"#).unwrap();
+
+        let effective = defaults.merged(&session).merged(&request);
+        assert_eq!(effective.options.temperature, Some(0.0));
+        assert_eq!(effective.options.num_ctx, Some(16384));
+        assert_eq!(effective.options.seed, Some(42));
+        assert_eq!(effective.options.stop, Some(vec!["\n\n".to_string()]));
+        assert_eq!(effective.keep_alive.as_deref(), Some("5m"));
+
+        // keep_alive stays out of the Ollama `options` object
+        let options = serde_json::to_value(&effective.options).unwrap();
+        assert!(options.get("keep_alive").is_none());
+        assert_eq!(options["seed"], 42);
+    }
+}
@@ -0,0 +1,459 @@
+//! Versioned JSON API under `/api/v1`, for scripts and other services.
+//!
+//! Session ids are passed in the path rather than by cookie. The API only
+//! sees sessions created through it, which have no owner; the web
+//! interface's conversations belong to a browser and are reported as
+//! missing here.
+
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::chat::persona::Persona;
+use crate::chat::title::MAX_TITLE_CHARS;
+use crate::chat::StreamEvent;
+use crate::error::AppError;
+use crate::ollama::types::{GenerationOptions, OllamaModel};
+use crate::session::{manager, reaper, ChatMessage, Session, SessionInfo};
+use crate::AppState;
+
+use super::chat::{event_stream, set_locale, spawn_chat, StopResult};
+use super::export::{export_response, import, ExportQuery, MAX_IMPORT_BYTES};
+
+/// Error responses as `{"error": "..."}` with the usual status codes.
+pub struct ApiError(AppError);
+
+impl From<AppError> for ApiError {
+    fn from(error: AppError) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (
+            self.0.status_code(),
+            Json(json!({ "error": self.0.to_string() })),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Serialize)]
+pub struct SessionList {
+    sessions: Vec<SessionInfo>,
+}
+
+#[derive(Serialize)]
+pub struct MessageHistory {
+    session_id: Uuid,
+    messages: Vec<ChatMessage>,
+}
+
+#[derive(Deserialize)]
+pub struct MessageInput {
+    pub message: String,
+    /// Stream events as SSE instead of returning one JSON reply
+    #[serde(default)]
+    pub stream: bool,
+    /// Generation options for this message only, over the session's
+    #[serde(default)]
+    pub options: GenerationOptions,
+}
+
+#[derive(Serialize)]
+pub struct ModelList {
+    default: String,
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Deserialize)]
+pub struct ModelChoice {
+    /// Installed model name; `null` or empty reverts to the server default
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TitleChoice {
+    /// `null` or empty clears the title so one is generated again
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PersonaList {
+    default: String,
+    personas: Vec<Persona>,
+}
+
+#[derive(Deserialize)]
+pub struct PersonaChoice {
+    /// Persona id; `null` or empty reverts to the server default
+    #[serde(default)]
+    pub persona: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct MessageReply {
+    session_id: Uuid,
+    reply: String,
+    tools: Vec<ToolActivity>,
+    /// The reply was stopped before it finished
+    interrupted: bool,
+}
+
+#[derive(Serialize)]
+pub struct ToolActivity {
+    id: String,
+    tool: String,
+    query: String,
+    success: bool,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/sessions", get(list_sessions).post(create_session))
+        .route(
+            "/sessions/import",
+            post(import_sessions).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
+        .route("/sessions/:id", get(get_session).delete(delete_session))
+        .route("/sessions/:id/export", get(export_session))
+        .route("/sessions/:id/messages", get(get_messages).post(post_message))
+        .route("/sessions/:id/stop", post(stop_session))
+        .route("/sessions/:id/model", put(set_model))
+        .route("/sessions/:id/persona", put(set_persona))
+        .route("/sessions/:id/title", put(set_title))
+        .route("/sessions/:id/options", get(get_options).put(set_options))
+        .route("/models", get(list_models))
+        .route("/personas", get(list_personas))
+}
+
+pub async fn list_models(State(state): State<AppState>) -> Result<Json<ModelList>, ApiError> {
+    Ok(Json(ModelList {
+        default: state.ollama.default_model().to_string(),
+        models: state.ollama.list_models().await?,
+    }))
+}
+
+pub async fn set_model(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(choice): Json<ModelChoice>,
+) -> Result<Json<SessionInfo>, ApiError> {
+    find(&state, id)?;
+    let model = validate_model(&state, choice.model.as_deref()).await?;
+
+    manager::set_session_model(&state.sessions, id, model)
+        .map(|session| Json(session.info()))
+        .ok_or_else(|| AppError::SessionNotFound.into())
+}
+
+pub async fn set_title(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(choice): Json<TitleChoice>,
+) -> Result<Json<SessionInfo>, ApiError> {
+    find(&state, id)?;
+    let title = choice
+        .title
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty());
+    if title.as_ref().is_some_and(|t| t.chars().count() > MAX_TITLE_CHARS) {
+        return Err(AppError::InvalidRequest(format!(
+            "Titles are limited to {} characters",
+            MAX_TITLE_CHARS
+        ))
+        .into());
+    }
+
+    manager::set_session_title(&state.sessions, id, title)
+        .map(|session| Json(session.info()))
+        .ok_or_else(|| AppError::SessionNotFound.into())
+}
+
+pub async fn list_personas(State(state): State<AppState>) -> Json<PersonaList> {
+    Json(PersonaList {
+        default: state.personas.default_id().to_string(),
+        personas: state.personas.list(),
+    })
+}
+
+pub async fn set_persona(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(choice): Json<PersonaChoice>,
+) -> Result<Json<SessionInfo>, ApiError> {
+    find(&state, id)?;
+    let persona = state.personas.validate(choice.persona.as_deref())?;
+
+    manager::set_session_persona(&state.sessions, id, persona)
+        .map(|session| Json(session.info()))
+        .ok_or_else(|| AppError::SessionNotFound.into())
+}
+
+/// The session's own options and what they resolve to with server defaults.
+#[derive(Serialize)]
+pub struct SessionOptions {
+    options: GenerationOptions,
+    effective: GenerationOptions,
+}
+
+pub async fn get_options(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SessionOptions>, ApiError> {
+    let session = find(&state, id)?;
+    Ok(Json(session_options(&state, session.options)))
+}
+
+/// Replace the session's options; fields left out fall back to the defaults.
+pub async fn set_options(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(options): Json<GenerationOptions>,
+) -> Result<Json<SessionOptions>, ApiError> {
+    find(&state, id)?;
+    manager::set_session_options(&state.sessions, id, options)
+        .map(|session| Json(session_options(&state, session.options)))
+        .ok_or_else(|| AppError::SessionNotFound.into())
+}
+
+fn session_options(state: &AppState, options: GenerationOptions) -> SessionOptions {
+    SessionOptions {
+        effective: state.ollama.effective_options(&options),
+        options,
+    }
+}
+
+/// Resolve a requested model against those installed. Blank means the
+/// server default, stored as `None`.
+pub async fn validate_model(
+    state: &AppState,
+    requested: Option<&str>,
+) -> Result<Option<String>, AppError> {
+    match requested.map(str::trim).filter(|m| !m.is_empty()) {
+        Some(model) => Ok(Some(state.ollama.resolve_model(model).await?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn create_session(State(state): State<AppState>) -> (StatusCode, Json<SessionInfo>) {
+    let session = manager::get_or_create_session(&state.sessions, Uuid::new_v4(), None);
+    reaper::enforce_capacity(
+        &state.sessions,
+        state.config.max_sessions,
+        &state.eviction_stats,
+    );
+
+    (StatusCode::CREATED, Json(session.info()))
+}
+
+pub async fn list_sessions(State(state): State<AppState>) -> Json<SessionList> {
+    Json(SessionList {
+        sessions: manager::list_api_sessions(&state.sessions),
+    })
+}
+
+pub async fn get_session(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SessionInfo>, ApiError> {
+    Ok(Json(find(&state, id)?.info()))
+}
+
+pub async fn delete_session(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    find(&state, id)?;
+    // A reply still being generated would have nowhere to go
+    state.turns.stop(id);
+    if manager::delete_session(&state.sessions, id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::SessionNotFound.into())
+    }
+}
+
+/// Download a session as Markdown or lossless JSON (`?format=markdown|json`).
+pub async fn export_session(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    Ok(export_response(&state, find(&state, id)?, query.format))
+}
+
+/// Import a Ferret JSON export, a bare session, or ChatGPT's
+/// `conversations.json`. Each conversation becomes a new session.
+pub async fn import_sessions(
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<SessionList>), ApiError> {
+    let sessions = import(&state, &body, None)?;
+    Ok((StatusCode::CREATED, Json(SessionList { sessions })))
+}
+
+pub async fn get_messages(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<MessageHistory>, ApiError> {
+    let session = find(&state, id)?;
+    Ok(Json(MessageHistory {
+        session_id: session.id,
+        messages: session.messages,
+    }))
+}
+
+pub async fn post_message(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(input): Json<MessageInput>,
+) -> Result<Response, ApiError> {
+    let message = input.message.trim().to_string();
+    if message.is_empty() {
+        return Err(AppError::InvalidRequest("Message cannot be empty".to_string()).into());
+    }
+
+    find(&state, id)?;
+    let turn = state.turns.try_start(id)?;
+    let session = manager::modify_session(&state.sessions, id, |session| {
+        set_locale(session, &headers);
+    })
+    .ok_or(AppError::SessionNotFound)?;
+    let mut rx = spawn_chat(state, turn, session, message, input.options);
+
+    if input.stream {
+        return Ok(event_stream(rx).into_response());
+    }
+
+    let mut reply = String::new();
+    let mut tools: Vec<ToolActivity> = Vec::new();
+    let mut error = None;
+    let mut interrupted = false;
+
+    // The channel closes once the session has been saved
+    while let Some(event) = rx.recv().await {
+        match event {
+            StreamEvent::Chunk { content } => reply.push_str(&content),
+            StreamEvent::ToolStart { id, tool, query } => tools.push(ToolActivity {
+                id,
+                tool,
+                query,
+                success: false,
+            }),
+            StreamEvent::ToolEnd { id, success, .. } => {
+                if let Some(activity) = tools.iter_mut().find(|t| t.id == id) {
+                    activity.success = success;
+                }
+            }
+            StreamEvent::Error { message } => error = Some(message),
+            StreamEvent::Interrupted => interrupted = true,
+            StreamEvent::Done => {}
+        }
+    }
+
+    // The message is already a formatted `AppError`
+    if let Some(message) = error.filter(|_| reply.is_empty()) {
+        return Ok((StatusCode::BAD_GATEWAY, Json(json!({ "error": message }))).into_response());
+    }
+
+    Ok(Json(MessageReply {
+        session_id: id,
+        reply,
+        tools,
+        interrupted,
+    })
+    .into_response())
+}
+
+/// Stop the reply being generated for a session; see `POST /chat/stop`.
+pub async fn stop_session(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<StopResult>, ApiError> {
+    find(&state, id)?;
+    Ok(Json(StopResult {
+        stopped: state.turns.stop(id),
+    }))
+}
+
+/// Look up a session created through the API. Browser conversations are
+/// reported as missing.
+fn find(state: &AppState, id: Uuid) -> Result<Session, AppError> {
+    manager::find_session(&state.sessions, id)
+        .filter(|session| session.owner.is_none())
+        .ok_or(AppError::SessionNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use crate::routes::test_state;
+
+    async fn call(state: &AppState, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router().with_state(state.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_browser_conversations_are_hidden() {
+        let state = test_state();
+        let owned = manager::create_conversation(&state.sessions, Uuid::new_v4()).id;
+        let (status, created) = call(&state, "POST", "/sessions", Value::Null).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (_, list) = call(&state, "GET", "/sessions", Value::Null).await;
+        let listed: Vec<&Value> = list["sessions"].as_array().unwrap().iter().map(|s| &s["id"]).collect();
+        assert_eq!(listed, [&created["id"]]);
+
+        let requests = [
+            ("GET", "", Value::Null),
+            ("GET", "/messages", Value::Null),
+            ("GET", "/export?format=json", Value::Null),
+            ("GET", "/options", Value::Null),
+            ("POST", "/messages", json!({ "message": "Hi" })),
+            ("POST", "/stop", Value::Null),
+            ("PUT", "/title", json!({ "title": "Mine now" })),
+            ("PUT", "/persona", json!({ "persona": null })),
+            ("PUT", "/options", json!({})),
+            ("DELETE", "", Value::Null),
+        ];
+        for (method, path, body) in requests {
+            let uri = format!("/sessions/{}{}", owned, path);
+            let (status, _) = call(&state, method, &uri, body).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{} {}", method, uri);
+        }
+
+        let session = manager::find_session(&state.sessions, owned).unwrap();
+        assert_eq!(session.title, None);
+        assert_eq!(session.version, 1);
+
+        let uri = format!("/sessions/{}", created["id"].as_str().unwrap());
+        assert_eq!(call(&state, "GET", &uri, Value::Null).await.0, StatusCode::OK);
+    }
+}
@@ -1,29 +1,44 @@
 use axum::{
     extract::State,
+    http::{header, HeaderMap},
     response::sse::{Event, KeepAlive, Sse},
-    Form,
+    Form, Json,
 };
 use axum_extra::extract::CookieJar;
+use chrono::Utc;
 use futures::stream::Stream;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{convert::Infallible, time::Duration};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
+use tracing::warn;
 use uuid::Uuid;
 
-use crate::chat::{handle_chat, StreamEvent};
+use crate::chat::handler::session_model;
+use crate::chat::turns::Turn;
+use crate::chat::{handle_chat, title, StreamEvent};
 use crate::error::AppError;
-use crate::session::manager;
+use crate::ollama::types::GenerationOptions;
+use crate::session::{manager, reaper, Session};
 use crate::AppState;
 
+use super::conversations::{current_conversation, owner_id, CONVERSATION_COOKIE};
+
 #[derive(Deserialize)]
 pub struct ChatInput {
     pub message: String,
 }
 
+#[derive(Serialize)]
+pub struct StopResult {
+    /// Whether a reply was being generated
+    pub stopped: bool,
+}
+
 pub async fn chat(
     cookies: CookieJar,
     State(state): State<AppState>,
+    headers: HeaderMap,
     Form(input): Form<ChatInput>,
 ) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
     let message = input.message.trim().to_string();
@@ -33,35 +48,281 @@ pub async fn chat(
     }
 
     let session_id = cookies
-        .get("session_id")
+        .get(CONVERSATION_COOKIE)
         .and_then(|c| Uuid::parse_str(c.value()).ok())
         .ok_or(AppError::SessionNotFound)?;
+    let owner = owner_id(&cookies).ok_or(AppError::SessionNotFound)?;
+
+    // Refuse a second message before touching the session
+    let turn = state.turns.try_start(session_id)?;
+
+    let session = manager::get_or_create_session(&state.sessions, session_id, Some(owner));
+    if session.legacy {
+        manager::adopt_conversation(&state.sessions, session_id, owner)
+            .ok_or(AppError::SessionNotFound)?;
+    } else if session.owner != Some(owner) {
+        return Err(AppError::SessionNotFound);
+    }
+    let session = manager::modify_session(&state.sessions, session_id, |session| {
+        set_locale(session, &headers);
+        // Marked active so making room below can't evict it
+        session.last_activity = Utc::now();
+    })
+    .ok_or(AppError::SessionNotFound)?;
+    reaper::enforce_capacity(
+        &state.sessions,
+        state.config.max_sessions,
+        &state.eviction_stats,
+    );
+
+    let rx = spawn_chat(state, turn, session, message, GenerationOptions::default());
+
+    Ok(event_stream(rx))
+}
+
+/// Stop the reply being generated for the open conversation. The partial
+/// answer is saved and the stream ends with an `interrupted` event.
+pub async fn stop(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+) -> Result<Json<StopResult>, AppError> {
+    let session = current_conversation(&state, &cookies)?;
 
-    let mut session = manager::get_or_create_session(&state.sessions, session_id);
+    Ok(Json(StopResult {
+        stopped: state.turns.stop(session.id),
+    }))
+}
 
+/// Run a chat turn in the background, saving the session when it finishes.
+///
+/// Only the messages added by the turn are appended to the latest copy of
+/// the session, so renames, settings and history changed while it ran are
+/// kept.
+pub fn spawn_chat(
+    state: AppState,
+    turn: Turn,
+    mut session: Session,
+    message: String,
+    options: GenerationOptions,
+) -> mpsc::Receiver<StreamEvent> {
     let (tx, rx) = mpsc::channel::<StreamEvent>(100);
 
     // Spawn chat handler
-    let ollama = state.ollama.clone();
-    let tools = state.tools.clone();
-
     tokio::spawn(async move {
-        handle_chat(&ollama, &tools, &mut session, message, tx).await;
+        let base_len = session.messages.len();
+        handle_chat(
+            &state,
+            &mut session,
+            message,
+            &options,
+            tx.clone(),
+            &turn.token,
+        )
+        .await;
+        let interrupted = turn.token.is_cancelled();
+
+        // Title new conversations straight away from the first message,
+        // then let the model suggest something better below
+        let exchange = title::first_exchange(&session)
+            .filter(|_| session.title.is_none())
+            .map(|(question, answer)| (question.to_string(), answer.to_string()));
+        let provisional = exchange
+            .as_ref()
+            .map(|(question, _)| title::fallback_title(question));
+
+        // Update session after handling; holding `tx` until now means the
+        // stream only closes once the session is saved
+        let id = session.id;
+        let saved = manager::modify_session(&state.sessions, id, |latest| {
+            // The summary indexes the history the turn started from, so it
+            // only applies if that is still what is stored
+            if latest.messages.len() == base_len {
+                latest.summary.clone_from(&session.summary);
+            }
+            latest
+                .messages
+                .extend_from_slice(&session.messages[base_len..]);
+            latest.last_activity = session.last_activity;
+            if latest.title.is_none() {
+                latest.title.clone_from(&provisional);
+            }
+        });
+        drop(turn);
+        drop(tx);
+
+        let Some(saved) = saved else {
+            warn!("Session {} was deleted before its reply was saved", id);
+            return;
+        };
 
-        // Update session after handling
-        manager::update_session(&state.sessions, session);
+        // A stopped turn shouldn't go on to use the model, and a title the
+        // user set meanwhile stays
+        let Some(((question, answer), provisional)) = exchange
+            .zip(provisional)
+            .filter(|(_, provisional)| !interrupted && saved.title.as_ref() == Some(provisional))
+        else {
+            return;
+        };
+        let model = session_model(&state, &saved);
+        let options = saved.options.merged(&options);
+        match title::generate_title(&state.ollama, &model, &options, &question, &answer).await {
+            Ok(generated) => manager::replace_title(&state.sessions, id, &provisional, generated),
+            Err(e) => warn!("Could not generate a title for {}: {}", id, e),
+        }
     });
 
-    // Convert to SSE stream
+    rx
+}
+
+/// Record the client's preferred locale from `Accept-Language`, keeping
+/// the previous one if the header is missing or unusable.
+pub fn set_locale(session: &mut Session, headers: &HeaderMap) {
+    if let Some(locale) = headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(preferred_locale)
+    {
+        session.locale = Some(locale);
+    }
+}
+
+/// First language tag from an `Accept-Language` value, e.g. `en-GB` from
+/// `en-GB,en;q=0.9`. It ends up in the prompt, so only tag characters pass.
+fn preferred_locale(value: &str) -> Option<String> {
+    let tag = value.split(',').next()?.split(';').next()?.trim();
+    let valid = !tag.is_empty()
+        && tag.len() <= 35
+        && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    valid.then(|| tag.to_string())
+}
+
+/// Convert chat events to an SSE response.
+pub fn event_stream(
+    rx: mpsc::Receiver<StreamEvent>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     let stream = ReceiverStream::new(rx).map(|event| {
         Ok(Event::default().data(serde_json::to_string(&event).unwrap()))
     });
 
-    Ok(Sse::new(stream).keep_alive(
+    Sse::new(stream).keep_alive(
         KeepAlive::new()
             .interval(Duration::from_secs(15))
             .text("keep-alive"),
-    ))
+    )
 }
 
 use futures::StreamExt;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Path;
+    use axum::http::StatusCode;
+    use axum_extra::extract::cookie::Cookie;
+    use serde_json::json;
+
+    use crate::ollama::{stub_server, OllamaClient, StubReply};
+    use crate::routes::conversations::{delete_conversation, OWNER_COOKIE};
+    use crate::routes::test_state;
+
+    #[tokio::test]
+    async fn test_browser_cannot_take_over_api_session() {
+        let state = test_state();
+        let api = manager::get_or_create_session(&state.sessions, Uuid::new_v4(), None).id;
+        let turn = state.turns.try_start(api).unwrap();
+        let cookies = CookieJar::new()
+            .add(Cookie::new(OWNER_COOKIE, Uuid::new_v4().to_string()))
+            .add(Cookie::new(CONVERSATION_COOKIE, api.to_string()));
+
+        let result = stop(cookies.clone(), State(state.clone())).await;
+        assert!(matches!(result, Err(AppError::SessionNotFound)));
+        assert!(!turn.token.is_cancelled());
+
+        drop(turn);
+        let input = ChatInput {
+            message: "Hi".to_string(),
+        };
+        let result = chat(cookies, State(state.clone()), HeaderMap::new(), Form(input)).await;
+        assert!(matches!(result, Err(AppError::SessionNotFound)));
+        let session = manager::find_session(&state.sessions, api).unwrap();
+        assert_eq!(session.owner, None);
+        assert!(session.messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stopping_saves_partial_reply() {
+        let mut state = test_state();
+        let url = stub_server(vec![StubReply {
+            messages: vec![json!({ "role": "assistant", "content": "Ferrets sleep" })],
+            finished: false,
+        }])
+        .await;
+        state.ollama = OllamaClient::new(&url, "test-model");
+
+        let session = manager::create_conversation(&state.sessions, Uuid::new_v4());
+        let id = session.id;
+        let turn = state.turns.try_start(id).unwrap();
+        let message = "How long do ferrets sleep?".to_string();
+        let mut rx = spawn_chat(state.clone(), turn, session, message, Default::default());
+
+        // Stop once the reply has started, while the model is still going
+        loop {
+            if let StreamEvent::Chunk { content } = rx.recv().await.expect("stream open") {
+                assert_eq!(content, "Ferrets sleep");
+                break;
+            }
+        }
+        assert!(state.turns.stop(id));
+
+        let mut rest = Vec::new();
+        while let Some(event) = rx.recv().await {
+            rest.push(event);
+        }
+        assert!(matches!(rest[..], [StreamEvent::Interrupted, StreamEvent::Done]));
+
+        // Saved by the time the stream closes, with the turn released
+        let saved = manager::find_session(&state.sessions, id).unwrap();
+        assert_eq!(saved.messages.len(), 2);
+        assert_eq!(saved.messages[1].content, "Ferrets sleep");
+        assert!(saved.messages[1].interrupted);
+        assert!(!state.turns.stop(id));
+        assert!(state.turns.try_start(id).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_deleting_stops_the_turn() {
+        let mut state = test_state();
+        let url = stub_server(vec![StubReply {
+            messages: vec![json!({ "role": "assistant", "content": "Ferrets sleep" })],
+            finished: false,
+        }])
+        .await;
+        state.ollama = OllamaClient::new(&url, "test-model");
+
+        let owner = Uuid::new_v4();
+        let session = manager::create_conversation(&state.sessions, owner);
+        let id = session.id;
+        let turn = state.turns.try_start(id).unwrap();
+        let message = "How long do ferrets sleep?".to_string();
+        let mut rx = spawn_chat(state.clone(), turn, session, message, Default::default());
+
+        loop {
+            if let StreamEvent::Chunk { .. } = rx.recv().await.expect("stream open") {
+                break;
+            }
+        }
+        let cookies = CookieJar::new().add(Cookie::new(OWNER_COOKIE, owner.to_string()));
+        let deleted = delete_conversation(cookies, State(state.clone()), Path(id)).await;
+        assert!(matches!(deleted, Ok((_, StatusCode::NO_CONTENT))));
+
+        let mut rest = Vec::new();
+        while let Some(event) = rx.recv().await {
+            rest.push(event);
+        }
+        assert!(matches!(rest[..], [StreamEvent::Interrupted, StreamEvent::Done]));
+
+        // The turn ended without bringing the conversation back
+        assert!(manager::find_session(&state.sessions, id).is_none());
+        assert!(state.turns.try_start(id).is_ok());
+    }
+}
@@ -4,23 +4,26 @@ use axum::{
     response::IntoResponse,
 };
 use axum_extra::extract::CookieJar;
-use uuid::Uuid;
 
+use crate::error::AppError;
 use crate::session::manager;
 use crate::AppState;
 
+use super::conversations::current_conversation;
+
+/// Clear the open conversation. Refused while a reply is being generated,
+/// since saving the reply would bring the history back.
 pub async fn clear(
     cookies: CookieJar,
     State(state): State<AppState>,
-) -> impl IntoResponse {
-    if let Some(cookie) = cookies.get("session_id") {
-        if let Ok(session_id) = Uuid::parse_str(cookie.value()) {
-            manager::clear_session(&state.sessions, session_id);
-        }
+) -> Result<impl IntoResponse, AppError> {
+    if let Ok(session) = current_conversation(&state, &cookies) {
+        let _turn = state.turns.try_start(session.id)?;
+        manager::clear_session(&state.sessions, session.id);
     }
 
     let mut headers = HeaderMap::new();
     headers.insert("HX-Trigger", HeaderValue::from_static("chat-cleared"));
 
-    (headers, "OK")
+    Ok((headers, "OK"))
 }
@@ -0,0 +1,345 @@
+//! Conversations for the web interface.
+//!
+//! The `owner_id` cookie identifies the browser user, who may have many
+//! conversations; `session_id` points at the one currently open.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, Response},
+    Form, Json,
+};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::chat::history::visible_history;
+use crate::chat::title::MAX_TITLE_CHARS;
+use crate::error::AppError;
+use crate::session::{manager, reaper, ChatMessage, Session, SessionInfo};
+use crate::AppState;
+
+use super::export::{export_response, import, ExportQuery};
+
+pub const OWNER_COOKIE: &str = "owner_id";
+pub const CONVERSATION_COOKIE: &str = "session_id";
+
+#[derive(Serialize)]
+pub struct ConversationList {
+    current: Option<Uuid>,
+    conversations: Vec<SessionInfo>,
+}
+
+#[derive(Serialize)]
+pub struct Conversation {
+    #[serde(flatten)]
+    info: SessionInfo,
+    messages: Vec<ChatMessage>,
+}
+
+#[derive(Serialize)]
+pub struct Imported {
+    conversations: Vec<SessionInfo>,
+}
+
+#[derive(Deserialize)]
+pub struct RenameInput {
+    #[serde(default)]
+    pub title: String,
+}
+
+pub fn owner_id(cookies: &CookieJar) -> Option<Uuid> {
+    cookies
+        .get(OWNER_COOKIE)
+        .and_then(|c| Uuid::parse_str(c.value()).ok())
+}
+
+/// Long-lived so conversations survive the browser being closed.
+pub fn owner_cookie(owner: Uuid) -> Cookie<'static> {
+    Cookie::build((OWNER_COOKIE, owner.to_string()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .permanent()
+        .build()
+}
+
+pub fn conversation_cookie(id: Uuid) -> Cookie<'static> {
+    Cookie::build((CONVERSATION_COOKIE, id.to_string()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .permanent()
+        .build()
+}
+
+/// The conversation to open for a page load: the current one if it still
+/// exists and belongs to `owner`, else their most recent, else a new one.
+/// Conversations saved before owners existed are adopted.
+pub fn open_conversation(state: &AppState, cookies: &CookieJar, owner: Uuid) -> Session {
+    let current = cookies
+        .get(CONVERSATION_COOKIE)
+        .and_then(|c| Uuid::parse_str(c.value()).ok())
+        .and_then(|id| manager::find_session(&state.sessions, id))
+        .filter(|s| s.owner == Some(owner) || s.legacy);
+
+    let current = current.and_then(|session| match session.legacy {
+        false => Some(session),
+        true => manager::adopt_conversation(&state.sessions, session.id, owner),
+    });
+    if let Some(session) = current {
+        return session;
+    }
+
+    let latest = manager::list_conversations(&state.sessions, owner)
+        .first()
+        .and_then(|info| manager::find_session(&state.sessions, info.id));
+
+    latest.unwrap_or_else(|| new_conversation(state, owner))
+}
+
+pub async fn list_conversations(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+) -> Result<Json<ConversationList>, AppError> {
+    let owner = owner_id(&cookies).ok_or(AppError::SessionNotFound)?;
+
+    Ok(Json(ConversationList {
+        current: cookies
+            .get(CONVERSATION_COOKIE)
+            .and_then(|c| Uuid::parse_str(c.value()).ok()),
+        conversations: manager::list_conversations(&state.sessions, owner),
+    }))
+}
+
+/// Start a new conversation and make it current.
+pub async fn create_conversation(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, CookieJar, Json<SessionInfo>), AppError> {
+    let owner = owner_id(&cookies).ok_or(AppError::SessionNotFound)?;
+    let session = new_conversation(&state, owner);
+
+    Ok((
+        StatusCode::CREATED,
+        cookies.add(conversation_cookie(session.id)),
+        Json(session.info()),
+    ))
+}
+
+/// A conversation's history, for showing it after switching.
+pub async fn get_conversation(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Conversation>, AppError> {
+    let session = owned(&state, &cookies, id)?;
+
+    Ok(Json(Conversation {
+        info: session.info(),
+        messages: session.messages,
+    }))
+}
+
+/// A conversation's history as HTML, for swapping into the chat pane.
+pub async fn conversation_messages(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Html<String>, AppError> {
+    let session = owned(&state, &cookies, id)?;
+
+    state
+        .templates
+        .render("messages.html", json!({ "messages": visible_history(&session, &state.tools.registry()) }))
+        .map(Html)
+}
+
+/// Make a conversation current.
+pub async fn select_conversation(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<(CookieJar, Json<SessionInfo>), AppError> {
+    let session = owned(&state, &cookies, id)?;
+
+    Ok((cookies.add(conversation_cookie(id)), Json(session.info())))
+}
+
+/// Download a conversation as Markdown or JSON.
+pub async fn export_conversation(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    Ok(export_response(&state, owned(&state, &cookies, id)?, query.format))
+}
+
+/// Import conversations from an uploaded export into this browser's list.
+pub async fn import_conversations(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<Imported>), AppError> {
+    let owner = owner_id(&cookies).ok_or(AppError::SessionNotFound)?;
+    let conversations = import(&state, &body, Some(owner))?;
+
+    Ok((StatusCode::CREATED, Json(Imported { conversations })))
+}
+
+/// Rename a conversation; an empty title lets one be generated again.
+pub async fn rename_conversation(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Form(input): Form<RenameInput>,
+) -> Result<Json<SessionInfo>, AppError> {
+    owned(&state, &cookies, id)?;
+
+    let title = input.title.trim();
+    if title.chars().count() > MAX_TITLE_CHARS {
+        return Err(AppError::InvalidRequest(format!(
+            "Titles are limited to {} characters",
+            MAX_TITLE_CHARS
+        )));
+    }
+    let title = (!title.is_empty()).then(|| title.to_string());
+
+    manager::set_session_title(&state.sessions, id, title)
+        .map(|session| Json(session.info()))
+        .ok_or(AppError::SessionNotFound)
+}
+
+/// Delete a conversation. If it was current, none is current until the
+/// browser picks another.
+pub async fn delete_conversation(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<(CookieJar, StatusCode), AppError> {
+    owned(&state, &cookies, id)?;
+
+    // A reply still being generated would have nowhere to go
+    state.turns.stop(id);
+    if !manager::delete_session(&state.sessions, id) {
+        return Err(AppError::SessionNotFound);
+    }
+
+    let was_current = cookies
+        .get(CONVERSATION_COOKIE)
+        .is_some_and(|c| c.value() == id.to_string());
+    let cookies = if was_current {
+        cookies.remove(Cookie::build(CONVERSATION_COOKIE).path("/"))
+    } else {
+        cookies
+    };
+
+    Ok((cookies, StatusCode::NO_CONTENT))
+}
+
+fn new_conversation(state: &AppState, owner: Uuid) -> Session {
+    let session = manager::create_conversation(&state.sessions, owner);
+    reaper::enforce_capacity(
+        &state.sessions,
+        state.config.max_sessions,
+        &state.eviction_stats,
+    );
+    session
+}
+
+/// The browser's open conversation, if it belongs to the browser's owner.
+pub fn current_conversation(state: &AppState, cookies: &CookieJar) -> Result<Session, AppError> {
+    let id = cookies
+        .get(CONVERSATION_COOKIE)
+        .and_then(|c| Uuid::parse_str(c.value()).ok())
+        .ok_or(AppError::SessionNotFound)?;
+    owned(state, cookies, id)
+}
+
+/// Look up a conversation belonging to the browser's owner. Other owners'
+/// conversations are reported as missing.
+fn owned(state: &AppState, cookies: &CookieJar, id: Uuid) -> Result<Session, AppError> {
+    let owner = owner_id(cookies).ok_or(AppError::SessionNotFound)?;
+
+    manager::find_session(&state.sessions, id)
+        .filter(|s| s.owner == Some(owner))
+        .ok_or(AppError::SessionNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request};
+    use axum::routing::{patch, post};
+    use axum::Router;
+    use tower::ServiceExt;
+
+    use crate::routes::test_state;
+
+    async fn call(state: &AppState, method: &str, uri: &str, owner: Uuid, body: &str) -> StatusCode {
+        let router = Router::new()
+            .route("/conversations/:id", patch(rename_conversation).delete(delete_conversation))
+            .route("/conversations/:id/select", post(select_conversation))
+            .with_state(state.clone());
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::COOKIE, format!("{}={}", OWNER_COOKIE, owner))
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_other_owners_conversations_are_out_of_reach() {
+        let state = test_state();
+        let (owner, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let id = manager::create_conversation(&state.sessions, owner).id;
+        let uri = format!("/conversations/{}", id);
+
+        let select = format!("{}/select", uri);
+        assert_eq!(call(&state, "POST", &select, other, "").await, StatusCode::NOT_FOUND);
+        assert_eq!(call(&state, "PATCH", &uri, other, "title=Mine").await, StatusCode::NOT_FOUND);
+        assert_eq!(call(&state, "DELETE", &uri, other, "").await, StatusCode::NOT_FOUND);
+
+        let session = manager::find_session(&state.sessions, id).expect("not deleted");
+        assert_eq!(session.title, None);
+
+        // The owner can do all three
+        assert_eq!(call(&state, "POST", &select, owner, "").await, StatusCode::OK);
+        assert_eq!(call(&state, "PATCH", &uri, owner, "title=Mine").await, StatusCode::OK);
+        let session = manager::find_session(&state.sessions, id).unwrap();
+        assert_eq!(session.title.as_deref(), Some("Mine"));
+        assert_eq!(call(&state, "DELETE", &uri, owner, "").await, StatusCode::NO_CONTENT);
+        assert!(manager::find_session(&state.sessions, id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_only_legacy_conversations_are_adopted() {
+        let state = test_state();
+        let owner = Uuid::new_v4();
+        let api = manager::get_or_create_session(&state.sessions, Uuid::new_v4(), None).id;
+        let mut legacy = Session::new(Uuid::new_v4());
+        legacy.legacy = true;
+        state.sessions.put(&mut legacy).unwrap();
+        let naming = |id| CookieJar::new().add(conversation_cookie(id));
+
+        // An API session named by the cookie is left to the API
+        let opened = open_conversation(&state, &naming(api), owner);
+        assert_ne!(opened.id, api);
+        assert_eq!(manager::find_session(&state.sessions, api).unwrap().owner, None);
+
+        let opened = open_conversation(&state, &naming(legacy.id), owner);
+        assert_eq!(opened.id, legacy.id);
+        assert_eq!(opened.owner, Some(owner));
+        assert!(!opened.legacy);
+    }
+}
@@ -0,0 +1,94 @@
+//! Export and import shared by the API and the web interface.
+
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::session::export::{parse_import, to_markdown, ExportFormat, SessionExport};
+use crate::session::{manager, reaper, Session, SessionInfo};
+use crate::AppState;
+
+/// Uploads can be a whole ChatGPT history, well past axum's 2 MB default.
+pub const MAX_IMPORT_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default = "default_format")]
+    pub format: ExportFormat,
+}
+
+fn default_format() -> ExportFormat {
+    ExportFormat::Json
+}
+
+/// The session as a download in the requested format.
+pub fn export_response(state: &AppState, session: Session, format: ExportFormat) -> Response {
+    let filename = format!("{}.{}", file_stem(&session), format.extension());
+    let body = match format {
+        ExportFormat::Markdown => to_markdown(&session, &state.tools.registry()),
+        ExportFormat::Json => serde_json::to_string_pretty(&SessionExport::new(session))
+            .unwrap_or_else(|_| "{}".to_string()),
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// Parse and store an upload, refusing more sessions than the server keeps.
+pub fn import(
+    state: &AppState,
+    body: &[u8],
+    owner: Option<Uuid>,
+) -> Result<Vec<SessionInfo>, AppError> {
+    let value = serde_json::from_slice(body)
+        .map_err(|e| AppError::InvalidRequest(format!("Import is not valid JSON: {}", e)))?;
+    let sessions = parse_import(value).map_err(AppError::InvalidRequest)?;
+
+    if sessions.len() > state.config.max_sessions {
+        return Err(AppError::InvalidRequest(format!(
+            "Import has {} conversations; this server keeps at most {}",
+            sessions.len(),
+            state.config.max_sessions
+        )));
+    }
+
+    let imported = manager::import_sessions(&state.sessions, sessions, owner);
+    reaper::enforce_capacity(
+        &state.sessions,
+        state.config.max_sessions,
+        &state.eviction_stats,
+    );
+    Ok(imported)
+}
+
+/// `ferret-<title slug>`, or `ferret-<id>` for untitled sessions.
+fn file_stem(session: &Session) -> String {
+    let slug = session
+        .title
+        .as_deref()
+        .unwrap_or_default()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() {
+        format!("ferret-{}", session.id)
+    } else {
+        format!("ferret-{}", slug)
+    }
+}
@@ -2,13 +2,24 @@ use axum::{extract::State, Json};
 use serde::Serialize;
 
 use crate::session::manager;
+use crate::tools::mcp::ServerHealth;
 use crate::AppState;
 
 #[derive(Serialize)]
 pub struct HealthResponse {
     status: String,
     ollama: String,
+    search: String,
     sessions: usize,
+    evictions: EvictionCounts,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    mcp: Vec<ServerHealth>,
+}
+
+#[derive(Serialize)]
+pub struct EvictionCounts {
+    expired: u64,
+    capacity: u64,
 }
 
 pub async fn health(State(state): State<AppState>) -> Json<HealthResponse> {
@@ -21,6 +32,12 @@ pub async fn health(State(state): State<AppState>) -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "ok".to_string(),
         ollama: ollama_status.to_string(),
+        search: state.tools.search_provider().unwrap_or("disabled").to_string(),
         sessions: manager::session_count(&state.sessions),
+        evictions: EvictionCounts {
+            expired: state.eviction_stats.expired(),
+            capacity: state.eviction_stats.capacity(),
+        },
+        mcp: state.tools.mcp_health(),
     })
 }
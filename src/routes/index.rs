@@ -2,36 +2,34 @@ use axum::{
     extract::State,
     response::{Html, IntoResponse},
 };
-use axum_extra::extract::{
-    cookie::{Cookie, SameSite},
-    CookieJar,
-};
+use axum_extra::extract::CookieJar;
+use serde_json::json;
 use uuid::Uuid;
 
-use crate::session::{manager, Session};
+use crate::chat::history::visible_history;
+use crate::error::AppError;
 use crate::AppState;
 
-const INDEX_HTML: &str = include_str!("../../templates/index.html");
+use super::conversations::{conversation_cookie, open_conversation, owner_cookie, owner_id};
 
-pub async fn index(cookies: CookieJar, State(state): State<AppState>) -> impl IntoResponse {
-    let session_id = cookies
-        .get("session_id")
-        .and_then(|c| Uuid::parse_str(c.value()).ok())
-        .unwrap_or_else(|| {
-            let id = Uuid::new_v4();
-            state.sessions.insert(id, Session::new(id));
-            id
-        });
+pub async fn index(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let owner = owner_id(&cookies).unwrap_or_else(Uuid::new_v4);
 
-    // Ensure session exists
-    manager::get_or_create_session(&state.sessions, session_id);
+    // Ensure a conversation is open
+    let session = open_conversation(&state, &cookies, owner);
 
-    let cookie = Cookie::build(("session_id", session_id.to_string()))
-        .path("/")
-        .http_only(true)
-        .same_site(SameSite::Strict);
+    // Render what the user saw before reloading
+    let page = state.templates.render(
+        "index.html",
+        json!({ "messages": visible_history(&session, &state.tools.registry()) }),
+    )?;
 
-    let cookies = cookies.add(cookie);
+    let cookies = cookies
+        .add(owner_cookie(owner))
+        .add(conversation_cookie(session.id));
 
-    (cookies, Html(INDEX_HTML))
+    Ok((cookies, Html(page)))
 }
@@ -1,9 +1,36 @@
+pub mod api;
 pub mod chat;
 pub mod clear;
+pub mod conversations;
+pub mod export;
 pub mod health;
 pub mod index;
+pub mod model;
+pub mod openai;
+pub mod persona;
 
 pub use chat::chat;
 pub use clear::clear;
 pub use health::health;
 pub use index::index;
+
+/// App state for route tests: in-memory sessions, the built-in persona, no
+/// tools, and an Ollama URL that nothing answers.
+#[cfg(test)]
+pub fn test_state() -> crate::AppState {
+    use std::sync::Arc;
+
+    crate::AppState {
+        sessions: crate::session::manager::create_session_manager(),
+        eviction_stats: Default::default(),
+        ollama: crate::ollama::OllamaClient::new("http://127.0.0.1:9", "test-model"),
+        tools: crate::tools::ToolExecutor::new(Vec::new(), 1),
+        personas: Arc::new(
+            crate::chat::persona::Personas::load(std::path::Path::new("no-personas"), "ferret")
+                .unwrap(),
+        ),
+        templates: Arc::new(crate::templates::Templates::new().unwrap()),
+        turns: Default::default(),
+        config: crate::config::AppConfig::from_env().unwrap(),
+    }
+}
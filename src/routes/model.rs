@@ -0,0 +1,57 @@
+use axum::{extract::State, Form, Json};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::chat::handler::session_model;
+use crate::error::AppError;
+use crate::session::{manager, Session};
+use crate::AppState;
+
+use super::api::validate_model;
+use super::conversations::current_conversation;
+
+#[derive(Deserialize)]
+pub struct ModelInput {
+    #[serde(default)]
+    pub model: String,
+}
+
+#[derive(Serialize)]
+pub struct CurrentModel {
+    /// Model the session runs on
+    model: String,
+    /// Whether that is a default (persona's or server's) rather than a choice
+    default: bool,
+}
+
+/// The model used by the browser session.
+pub async fn current_model(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+) -> Result<Json<CurrentModel>, AppError> {
+    let session = current_conversation(&state, &cookies)?;
+
+    Ok(Json(describe(&state, &session)))
+}
+
+/// Switch the browser session's model, keeping its history.
+pub async fn set_model(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+    Form(input): Form<ModelInput>,
+) -> Result<Json<CurrentModel>, AppError> {
+    let session = current_conversation(&state, &cookies)?;
+    let model = validate_model(&state, Some(&input.model)).await?;
+
+    let session = manager::set_session_model(&state.sessions, session.id, model)
+        .ok_or(AppError::SessionNotFound)?;
+
+    Ok(Json(describe(&state, &session)))
+}
+
+fn describe(state: &AppState, session: &Session) -> CurrentModel {
+    CurrentModel {
+        default: session.model.is_none(),
+        model: session_model(state, session),
+    }
+}
@@ -0,0 +1,587 @@
+//! OpenAI-compatible API so existing SDKs and tools can use Ferret.
+//!
+//! Requests are stateless: the supplied messages become a throwaway session
+//! and the last user message is run through the normal tool loop. Tool
+//! activity is reported as a `ferret_tool` field on streamed chunks and as a
+//! `sources` list on the final chunk or completion object.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use chrono::Utc;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{convert::Infallible, time::Duration};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::chat::context::estimate_tokens;
+use crate::chat::handler::session_model;
+use crate::chat::{handle_chat, StreamEvent};
+use crate::ollama::types::{GenerationOptions, OllamaOptions};
+use crate::session::{ChatMessage, Role, Session};
+use crate::AppState;
+
+use super::api::validate_model;
+use super::chat::set_locale;
+
+#[derive(Deserialize)]
+pub struct ChatCompletionRequest {
+    #[serde(default)]
+    pub model: Option<String>,
+    pub messages: Vec<RequestMessage>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+    #[serde(default)]
+    pub stop: Option<StopSequences>,
+    #[serde(default, alias = "max_completion_tokens")]
+    pub max_tokens: Option<i32>,
+    /// Ollama options not covered by the OpenAI fields, such as `num_ctx`
+    #[serde(default)]
+    pub options: GenerationOptions,
+    /// Ferret persona id; the server default when omitted
+    #[serde(default)]
+    pub persona: Option<String>,
+}
+
+/// `stop` may be a single string or a list.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum StopSequences {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl ChatCompletionRequest {
+    /// Ollama options for this request; the OpenAI fields take precedence
+    /// over the same settings in `options`.
+    fn generation_options(&self) -> GenerationOptions {
+        let stop = self.stop.as_ref().map(|stop| match stop {
+            StopSequences::One(s) => vec![s.clone()],
+            StopSequences::Many(list) => list.clone(),
+        });
+
+        self.options.merged(&GenerationOptions {
+            options: OllamaOptions {
+                temperature: self.temperature,
+                top_p: self.top_p,
+                seed: self.seed,
+                stop,
+                num_predict: self.max_tokens,
+                ..OllamaOptions::default()
+            },
+            keep_alive: None,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RequestMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: Option<MessageContent>,
+}
+
+/// Message content is either a string or a list of typed parts.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Deserialize)]
+pub struct ContentPart {
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+impl MessageContent {
+    fn into_text(self) -> String {
+        match self {
+            MessageContent::Text(text) => text,
+            // Only text parts are supported; images and the like are dropped
+            MessageContent::Parts(parts) => parts
+                .into_iter()
+                .filter_map(|p| p.text)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ChatCompletion {
+    id: String,
+    object: &'static str,
+    created: i64,
+    model: String,
+    choices: Vec<CompletionChoice>,
+    usage: Usage,
+    sources: Vec<Source>,
+}
+
+#[derive(Serialize)]
+struct CompletionChoice {
+    index: u32,
+    message: ResponseMessage,
+    finish_reason: &'static str,
+}
+
+#[derive(Serialize)]
+struct ResponseMessage {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct Usage {
+    prompt_tokens: usize,
+    completion_tokens: usize,
+    total_tokens: usize,
+}
+
+#[derive(Serialize)]
+struct ChatCompletionChunk {
+    id: String,
+    object: &'static str,
+    created: i64,
+    model: String,
+    choices: Vec<ChunkChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ferret_tool: Option<ToolActivity>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sources: Vec<Source>,
+}
+
+#[derive(Serialize)]
+struct ChunkChoice {
+    index: u32,
+    delta: Delta,
+    finish_reason: Option<&'static str>,
+}
+
+#[derive(Serialize, Default)]
+struct Delta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+}
+
+/// A tool starting or finishing, attached to a streamed chunk.
+#[derive(Serialize)]
+struct ToolActivity {
+    id: String,
+    tool: String,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    success: Option<bool>,
+}
+
+/// A tool call made while answering: the search query or fetched URL.
+#[derive(Serialize, Clone)]
+struct Source {
+    id: String,
+    tool: String,
+    query: String,
+    success: bool,
+}
+
+#[derive(Serialize)]
+pub struct ModelList {
+    object: &'static str,
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Serialize)]
+pub struct ModelEntry {
+    id: String,
+    object: &'static str,
+    created: i64,
+    owned_by: &'static str,
+}
+
+pub async fn models(State(state): State<AppState>) -> Response {
+    let models = match state.ollama.list_models().await {
+        Ok(models) => models,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, &e.to_string()),
+    };
+
+    Json(ModelList {
+        object: "list",
+        data: models
+            .into_iter()
+            .map(|model| ModelEntry {
+                id: model.name,
+                object: "model",
+                created: 0,
+                owned_by: "ferret",
+            })
+            .collect(),
+    })
+    .into_response()
+}
+
+pub async fn chat_completions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ChatCompletionRequest>,
+) -> Response {
+    let stream = request.stream;
+    let options = request.generation_options();
+    let (mut session, user_message) = match session_from_messages(request.messages) {
+        Ok(parts) => parts,
+        Err(message) => return error_response(StatusCode::BAD_REQUEST, &message),
+    };
+
+    session.model = match validate_model(&state, request.model.as_deref()).await {
+        Ok(model) => model,
+        Err(e) => return error_response(e.status_code(), &e.to_string()),
+    };
+    session.persona = match state.personas.validate(request.persona.as_deref()) {
+        Ok(persona) => persona,
+        Err(e) => return error_response(e.status_code(), &e.to_string()),
+    };
+    set_locale(&mut session, &headers);
+    let model = session_model(&state, &session);
+    debug!("OpenAI-compatible request using {}", model);
+
+    let prompt_tokens = session.messages.iter().map(estimate_tokens).sum::<usize>()
+        + estimate_tokens(&ChatMessage::new(Role::User, user_message.as_str()));
+
+    let (tx, rx) = mpsc::channel::<StreamEvent>(100);
+    // Nothing can stop these turns by id; they end when the client leaves
+    let cancel = CancellationToken::new();
+    let mut completion = CompletionState::new(model);
+
+    if stream {
+        let (event_tx, event_rx) = mpsc::channel::<Event>(100);
+
+        tokio::spawn(async move {
+            let chat = handle_chat(
+                &state,
+                &mut session,
+                user_message,
+                &options,
+                tx,
+                &cancel,
+            );
+            let forward = async {
+                let mut rx = rx;
+                while let Some(event) = rx.recv().await {
+                    let data = completion.stream_event(event);
+                    if event_tx.send(Event::default().data(data)).await.is_err() {
+                        return;
+                    }
+                }
+                let _ = event_tx.send(Event::default().data("[DONE]")).await;
+            };
+            tokio::join!(chat, forward);
+        });
+
+        let events = ReceiverStream::new(event_rx).map(Ok::<_, Infallible>);
+        return Sse::new(events)
+            .keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+            .into_response();
+    }
+
+    let chat = handle_chat(
+        &state,
+        &mut session,
+        user_message,
+        &options,
+        tx,
+        &cancel,
+    );
+    let collect = async {
+        let mut rx = rx;
+        while let Some(event) = rx.recv().await {
+            completion.collect_event(event);
+        }
+    };
+    tokio::join!(chat, collect);
+
+    if let Some(error) = completion.error.filter(|_| completion.content.is_empty()) {
+        return error_response(StatusCode::BAD_GATEWAY, &error);
+    }
+
+    let completion_tokens = estimate_tokens(&ChatMessage::new(
+        Role::Assistant,
+        completion.content.as_str(),
+    ));
+
+    Json(ChatCompletion {
+        id: completion.id,
+        object: "chat.completion",
+        created: completion.created,
+        model: completion.model,
+        choices: vec![CompletionChoice {
+            index: 0,
+            message: ResponseMessage {
+                role: "assistant",
+                content: completion.content,
+            },
+            finish_reason: "stop",
+        }],
+        usage: Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        },
+        sources: completion.sources,
+    })
+    .into_response()
+}
+
+/// Split request messages into prior history and the new user message.
+fn session_from_messages(
+    messages: Vec<RequestMessage>,
+) -> Result<(Session, String), String> {
+    let mut history = Vec::with_capacity(messages.len());
+
+    for message in messages {
+        let role = match message.role.as_str() {
+            "system" | "developer" => Role::System,
+            "user" => Role::User,
+            "assistant" => Role::Assistant,
+            "tool" => Role::Tool,
+            other => return Err(format!("Unsupported message role: {}", other)),
+        };
+        let content = message.content.map(MessageContent::into_text).unwrap_or_default();
+        history.push(ChatMessage::new(role, content));
+    }
+
+    let last = history
+        .pop()
+        .ok_or_else(|| "messages must not be empty".to_string())?;
+    if last.role != Role::User || last.content.trim().is_empty() {
+        return Err("The last message must be a non-empty user message".to_string());
+    }
+
+    let mut session = Session::new(Uuid::new_v4());
+    session.messages = history;
+    Ok((session, last.content))
+}
+
+/// Accumulates one completion from the chat handler's events.
+struct CompletionState {
+    id: String,
+    created: i64,
+    model: String,
+    content: String,
+    sources: Vec<Source>,
+    error: Option<String>,
+    sent_role: bool,
+}
+
+impl CompletionState {
+    fn new(model: String) -> Self {
+        Self {
+            id: format!("chatcmpl-{}", Uuid::new_v4().simple()),
+            created: Utc::now().timestamp(),
+            model,
+            content: String::new(),
+            sources: Vec::new(),
+            error: None,
+            sent_role: false,
+        }
+    }
+
+    fn collect_event(&mut self, event: StreamEvent) {
+        match event {
+            StreamEvent::Chunk { content } => self.content.push_str(&content),
+            StreamEvent::ToolStart { id, tool, query } => self.sources.push(Source {
+                id,
+                tool,
+                query,
+                success: false,
+            }),
+            StreamEvent::ToolEnd { id, success, .. } => {
+                if let Some(source) = self.sources.iter_mut().find(|s| s.id == id) {
+                    source.success = success;
+                }
+            }
+            StreamEvent::Error { message } => self.error = Some(message),
+            StreamEvent::Interrupted | StreamEvent::Done => {}
+        }
+    }
+
+    /// Translate an event into an SSE data payload.
+    fn stream_event(&mut self, event: StreamEvent) -> String {
+        let chunk = match &event {
+            StreamEvent::Chunk { content } => self.chunk(
+                Delta {
+                    role: (!self.sent_role).then_some("assistant"),
+                    content: Some(content.clone()),
+                },
+                None,
+                None,
+            ),
+            StreamEvent::ToolStart { id, tool, query } => self.chunk(
+                Delta::default(),
+                None,
+                Some(ToolActivity {
+                    id: id.clone(),
+                    tool: tool.clone(),
+                    status: "started",
+                    query: Some(query.clone()),
+                    success: None,
+                }),
+            ),
+            StreamEvent::ToolEnd { id, tool, success } => self.chunk(
+                Delta::default(),
+                None,
+                Some(ToolActivity {
+                    id: id.clone(),
+                    tool: tool.clone(),
+                    status: "finished",
+                    query: None,
+                    success: Some(*success),
+                }),
+            ),
+            StreamEvent::Error { message } => {
+                let payload =
+                    json!({ "error": { "message": message, "type": "server_error" } });
+                self.collect_event(event);
+                return payload.to_string();
+            }
+            // OpenAI has no finish reason for this; `Done` follows
+            StreamEvent::Interrupted => self.chunk(Delta::default(), None, None),
+            StreamEvent::Done => {
+                let mut chunk = self.chunk(Delta::default(), Some("stop"), None);
+                chunk.sources = self.sources.clone();
+                chunk
+            }
+        };
+
+        if matches!(event, StreamEvent::Chunk { .. }) {
+            self.sent_role = true;
+        }
+        self.collect_event(event);
+        serde_json::to_string(&chunk).unwrap_or_default()
+    }
+
+    fn chunk(
+        &self,
+        delta: Delta,
+        finish_reason: Option<&'static str>,
+        ferret_tool: Option<ToolActivity>,
+    ) -> ChatCompletionChunk {
+        ChatCompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk",
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![ChunkChoice {
+                index: 0,
+                delta,
+                finish_reason,
+            }],
+            ferret_tool,
+            sources: Vec::new(),
+        }
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    let kind = if status.is_client_error() {
+        "invalid_request_error"
+    } else {
+        "server_error"
+    };
+    (
+        status,
+        Json(json!({ "error": { "message": message, "type": kind } })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: serde_json::Value) -> RequestMessage {
+        serde_json::from_value(json!({ "role": role, "content": content })).unwrap()
+    }
+
+    #[test]
+    fn test_session_from_messages() {
+        let (session, user_message) = session_from_messages(vec![
+            message("system", json!("Be brief.")),
+            message("user", json!("Hi")),
+            message("assistant", json!("Hello!")),
+            message("user", json!([{ "type": "text", "text": "What's new in Rust?" }])),
+        ])
+        .unwrap();
+
+        assert_eq!(user_message, "What's new in Rust?");
+        assert_eq!(session.messages.len(), 3);
+        assert_eq!(session.messages[0].role, Role::System);
+
+        assert!(session_from_messages(vec![]).is_err());
+        assert!(session_from_messages(vec![message("assistant", json!("Hi"))]).is_err());
+        assert!(session_from_messages(vec![message("narrator", json!("Hi"))]).is_err());
+    }
+
+    #[test]
+    fn test_openai_fields_become_options() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "messages": [],
+            "temperature": 0.2,
+            "stop": "END",
+            "max_tokens": 256,
+            "options": { "temperature": 0.9, "num_ctx": 8192 },
+        }))
+        .unwrap();
+
+        let options = request.generation_options().options;
+        assert_eq!(options.temperature, Some(0.2));
+        assert_eq!(options.stop, Some(vec!["END".to_string()]));
+        assert_eq!(options.num_predict, Some(256));
+        assert_eq!(options.num_ctx, Some(8192));
+        assert_eq!(options.top_p, None);
+    }
+
+    #[test]
+    fn test_stream_events_become_chunks() {
+        let mut completion = CompletionState::new("qwen2.5:7b".to_string());
+
+        let first: serde_json::Value =
+            serde_json::from_str(&completion.stream_event(StreamEvent::chunk("Hi"))).unwrap();
+        assert_eq!(first["object"], "chat.completion.chunk");
+        assert_eq!(first["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(first["choices"][0]["delta"]["content"], "Hi");
+
+        completion.stream_event(StreamEvent::tool_start("call_0_0", "search", "rust"));
+        let end: serde_json::Value = serde_json::from_str(
+            &completion.stream_event(StreamEvent::tool_end("call_0_0", "search", true)),
+        )
+        .unwrap();
+        assert_eq!(end["ferret_tool"]["status"], "finished");
+
+        let done: serde_json::Value =
+            serde_json::from_str(&completion.stream_event(StreamEvent::done())).unwrap();
+        assert_eq!(done["choices"][0]["finish_reason"], "stop");
+        assert_eq!(done["sources"][0]["query"], "rust");
+        assert_eq!(done["sources"][0]["success"], true);
+    }
+}
@@ -0,0 +1,97 @@
+use axum::{extract::State, Form, Json};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::chat::persona::Persona;
+use crate::error::AppError;
+use crate::session::manager;
+use crate::AppState;
+
+use super::conversations::current_conversation;
+
+#[derive(Deserialize)]
+pub struct PersonaInput {
+    #[serde(default)]
+    pub persona: String,
+}
+
+#[derive(Serialize)]
+pub struct CurrentPersona {
+    /// Persona the session uses
+    persona: String,
+    /// Whether that is the server default rather than a choice
+    default: bool,
+    /// Every persona available, for the picker
+    personas: Vec<Persona>,
+}
+
+/// The persona used by the browser session, and the alternatives.
+pub async fn current_persona(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+) -> Result<Json<CurrentPersona>, AppError> {
+    let session = current_conversation(&state, &cookies)?;
+
+    Ok(Json(describe(&state, session.persona)))
+}
+
+/// Switch the browser session's persona, keeping its history.
+pub async fn set_persona(
+    cookies: CookieJar,
+    State(state): State<AppState>,
+    Form(input): Form<PersonaInput>,
+) -> Result<Json<CurrentPersona>, AppError> {
+    let session = current_conversation(&state, &cookies)?;
+    let persona = state.personas.validate(Some(&input.persona))?;
+
+    let session = manager::set_session_persona(&state.sessions, session.id, persona)
+        .ok_or(AppError::SessionNotFound)?;
+
+    Ok(Json(describe(&state, session.persona)))
+}
+
+fn describe(state: &AppState, persona: Option<String>) -> CurrentPersona {
+    CurrentPersona {
+        default: persona.is_none(),
+        persona: state.personas.get(persona.as_deref()).id.clone(),
+        personas: state.personas.list(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum_extra::extract::cookie::Cookie;
+    use uuid::Uuid;
+
+    use crate::routes::conversations::{CONVERSATION_COOKIE, OWNER_COOKIE};
+
+    fn cookies(owner: Uuid, conversation: Uuid) -> CookieJar {
+        CookieJar::new()
+            .add(Cookie::new(OWNER_COOKIE, owner.to_string()))
+            .add(Cookie::new(CONVERSATION_COOKIE, conversation.to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_persona_follows_conversation_owner() {
+        let state = crate::routes::test_state();
+        let (owner, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let id = manager::create_conversation(&state.sessions, owner).id;
+        let input = || PersonaInput {
+            persona: "ferret".to_string(),
+        };
+
+        // Another browser holding the conversation's id gets nowhere
+        let result = current_persona(cookies(other, id), State(state.clone())).await;
+        assert!(matches!(result, Err(AppError::SessionNotFound)));
+        let result = set_persona(cookies(other, id), State(state.clone()), Form(input())).await;
+        assert!(matches!(result, Err(AppError::SessionNotFound)));
+        assert_eq!(manager::find_session(&state.sessions, id).unwrap().persona, None);
+
+        let Json(current) = set_persona(cookies(owner, id), State(state.clone()), Form(input()))
+            .await
+            .unwrap();
+        assert!(!current.default);
+        assert_eq!(current.persona, "ferret");
+    }
+}
@@ -0,0 +1,389 @@
+//! Conversation export and import.
+//!
+//! Exports are Markdown for reading and sharing, or lossless JSON: the
+//! whole `Session` wrapped with a format marker. Imports accept that JSON,
+//! a bare `Session`, or ChatGPT's `conversations.json` (an array of
+//! conversations, each a tree of messages under `mapping`).
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Write;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::tools::parser::{parse_native_call, parse_tool_calls};
+use crate::tools::{Tool, ToolCall};
+
+use super::types::{ChatMessage, Role, Session};
+
+pub const EXPORT_FORMAT: &str = "ferret.session";
+pub const EXPORT_VERSION: u32 = 1;
+
+/// What a JSON export contains.
+#[derive(Serialize, Deserialize)]
+pub struct SessionExport {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub session: Session,
+}
+
+impl SessionExport {
+    /// The owner id is left out: it works like a password for the owner's
+    /// conversations, and exports get shared.
+    pub fn new(mut session: Session) -> Self {
+        session.owner = None;
+        Self {
+            format: EXPORT_FORMAT.to_string(),
+            version: EXPORT_VERSION,
+            exported_at: Utc::now(),
+            session,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Json,
+    #[serde(alias = "md")]
+    Markdown,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "md",
+        }
+    }
+}
+
+/// Render the conversation as Markdown: each user message, then the tools
+/// used and the answer that ended the turn. Links in answers are kept as
+/// written, so citations survive.
+pub fn to_markdown(session: &Session, tools: &[Arc<dyn Tool>]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}\n", session.title.as_deref().unwrap_or("Conversation"));
+    let _ = write!(out, "*Exported from Ferret on {}", format_time(Utc::now()));
+    if let Some(model) = &session.model {
+        let _ = write!(out, " · model {}", model);
+    }
+    out.push_str("*\n");
+
+    let mut sources: Vec<String> = Vec::new();
+    let mut answer: Option<&ChatMessage> = None;
+
+    for message in &session.messages {
+        match message.role {
+            Role::User => {
+                flush_turn(&mut out, &mut sources, answer.take());
+                heading(&mut out, "You", message);
+                let _ = writeln!(out, "{}", message.content.trim());
+            }
+            Role::Assistant if message.tool_calls.is_empty() => {
+                // Tag-mode tool steps carry their tags in the text
+                let tagged = parse_tool_calls(&message.content, tools);
+                if tagged.is_empty() {
+                    answer = Some(message);
+                } else {
+                    sources.extend(tagged.iter().flatten().map(|call| describe(tools, call)));
+                }
+            }
+            Role::Assistant => {
+                sources.extend(
+                    message
+                        .tool_calls
+                        .iter()
+                        .filter_map(|call| parse_native_call(call, tools).ok())
+                        .map(|call| describe(tools, &call)),
+                );
+            }
+            Role::Tool | Role::System => {}
+        }
+    }
+    flush_turn(&mut out, &mut sources, answer);
+
+    out
+}
+
+fn flush_turn(out: &mut String, sources: &mut Vec<String>, answer: Option<&ChatMessage>) {
+    let Some(answer) = answer else {
+        sources.clear();
+        return;
+    };
+
+    heading(out, "Ferret", answer);
+    if !sources.is_empty() {
+        for source in sources.drain(..) {
+            let _ = writeln!(out, "> {}", source);
+        }
+        out.push('\n');
+    }
+    let _ = writeln!(out, "{}", answer.content.trim());
+    if answer.interrupted {
+        out.push_str("\n*(stopped before finishing)*\n");
+    }
+}
+
+/// What a call consulted, e.g. "Searched: rust release".
+fn describe(tools: &[Arc<dyn Tool>], call: &ToolCall) -> String {
+    tools
+        .iter()
+        .find(|tool| tool.name() == call.name())
+        .map(|tool| tool.describe(call))
+        .unwrap_or_else(|| format!("Used {}: {}", call.name(), call.query()))
+}
+
+fn heading(out: &mut String, speaker: &str, message: &ChatMessage) {
+    let _ = write!(out, "\n## {}", speaker);
+    if let Some(at) = message.created_at {
+        let _ = write!(out, " · {}", format_time(at));
+    }
+    out.push_str("\n\n");
+}
+
+fn format_time(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+/// Parse an upload into sessions ready to store. Each gets a fresh id and
+/// no owner, and counts as active now, so imports neither clobber existing
+/// sessions nor expire at once.
+pub fn parse_import(value: Value) -> Result<Vec<Session>, String> {
+    let mut sessions = match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(parse_one)
+            .collect::<Result<Vec<_>, _>>()?,
+        other => vec![parse_one(other)?],
+    };
+
+    if sessions.is_empty() {
+        return Err("No conversations found".to_string());
+    }
+
+    let now = Utc::now();
+    for session in &mut sessions {
+        session.id = Uuid::new_v4();
+        session.version = 0;
+        session.owner = None;
+        session.last_activity = now;
+    }
+    Ok(sessions)
+}
+
+fn parse_one(value: Value) -> Result<Session, String> {
+    if value.get("mapping").is_some() {
+        return parse_chatgpt(&value);
+    }
+
+    if let Some(format) = value.get("format").and_then(Value::as_str) {
+        if format != EXPORT_FORMAT {
+            return Err(format!("Unsupported export format '{}'", format));
+        }
+        let export: SessionExport =
+            serde_json::from_value(value).map_err(|e| format!("Invalid Ferret export: {}", e))?;
+        if export.version > EXPORT_VERSION {
+            return Err(format!(
+                "Export version {} is newer than this server supports",
+                export.version
+            ));
+        }
+        return Ok(export.session);
+    }
+
+    if value.get("messages").is_some() {
+        return serde_json::from_value(value).map_err(|e| format!("Invalid Ferret session: {}", e));
+    }
+
+    Err("Unrecognised format: expected a Ferret export or ChatGPT conversations.json".to_string())
+}
+
+/// One ChatGPT conversation. Messages form a tree (edits and regenerations
+/// branch it); the thread shown in ChatGPT is the path from the root to
+/// `current_node`. Only user and assistant text is kept.
+fn parse_chatgpt(value: &Value) -> Result<Session, String> {
+    let mapping = value
+        .get("mapping")
+        .and_then(Value::as_object)
+        .ok_or("ChatGPT conversation has no message mapping")?;
+
+    let mut node = value
+        .get("current_node")
+        .and_then(Value::as_str)
+        .or_else(|| {
+            // Older exports lack `current_node`: take the first leaf
+            mapping
+                .iter()
+                .find(|(_, n)| n.get("children").and_then(Value::as_array).is_none_or(|c| c.is_empty()))
+                .map(|(id, _)| id.as_str())
+        })
+        .ok_or("ChatGPT conversation has no messages")?;
+
+    let mut path = Vec::new();
+    while let Some(entry) = mapping.get(node) {
+        path.push(entry);
+        match entry.get("parent").and_then(Value::as_str) {
+            Some(parent) if path.len() <= mapping.len() => node = parent,
+            _ => break,
+        }
+    }
+    path.reverse();
+
+    let mut session = Session::new(Uuid::new_v4());
+    session.title = value
+        .get("title")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string);
+    if let Some(created) = value.get("create_time").and_then(timestamp) {
+        session.created_at = created;
+    }
+
+    for message in path.iter().filter_map(|entry| entry.get("message")) {
+        let role = match message.pointer("/author/role").and_then(Value::as_str) {
+            Some("user") => Role::User,
+            Some("assistant") => Role::Assistant,
+            _ => continue,
+        };
+        let content_type = message.pointer("/content/content_type").and_then(Value::as_str);
+        if !matches!(content_type, Some("text" | "multimodal_text")) {
+            continue;
+        }
+        let text = message
+            .pointer("/content/parts")
+            .and_then(Value::as_array)
+            .map(|parts| {
+                parts
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        session.messages.push(ChatMessage {
+            created_at: message.get("create_time").and_then(timestamp),
+            ..ChatMessage::new(role, text)
+        });
+    }
+
+    if session.messages.is_empty() {
+        return Err(format!(
+            "ChatGPT conversation '{}' has no text messages",
+            session.title.as_deref().unwrap_or("untitled")
+        ));
+    }
+    Ok(session)
+}
+
+/// ChatGPT times are fractional Unix seconds.
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    let seconds = value.as_f64()?;
+    Utc.timestamp_millis_opt((seconds * 1000.0) as i64).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CHATGPT: &str = include_str!("../../tests/fixtures/import/chatgpt_conversations.json");
+
+    fn researched_session() -> Session {
+        let mut session = Session::new(Uuid::new_v4());
+        session.owner = Some(Uuid::new_v4());
+        session.title = Some("Rust releases".to_string());
+        session.add_message(ChatMessage::new(Role::User, "What's new in Rust?"));
+        session.add_message(ChatMessage {
+            tool_calls: vec![serde_json::from_value(json!({
+                "function": { "name": "search", "arguments": { "query": "rust release" } }
+            }))
+            .unwrap()],
+            ..ChatMessage::new(Role::Assistant, "")
+        });
+        session.add_message(ChatMessage::tool_result("search", "[Tool Result: search]"));
+        session.add_message(ChatMessage::new(
+            Role::Assistant,
+            "<fetch>https://blog.rust-lang.org/</fetch>\n\n[Tool Result: fetch]",
+        ));
+        session.add_message(ChatMessage::new(
+            Role::Assistant,
+            "Rust 1.80 is out, per [the Rust blog](https://blog.rust-lang.org/).",
+        ));
+        session
+    }
+
+    #[test]
+    fn test_markdown_keeps_citations_and_sources() {
+        let markdown = to_markdown(&researched_session(), &crate::tools::test_tools());
+
+        assert!(markdown.starts_with("# Rust releases\n"));
+        assert!(markdown.contains("## You · "));
+        assert!(markdown.contains("What's new in Rust?"));
+        assert!(markdown.contains("> Searched: rust release\n> Read: <https://blog.rust-lang.org/>\n\n"));
+        assert!(markdown.contains("[the Rust blog](https://blog.rust-lang.org/)"));
+        assert!(!markdown.contains("[Tool Result"));
+    }
+
+    #[test]
+    fn test_json_export_round_trips() {
+        let session = researched_session();
+        let export = serde_json::to_value(SessionExport::new(session.clone())).unwrap();
+
+        assert!(export["session"].get("owner").is_none());
+
+        let imported = parse_import(export).unwrap().remove(0);
+        assert_ne!(imported.id, session.id);
+        assert_eq!(imported.title, session.title);
+        assert_eq!(imported.messages.len(), session.messages.len());
+        assert_eq!(imported.messages[1].tool_calls.len(), 1);
+        assert_eq!(imported.messages[2].tool_name.as_deref(), Some("search"));
+        assert_eq!(imported.messages[0].created_at, session.messages[0].created_at);
+
+        // A bare session works too
+        assert!(parse_import(serde_json::to_value(&session).unwrap()).is_ok());
+        assert!(parse_import(json!({ "format": "other" })).is_err());
+        assert!(parse_import(json!({ "hello": "world" })).is_err());
+        assert!(parse_import(json!([])).is_err());
+    }
+
+    #[test]
+    fn test_chatgpt_import_follows_current_branch() {
+        let sessions = parse_import(serde_json::from_str(CHATGPT).unwrap()).unwrap();
+        assert_eq!(sessions.len(), 2);
+
+        let ferrets = &sessions[0];
+        assert_eq!(ferrets.title.as_deref(), Some("Ferret diet"));
+        let contents: Vec<&str> = ferrets.messages.iter().map(|m| m.content.as_str()).collect();
+        // The hidden system message is dropped, and the edited question
+        // replaces the original branch
+        assert_eq!(
+            contents,
+            vec![
+                "What do ferrets eat, exactly?",
+                "Ferrets are obligate carnivores.",
+                "Can they eat fruit?",
+                "Not really; their gut can't digest fibre."
+            ]
+        );
+        assert_eq!(ferrets.messages[0].role, Role::User);
+        assert!(ferrets.messages[0].created_at.is_some());
+        assert_eq!(ferrets.created_at.timestamp(), 1_700_000_000);
+
+        assert_eq!(sessions[1].messages.len(), 2);
+    }
+}
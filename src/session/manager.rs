@@ -1,30 +1,220 @@
-use dashmap::DashMap;
 use std::sync::Arc;
+use tracing::{debug, error};
 use uuid::Uuid;
 
-use super::types::Session;
+use crate::config::{AppConfig, SessionBackend};
+use crate::error::AppError;
+use crate::ollama::types::GenerationOptions;
 
-pub type SessionManager = Arc<DashMap<Uuid, Session>>;
+use super::sqlite::SqliteStore;
+use super::store::{MemoryStore, SessionStore};
+use super::types::{Session, SessionInfo};
+
+pub type SessionManager = Arc<dyn SessionStore>;
+
+/// Tries at a read-modify-write before giving up; conflicts need another
+/// write to land in the moment between reading and saving.
+const MAX_WRITE_ATTEMPTS: usize = 8;
 
 pub fn create_session_manager() -> SessionManager {
-    Arc::new(DashMap::new())
+    Arc::new(MemoryStore::new())
+}
+
+/// Open the session store selected by configuration.
+pub fn open_session_manager(config: &AppConfig) -> Result<SessionManager, AppError> {
+    match config.session_backend {
+        SessionBackend::Memory => Ok(create_session_manager()),
+        SessionBackend::Sqlite => Ok(Arc::new(SqliteStore::open(&config.session_db_path)?)),
+    }
+}
+
+/// Load a session, creating it for `owner` if it doesn't exist.
+pub fn get_or_create_session(manager: &SessionManager, id: Uuid, owner: Option<Uuid>) -> Session {
+    match manager.get(id) {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            let mut session = Session::new(id);
+            session.owner = owner;
+            session.legacy = false;
+            match manager.put(&mut session) {
+                Ok(()) => session,
+                // Another request created it first
+                Err(AppError::StaleSession) => find_session(manager, id).unwrap_or(session),
+                Err(e) => {
+                    error!("Failed to save session {}: {}", id, e);
+                    session
+                }
+            }
+        }
+        Err(e) => {
+            error!("Failed to load session {}: {}", id, e);
+            Session::new(id)
+        }
+    }
+}
+
+/// Look up an existing session without creating one.
+pub fn find_session(manager: &SessionManager, id: Uuid) -> Option<Session> {
+    manager.get(id).unwrap_or_else(|e| {
+        error!("Failed to load session {}: {}", id, e);
+        None
+    })
 }
 
-pub fn get_or_create_session(manager: &SessionManager, id: Uuid) -> Session {
-    manager
-        .entry(id)
-        .or_insert_with(|| Session::new(id))
-        .clone()
+/// Apply `change` to the latest copy of a session and save it, starting
+/// again from a fresh copy if another write lands in between. `None` if the
+/// session doesn't exist or couldn't be saved.
+pub fn modify_session(
+    manager: &SessionManager,
+    id: Uuid,
+    mut change: impl FnMut(&mut Session),
+) -> Option<Session> {
+    for _ in 0..MAX_WRITE_ATTEMPTS {
+        let mut session = find_session(manager, id)?;
+        change(&mut session);
+        match manager.put(&mut session) {
+            Ok(()) => return Some(session),
+            Err(AppError::StaleSession) => debug!("Session {} changed while saving, retrying", id),
+            Err(e) => {
+                error!("Failed to save session {}: {}", id, e);
+                return None;
+            }
+        }
+    }
+    error!("Gave up saving session {} after repeated conflicts", id);
+    None
 }
 
-pub fn update_session(manager: &SessionManager, session: Session) {
-    manager.insert(session.id, session);
+pub fn clear_session(manager: &SessionManager, id: Uuid) -> Option<Session> {
+    modify_session(manager, id, Session::clear)
 }
 
-pub fn clear_session(manager: &SessionManager, id: Uuid) {
-    if let Some(mut entry) = manager.get_mut(&id) {
-        entry.clear();
+/// Switch a session's model; `None` reverts to the server default.
+pub fn set_session_model(
+    manager: &SessionManager,
+    id: Uuid,
+    model: Option<String>,
+) -> Option<Session> {
+    modify_session(manager, id, |session| session.model.clone_from(&model))
+}
+
+/// Switch a session's persona, keeping its history.
+pub fn set_session_persona(
+    manager: &SessionManager,
+    id: Uuid,
+    persona: Option<String>,
+) -> Option<Session> {
+    modify_session(manager, id, |session| session.persona.clone_from(&persona))
+}
+
+/// Replace a session's generation options.
+pub fn set_session_options(
+    manager: &SessionManager,
+    id: Uuid,
+    options: GenerationOptions,
+) -> Option<Session> {
+    modify_session(manager, id, |session| session.options.clone_from(&options))
+}
+
+/// Delete a session, returning whether it existed.
+pub fn delete_session(manager: &SessionManager, id: Uuid) -> bool {
+    if find_session(manager, id).is_none() {
+        return false;
+    }
+    if let Err(e) = manager.remove(id) {
+        error!("Failed to delete session {}: {}", id, e);
+        return false;
     }
+    true
+}
+
+pub fn list_sessions(manager: &SessionManager) -> Vec<SessionInfo> {
+    manager.list().unwrap_or_else(|e| {
+        error!("Failed to list sessions: {}", e);
+        Vec::new()
+    })
+}
+
+/// Sessions created through the API, which have no owner, most recently
+/// active first.
+pub fn list_api_sessions(manager: &SessionManager) -> Vec<SessionInfo> {
+    list_sessions(manager)
+        .into_iter()
+        .filter(|s| s.owner.is_none())
+        .collect()
+}
+
+/// An owner's conversations, most recently active first.
+pub fn list_conversations(manager: &SessionManager, owner: Uuid) -> Vec<SessionInfo> {
+    list_sessions(manager)
+        .into_iter()
+        .filter(|s| s.owner == Some(owner))
+        .collect()
+}
+
+/// Start an empty conversation for `owner`.
+pub fn create_conversation(manager: &SessionManager, owner: Uuid) -> Session {
+    let mut session = Session::new(Uuid::new_v4());
+    session.owner = Some(owner);
+    if let Err(e) = manager.put(&mut session) {
+        error!("Failed to save session {}: {}", session.id, e);
+    }
+    session
+}
+
+/// Give a conversation saved before owners existed to the browser that
+/// opened it, returning it if it belongs to `owner`. Any other session is
+/// left alone.
+pub fn adopt_conversation(manager: &SessionManager, id: Uuid, owner: Uuid) -> Option<Session> {
+    modify_session(manager, id, |session| {
+        if session.legacy {
+            session.owner = Some(owner);
+            session.legacy = false;
+        }
+    })
+    .filter(|session| session.owner == Some(owner))
+}
+
+/// Store imported sessions under `owner` (`None` for API sessions).
+pub fn import_sessions(
+    manager: &SessionManager,
+    sessions: Vec<Session>,
+    owner: Option<Uuid>,
+) -> Vec<SessionInfo> {
+    sessions
+        .into_iter()
+        .filter_map(|mut session| {
+            session.owner = owner;
+            // Only conversations already here before owners can be adopted
+            session.legacy = false;
+            match manager.put(&mut session) {
+                Ok(()) => Some(session.info()),
+                Err(e) => {
+                    error!("Failed to save imported session {}: {}", session.id, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Rename a session; `None` clears the title.
+pub fn set_session_title(
+    manager: &SessionManager,
+    id: Uuid,
+    title: Option<String>,
+) -> Option<Session> {
+    modify_session(manager, id, |session| session.title.clone_from(&title))
+}
+
+/// Swap a provisional title for a generated one, unless the user renamed
+/// the conversation in the meantime.
+pub fn replace_title(manager: &SessionManager, id: Uuid, provisional: &str, title: String) {
+    modify_session(manager, id, |session| {
+        if session.title.as_deref() == Some(provisional) {
+            session.title = Some(title.clone());
+        }
+    });
 }
 
 pub fn session_count(manager: &SessionManager) -> usize {
@@ -1,5 +1,10 @@
+pub mod export;
 pub mod manager;
+pub mod reaper;
+pub mod sqlite;
+pub mod store;
 pub mod types;
 
-pub use manager::{create_session_manager, SessionManager};
-pub use types::{ChatMessage, Role, Session};
+pub use manager::{open_session_manager, SessionManager};
+pub use reaper::EvictionStats;
+pub use types::{ChatMessage, ConversationSummary, Generation, Role, Session, SessionInfo};
@@ -0,0 +1,180 @@
+use chrono::{Duration as ChronoDuration, Utc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+use super::manager::SessionManager;
+
+/// Running totals of sessions removed by the reaper, reported by `/health`.
+#[derive(Debug, Default)]
+pub struct EvictionStats {
+    expired: AtomicU64,
+    capacity: AtomicU64,
+}
+
+impl EvictionStats {
+    pub fn expired(&self) -> u64 {
+        self.expired.load(Ordering::Relaxed)
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity.load(Ordering::Relaxed)
+    }
+}
+
+/// Drop sessions that have been idle for longer than `timeout_mins` from
+/// memory. Persistent stores keep them on disk until they are used again.
+pub fn evict_expired(manager: &SessionManager, timeout_mins: u64, stats: &EvictionStats) -> usize {
+    let cutoff = Utc::now() - ChronoDuration::minutes(timeout_mins as i64);
+
+    let mut evicted = 0;
+    for (id, last_activity) in manager.resident() {
+        if last_activity < cutoff && manager.unload(id) {
+            debug!("Evicted expired session {}", id);
+            evicted += 1;
+        }
+    }
+
+    stats.expired.fetch_add(evicted as u64, Ordering::Relaxed);
+    evicted
+}
+
+/// Permanently delete sessions idle for longer than `retention_days`, on
+/// disk as well as in memory.
+pub fn delete_expired(manager: &SessionManager, retention_days: u64) -> usize {
+    let cutoff = Utc::now() - ChronoDuration::days(retention_days as i64);
+
+    match manager.remove_expired(cutoff) {
+        Ok(ids) => {
+            for id in &ids {
+                debug!("Deleted session {} after {} days idle", id, retention_days);
+            }
+            ids.len()
+        }
+        Err(e) => {
+            error!("Failed to delete expired sessions: {}", e);
+            0
+        }
+    }
+}
+
+/// Evict least-recently-active sessions until at most `max_sessions` remain
+/// resident. Persistent stores keep evicted sessions on disk.
+pub fn enforce_capacity(
+    manager: &SessionManager,
+    max_sessions: usize,
+    stats: &EvictionStats,
+) -> usize {
+    let excess = manager.len().saturating_sub(max_sessions);
+    if excess == 0 {
+        return 0;
+    }
+
+    let mut by_activity: Vec<_> = manager
+        .resident()
+        .into_iter()
+        .map(|(id, last_activity)| (last_activity, id))
+        .collect();
+    by_activity.sort_unstable();
+
+    let mut evicted = 0;
+    for (_, id) in by_activity.into_iter().take(excess) {
+        if manager.unload(id) {
+            debug!("Evicted least recently used session {}", id);
+            evicted += 1;
+        }
+    }
+
+    stats.capacity.fetch_add(evicted as u64, Ordering::Relaxed);
+    evicted
+}
+
+/// Periodically sweep the session map, enforcing the idle timeout and the
+/// session cap, and deleting sessions past `retention_days` if set.
+pub fn spawn_reaper(
+    manager: SessionManager,
+    stats: Arc<EvictionStats>,
+    timeout_mins: u64,
+    max_sessions: usize,
+    retention_days: Option<u64>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately; nothing can have expired yet
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            let deleted = retention_days.map_or(0, |days| delete_expired(&manager, days));
+            let expired = evict_expired(&manager, timeout_mins, &stats);
+            let over_capacity = enforce_capacity(&manager, max_sessions, &stats);
+
+            if deleted > 0 {
+                info!("Session reaper deleted {} sessions past retention", deleted);
+            }
+            if expired > 0 || over_capacity > 0 {
+                info!(
+                    "Session reaper evicted {} expired and {} over-capacity sessions ({} remaining)",
+                    expired,
+                    over_capacity,
+                    manager.len()
+                );
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::manager::create_session_manager;
+    use crate::session::Session;
+    use uuid::Uuid;
+
+    fn session_idle_for(mins: i64) -> Session {
+        let mut session = Session::new(Uuid::new_v4());
+        session.last_activity = Utc::now() - ChronoDuration::minutes(mins);
+        session
+    }
+
+    #[test]
+    fn test_evict_expired() {
+        let manager = create_session_manager();
+        let stats = EvictionStats::default();
+
+        let mut fresh = session_idle_for(5);
+        let mut stale = session_idle_for(90);
+        let fresh_id = fresh.id;
+        manager.put(&mut fresh).unwrap();
+        manager.put(&mut stale).unwrap();
+
+        assert_eq!(evict_expired(&manager, 60, &stats), 1);
+        assert_eq!(manager.len(), 1);
+        assert!(manager.get(fresh_id).unwrap().is_some());
+        assert_eq!(stats.expired(), 1);
+    }
+
+    #[test]
+    fn test_enforce_capacity_evicts_oldest() {
+        let manager = create_session_manager();
+        let stats = EvictionStats::default();
+
+        let oldest = session_idle_for(30);
+        let middle = session_idle_for(20);
+        let newest = session_idle_for(10);
+        let (middle_id, newest_id) = (middle.id, newest.id);
+        for mut session in [oldest, middle, newest] {
+            manager.put(&mut session).unwrap();
+        }
+
+        assert_eq!(enforce_capacity(&manager, 2, &stats), 1);
+        assert!(manager.get(middle_id).unwrap().is_some());
+        assert!(manager.get(newest_id).unwrap().is_some());
+        assert_eq!(stats.capacity(), 1);
+
+        assert_eq!(enforce_capacity(&manager, 2, &stats), 0);
+    }
+}
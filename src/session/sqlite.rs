@@ -0,0 +1,382 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use dashmap::DashMap;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+use super::store::SessionStore;
+use super::types::{Session, SessionInfo};
+
+/// Sessions written through to a SQLite database so they survive restarts.
+///
+/// Resident sessions are cached in memory; anything else is loaded from the
+/// database the first time its id is seen.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+    cache: DashMap<Uuid, Session>,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AppError> {
+        let conn = Connection::open(path.as_ref()).map_err(storage_error)?;
+        let store = Self::init_store(conn)?;
+        info!("Opened session database at {}", path.as_ref().display());
+        Ok(store)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, AppError> {
+        let conn = Connection::open_in_memory().map_err(storage_error)?;
+        Self::init_store(conn)
+    }
+
+    fn init_store(conn: Connection) -> Result<Self, AppError> {
+        let conn = Self::init(conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            cache: DashMap::new(),
+        })
+    }
+
+    fn init(conn: Connection) -> Result<Connection, AppError> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS sessions (
+                 id TEXT PRIMARY KEY,
+                 last_activity TEXT NOT NULL,
+                 data TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS sessions_last_activity
+                 ON sessions (last_activity);",
+        )
+        .map_err(storage_error)?;
+
+        // Ownerless rows from before browser conversations had owners can't
+        // be told apart from API sessions later, so they are marked once as
+        // legacy for the browser using them to adopt
+        let version: i64 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(storage_error)?;
+        if version < 1 {
+            conn.execute_batch(
+                "BEGIN;
+                 UPDATE sessions SET data = json_set(data, '$.legacy', json('true'))
+                     WHERE json_extract(data, '$.owner') IS NULL;
+                 PRAGMA user_version = 1;
+                 COMMIT;",
+            )
+            .map_err(storage_error)?;
+        }
+        Ok(conn)
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SessionStore for SqliteStore {
+    fn get(&self, id: Uuid) -> Result<Option<Session>, AppError> {
+        if let Some(session) = self.cache.get(&id) {
+            return Ok(Some(session.clone()));
+        }
+
+        // Hold the connection lock until the cache is filled, so a concurrent
+        // `put` can't be overwritten by the older row read here
+        let conn = self.conn();
+        let data: Option<String> = conn
+            .query_row(
+                "SELECT data FROM sessions WHERE id = ?1",
+                params![id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(storage_error)?;
+
+        let Some(data) = data else {
+            return Ok(None);
+        };
+
+        let session: Session = serde_json::from_str(&data)
+            .map_err(|e| AppError::Storage(format!("Corrupt session {}: {}", id, e)))?;
+
+        debug!("Loaded session {} from database", id);
+        self.cache.insert(id, session.clone());
+        Ok(Some(session))
+    }
+
+    fn put(&self, session: &mut Session) -> Result<(), AppError> {
+        let expected = session.version;
+        let mut next = session.clone();
+        next.version += 1;
+        let data = serde_json::to_string(&next).map_err(|e| AppError::Storage(e.to_string()))?;
+
+        // The connection lock also keeps the cache in step with the table
+        let conn = self.conn();
+        let id = session.id.to_string();
+        let last_activity = timestamp(session.last_activity);
+        let written = if expected == 0 {
+            // New, or saved before sessions had versions
+            conn.execute(
+                "INSERT INTO sessions (id, last_activity, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(id) DO UPDATE SET last_activity = ?2, data = ?3
+                 WHERE IFNULL(json_extract(sessions.data, '$.version'), 0) = 0",
+                params![id, last_activity, data],
+            )
+        } else {
+            conn.execute(
+                "UPDATE sessions SET last_activity = ?2, data = ?3
+                 WHERE id = ?1 AND json_extract(data, '$.version') = ?4",
+                params![id, last_activity, data, expected as i64],
+            )
+        }
+        .map_err(storage_error)?;
+
+        if written == 0 {
+            return Err(AppError::StaleSession);
+        }
+        session.version = next.version;
+        self.cache.insert(next.id, next);
+        Ok(())
+    }
+
+    fn remove(&self, id: Uuid) -> Result<(), AppError> {
+        // Evicted under the lock, after the row is gone, so a concurrent
+        // `get` can't load it back
+        let conn = self.conn();
+        conn.execute(
+            "DELETE FROM sessions WHERE id = ?1",
+            params![id.to_string()],
+        )
+        .map_err(storage_error)?;
+        self.cache.remove(&id);
+        Ok(())
+    }
+
+    fn remove_expired(&self, cutoff: DateTime<Utc>) -> Result<Vec<Uuid>, AppError> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("DELETE FROM sessions WHERE last_activity < ?1 RETURNING id")
+            .map_err(storage_error)?;
+        let ids = stmt
+            .query_map(params![timestamp(cutoff)], |row| row.get::<_, String>(0))
+            .map_err(storage_error)?
+            .filter_map(|id| id.ok().and_then(|id| Uuid::parse_str(&id).ok()))
+            .collect::<Vec<_>>();
+
+        for id in &ids {
+            self.cache.remove(id);
+        }
+        Ok(ids)
+    }
+
+    fn list(&self) -> Result<Vec<SessionInfo>, AppError> {
+        // Every resident session has been written through, so the table is
+        // complete; read metadata without deserialising whole histories
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, json_extract(data, '$.created_at'), last_activity,
+                        json_extract(data, '$.owner'), json_extract(data, '$.title'),
+                        json_extract(data, '$.model'), json_extract(data, '$.persona'),
+                        json_array_length(data, '$.messages')
+                 FROM sessions ORDER BY last_activity DESC",
+            )
+            .map_err(storage_error)?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, i64>(7)?,
+                ))
+            })
+            .map_err(storage_error)?;
+
+        let mut sessions = Vec::new();
+        for row in rows {
+            let (id, created_at, last_activity, owner, title, model, persona, message_count) =
+                row.map_err(storage_error)?;
+            let (Ok(id), Some(created_at), Some(last_activity)) = (
+                Uuid::parse_str(&id),
+                parse_timestamp(&created_at),
+                parse_timestamp(&last_activity),
+            ) else {
+                debug!("Skipping unreadable session row {}", id);
+                continue;
+            };
+            sessions.push(SessionInfo {
+                id,
+                created_at,
+                last_activity,
+                owner: owner.and_then(|o| Uuid::parse_str(&o).ok()),
+                title,
+                model,
+                persona,
+                message_count: message_count.max(0) as usize,
+            });
+        }
+        Ok(sessions)
+    }
+
+    fn unload(&self, id: Uuid) -> bool {
+        self.cache.remove(&id).is_some()
+    }
+
+    fn resident(&self) -> Vec<(Uuid, DateTime<Utc>)> {
+        self.cache
+            .iter()
+            .map(|entry| (*entry.key(), entry.last_activity))
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.cache.len()
+    }
+}
+
+/// Fixed-width UTC timestamps so that text comparison orders correctly.
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn parse_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+fn storage_error(e: rusqlite::Error) -> AppError {
+    AppError::Storage(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::reaper::{self, EvictionStats};
+    use crate::session::{ChatMessage, Role, SessionManager};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_round_trip_and_lazy_reload() {
+        let store = SqliteStore::open_in_memory().unwrap();
+
+        let mut session = Session::new(Uuid::new_v4());
+        session.add_message(ChatMessage::new(Role::User, "hello"));
+        session.add_message(ChatMessage::tool_result("search", "[Tool Result: search]"));
+        store.put(&mut session).unwrap();
+
+        // Simulate a restart by dropping the resident copy
+        assert!(store.unload(session.id));
+        assert_eq!(store.len(), 0);
+
+        let mut loaded = store.get(session.id).unwrap().expect("session persisted");
+        assert_eq!(loaded.messages.len(), 2);
+        assert_eq!(loaded.messages[1].role, Role::Tool);
+        assert_eq!(loaded.messages[1].tool_name.as_deref(), Some("search"));
+        assert_eq!(loaded.created_at, session.created_at);
+        assert_eq!(store.len(), 1);
+
+        // The version survives the reload, so stale copies are still caught
+        assert_eq!(loaded.version, 1);
+        store.put(&mut loaded).unwrap();
+        store.unload(session.id);
+        assert!(matches!(store.put(&mut session), Err(AppError::StaleSession)));
+        assert_eq!(store.get(session.id).unwrap().unwrap().version, 2);
+    }
+
+    #[test]
+    fn test_list_reads_metadata_from_disk() {
+        let store = SqliteStore::open_in_memory().unwrap();
+
+        let mut older = Session::new(Uuid::new_v4());
+        older.last_activity = Utc::now() - chrono::Duration::minutes(5);
+        store.put(&mut older).unwrap();
+
+        let mut newer = Session::new(Uuid::new_v4());
+        newer.model = Some("llama3.1:8b".to_string());
+        newer.add_message(ChatMessage::new(Role::User, "hello"));
+        store.put(&mut newer).unwrap();
+        store.unload(newer.id);
+
+        let listed = store.list().unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].id, newer.id);
+        assert_eq!(listed[0].message_count, 1);
+        assert_eq!(listed[0].model.as_deref(), Some("llama3.1:8b"));
+        assert_eq!(listed[1].model, None);
+        assert_eq!(listed[0].created_at, newer.created_at);
+        assert_eq!(listed[1].id, older.id);
+    }
+
+    #[test]
+    fn test_ownerless_sessions_from_before_owners_are_legacy() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sessions (id TEXT PRIMARY KEY, last_activity TEXT NOT NULL, data TEXT NOT NULL)",
+        )
+        .unwrap();
+        let mut owned = Session::new(Uuid::new_v4());
+        owned.owner = Some(Uuid::new_v4());
+        let ownerless = Session::new(Uuid::new_v4());
+        for session in [&owned, &ownerless] {
+            conn.execute(
+                "INSERT INTO sessions (id, last_activity, data) VALUES (?1, ?2, ?3)",
+                params![
+                    session.id.to_string(),
+                    timestamp(session.last_activity),
+                    serde_json::to_string(session).unwrap()
+                ],
+            )
+            .unwrap();
+        }
+
+        let store = SqliteStore::init_store(conn).unwrap();
+        assert!(store.get(ownerless.id).unwrap().unwrap().legacy);
+        assert!(!store.get(owned.id).unwrap().unwrap().legacy);
+
+        // Sessions saved afterwards, such as new API sessions, are not
+        let mut api = Session::new(Uuid::new_v4());
+        store.put(&mut api).unwrap();
+        let conn = store.conn.into_inner().unwrap();
+        let store = SqliteStore::init_store(conn).unwrap();
+        assert!(!store.get(api.id).unwrap().unwrap().legacy);
+    }
+
+    #[test]
+    fn test_idle_sessions_stay_on_disk() {
+        let manager: SessionManager = Arc::new(SqliteStore::open_in_memory().unwrap());
+        let stats = EvictionStats::default();
+
+        let mut idle = Session::new(Uuid::new_v4());
+        idle.add_message(ChatMessage::new(Role::User, "hello"));
+        idle.last_activity = Utc::now() - chrono::Duration::hours(2);
+        manager.put(&mut idle).unwrap();
+
+        // The idle timeout only drops the session from memory
+        assert_eq!(reaper::evict_expired(&manager, 60, &stats), 1);
+        assert_eq!(manager.len(), 0);
+        let reloaded = manager.get(idle.id).unwrap().expect("session kept on disk");
+        assert_eq!(reloaded.messages.len(), 1);
+
+        // Only the retention period deletes it
+        assert_eq!(reaper::delete_expired(&manager, 1), 0);
+        let mut old = Session::new(Uuid::new_v4());
+        old.last_activity = Utc::now() - chrono::Duration::days(3);
+        manager.put(&mut old).unwrap();
+        manager.unload(old.id);
+        assert_eq!(reaper::delete_expired(&manager, 2), 1);
+        assert!(manager.get(old.id).unwrap().is_none());
+        assert!(manager.get(idle.id).unwrap().is_some());
+    }
+}
@@ -0,0 +1,153 @@
+use chrono::{DateTime, Utc};
+use dashmap::{mapref::entry::Entry, DashMap};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+use super::types::{Session, SessionInfo};
+
+/// Backing storage for conversation sessions.
+///
+/// Stores keep a resident working set in memory; persistent stores also
+/// write every session through to disk and reload it lazily on first access.
+///
+/// Writes are versioned: `put` only succeeds if the stored session still has
+/// the version the caller read, so a stale copy cannot overwrite newer state.
+pub trait SessionStore: Send + Sync {
+    /// Fetch a session, loading it from the backing store if not resident.
+    fn get(&self, id: Uuid) -> Result<Option<Session>, AppError>;
+
+    /// Insert a new session (version 0) or replace the stored one if it has
+    /// the same version, then bump `session.version`. Fails with
+    /// `AppError::StaleSession` if the session changed or was deleted since
+    /// it was read.
+    fn put(&self, session: &mut Session) -> Result<(), AppError>;
+
+    /// Permanently delete a session.
+    fn remove(&self, id: Uuid) -> Result<(), AppError>;
+
+    /// Permanently delete every session idle since before `cutoff`, from
+    /// disk as well as memory, returning their ids.
+    fn remove_expired(&self, cutoff: DateTime<Utc>) -> Result<Vec<Uuid>, AppError>;
+
+    /// Metadata for every stored session, most recently active first.
+    fn list(&self) -> Result<Vec<SessionInfo>, AppError>;
+
+    /// Drop a session from memory. Persistent stores keep it on disk.
+    fn unload(&self, id: Uuid) -> bool;
+
+    /// Last activity of every resident session.
+    fn resident(&self) -> Vec<(Uuid, DateTime<Utc>)>;
+
+    /// Number of resident sessions.
+    fn len(&self) -> usize;
+}
+
+/// Sessions held only in process memory; lost on restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    sessions: DashMap<Uuid, Session>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn get(&self, id: Uuid) -> Result<Option<Session>, AppError> {
+        Ok(self.sessions.get(&id).map(|s| s.clone()))
+    }
+
+    fn put(&self, session: &mut Session) -> Result<(), AppError> {
+        match self.sessions.entry(session.id) {
+            Entry::Occupied(mut stored) if stored.get().version == session.version => {
+                session.version += 1;
+                stored.insert(session.clone());
+            }
+            Entry::Vacant(slot) if session.version == 0 => {
+                session.version += 1;
+                slot.insert(session.clone());
+            }
+            _ => return Err(AppError::StaleSession),
+        }
+        Ok(())
+    }
+
+    fn remove(&self, id: Uuid) -> Result<(), AppError> {
+        self.sessions.remove(&id);
+        Ok(())
+    }
+
+    fn remove_expired(&self, cutoff: DateTime<Utc>) -> Result<Vec<Uuid>, AppError> {
+        let expired: Vec<Uuid> = self
+            .sessions
+            .iter()
+            .filter(|entry| entry.last_activity < cutoff)
+            .map(|entry| *entry.key())
+            .collect();
+
+        // Re-check under the entry lock in case the session was used meanwhile
+        Ok(expired
+            .into_iter()
+            .filter(|id| {
+                self.sessions
+                    .remove_if(id, |_, s| s.last_activity < cutoff)
+                    .is_some()
+            })
+            .collect())
+    }
+
+    fn list(&self) -> Result<Vec<SessionInfo>, AppError> {
+        let mut sessions: Vec<SessionInfo> =
+            self.sessions.iter().map(|entry| entry.info()).collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_activity));
+        Ok(sessions)
+    }
+
+    fn unload(&self, id: Uuid) -> bool {
+        self.sessions.remove(&id).is_some()
+    }
+
+    fn resident(&self) -> Vec<(Uuid, DateTime<Utc>)> {
+        self.sessions
+            .iter()
+            .map(|entry| (*entry.key(), entry.last_activity))
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.sessions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stale_writes_are_rejected() {
+        let store = MemoryStore::new();
+        let mut session = Session::new(Uuid::new_v4());
+        store.put(&mut session).unwrap();
+        assert_eq!(session.version, 1);
+
+        let mut stale = session.clone();
+        session.title = Some("Newer".to_string());
+        store.put(&mut session).unwrap();
+
+        stale.title = Some("Older".to_string());
+        assert!(matches!(store.put(&mut stale), Err(AppError::StaleSession)));
+        assert_eq!(stale.version, 1);
+        assert_eq!(
+            store.get(session.id).unwrap().unwrap().title.as_deref(),
+            Some("Newer")
+        );
+
+        // A deleted session stays deleted
+        store.remove(session.id).unwrap();
+        assert!(store.put(&mut session).is_err());
+        assert!(store.get(session.id).unwrap().is_none());
+    }
+}
@@ -2,10 +2,56 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::ollama::types::{GenerationOptions, OllamaToolCall};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: Role,
     pub content: String,
+    /// Structured tool calls requested by the model in native tool mode
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<OllamaToolCall>,
+    /// Name of the tool that produced a `Role::Tool` message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    /// Model and options that produced an assistant message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation: Option<Generation>,
+    /// When the message was added to its session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    /// The reply was cut short by the user stopping it or leaving
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+}
+
+/// How an assistant message was generated, for comparing and reproducing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Generation {
+    pub model: String,
+    #[serde(default, skip_serializing_if = "GenerationOptions::is_empty")]
+    pub options: GenerationOptions,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_name: None,
+            generation: None,
+            created_at: None,
+            interrupted: false,
+        }
+    }
+
+    pub fn tool_result(tool: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            tool_name: Some(tool.into()),
+            ..Self::new(Role::Tool, content)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
@@ -14,14 +60,66 @@ pub enum Role {
     System,
     User,
     Assistant,
+    Tool,
 }
 
-#[derive(Debug, Clone)]
+/// One conversation. Browser users own many, grouped by an owner id that
+/// is separate from the conversation id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub id: Uuid,
+    /// Bumped by every write; a store refuses writes from an older copy
+    #[serde(default)]
+    pub version: u64,
     pub messages: Vec<ChatMessage>,
     pub created_at: DateTime<Utc>,
     pub last_activity: DateTime<Utc>,
+    /// Browser user the conversation belongs to; `None` for API sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Uuid>,
+    /// Saved from the browser before conversations had owners; the first
+    /// browser to open it adopts it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub legacy: bool,
+    /// Set by the user, or generated from the first exchange
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Ollama model chosen for this session; `None` uses the server default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Persona id; `None` uses the server's default persona
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>,
+    /// Most recent locale the user's client reported, e.g. `en-GB`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Generation options for this session, over the server defaults
+    #[serde(default, skip_serializing_if = "GenerationOptions::is_empty")]
+    pub options: GenerationOptions,
+    /// Condensed form of older turns that no longer fit the context window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<ConversationSummary>,
+}
+
+/// A model-written summary standing in for the first `covers` messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub text: String,
+    pub covers: usize,
+}
+
+/// Session metadata without the message history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_activity: DateTime<Utc>,
+    #[serde(default, skip_serializing)]
+    pub owner: Option<Uuid>,
+    pub title: Option<String>,
+    pub model: Option<String>,
+    pub persona: Option<String>,
+    pub message_count: usize,
 }
 
 impl Session {
@@ -29,19 +127,45 @@ impl Session {
         let now = Utc::now();
         Self {
             id,
+            version: 0,
             messages: Vec::new(),
             created_at: now,
             last_activity: now,
+            model: None,
+            owner: None,
+            legacy: false,
+            title: None,
+            persona: None,
+            locale: None,
+            options: GenerationOptions::default(),
+            summary: None,
         }
     }
 
-    pub fn add_message(&mut self, message: ChatMessage) {
+    pub fn info(&self) -> SessionInfo {
+        SessionInfo {
+            id: self.id,
+            created_at: self.created_at,
+            last_activity: self.last_activity,
+            owner: self.owner,
+            title: self.title.clone(),
+            model: self.model.clone(),
+            persona: self.persona.clone(),
+            message_count: self.messages.len(),
+        }
+    }
+
+    pub fn add_message(&mut self, mut message: ChatMessage) {
+        let now = Utc::now();
+        message.created_at.get_or_insert(now);
         self.messages.push(message);
-        self.last_activity = Utc::now();
+        self.last_activity = now;
     }
 
     pub fn clear(&mut self) {
         self.messages.clear();
+        self.summary = None;
+        self.title = None;
         self.last_activity = Utc::now();
     }
 }
@@ -0,0 +1,120 @@
+//! HTML pages and fragments, rendered with minijinja.
+//!
+//! Templates are compiled into the binary from `templates/`. `.html`
+//! templates escape their values; the `markdown` filter turns reply text into
+//! HTML the way the browser does while streaming: links become anchors and
+//! line breaks become `<br>`.
+
+use lazy_static::lazy_static;
+use minijinja::{Environment, HtmlEscape, Value};
+use regex::Regex;
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::error::AppError;
+
+const TEMPLATES: &[(&str, &str)] = &[
+    ("index.html", include_str!("../templates/index.html")),
+    ("messages.html", include_str!("../templates/messages.html")),
+];
+
+lazy_static! {
+    static ref LINK_PATTERN: Regex = Regex::new(r"\[([^\]]+)\]\(([^)\s]+)\)").unwrap();
+}
+
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+impl Templates {
+    pub fn new() -> Result<Self, String> {
+        let mut env = Environment::new();
+        env.add_filter("markdown", markdown);
+        for (name, source) in TEMPLATES {
+            env.add_template(name, source)
+                .map_err(|e| format!("Template {}: {}", name, e))?;
+        }
+        Ok(Self { env })
+    }
+
+    pub fn render(&self, name: &str, context: impl Serialize) -> Result<String, AppError> {
+        self.env
+            .get_template(name)
+            .and_then(|template| template.render(context))
+            .map_err(|e| AppError::Template(format!("{}: {}", name, e)))
+    }
+}
+
+/// Escape `text`, then link `[label](url)` for http(s) URLs and keep line
+/// breaks. Other link targets are left as text.
+fn markdown(text: &str) -> Value {
+    let mut html = String::new();
+    let mut last = 0;
+
+    for caps in LINK_PATTERN.captures_iter(text) {
+        let whole = caps.get(0).unwrap();
+        push_text(&mut html, &text[last..whole.start()]);
+
+        let url = &caps[2];
+        if url.starts_with("http://") || url.starts_with("https://") {
+            let _ = write!(
+                html,
+                "<a href=\"{}\" target=\"_blank\" rel=\"noopener noreferrer\">{}</a>",
+                HtmlEscape(url),
+                HtmlEscape(&caps[1])
+            );
+        } else {
+            push_text(&mut html, whole.as_str());
+        }
+        last = whole.end();
+    }
+    push_text(&mut html, &text[last..]);
+
+    Value::from_safe_string(html)
+}
+
+fn push_text(html: &mut String, text: &str) {
+    html.push_str(&HtmlEscape(text).to_string().replace('\n', "<br>"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::history::VisibleMessage;
+    use crate::session::Role;
+    use serde_json::json;
+
+    #[test]
+    fn test_messages_are_escaped_and_linked() {
+        let templates = Templates::new().unwrap();
+        let messages = vec![
+            VisibleMessage {
+                role: Role::User,
+                content: "<b>hi</b> [x](https://example.com)".to_string(),
+                created_at: None,
+                interrupted: false,
+            },
+            VisibleMessage {
+                role: Role::Assistant,
+                content: "See [the <docs>](https://example.com/a?b=1&c=2)\nor [this](javascript:alert(1))".to_string(),
+                created_at: None,
+                interrupted: false,
+            },
+        ];
+        let html = templates
+            .render("messages.html", json!({ "messages": messages }))
+            .unwrap();
+
+        assert!(html.contains("&lt;b&gt;hi&lt;&#x2f;b&gt; [x]"));
+        assert!(html.contains(
+            "<a href=\"https:&#x2f;&#x2f;example.com&#x2f;a?b=1&amp;c=2\" target=\"_blank\" rel=\"noopener noreferrer\">the &lt;docs&gt;</a><br>or [this](javascript:alert(1))"
+        ));
+        assert!(!html.contains("Hello! I'm Ferret"));
+        assert!(!html.contains("Stopped"));
+
+        let empty = templates
+            .render("messages.html", json!({ "messages": [] }))
+            .unwrap();
+        assert!(empty.contains("Hello! I'm Ferret"));
+    }
+}
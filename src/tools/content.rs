@@ -0,0 +1,330 @@
+use encoding_rs::{Encoding, UTF_8};
+use lazy_static::lazy_static;
+use regex::bytes::Regex;
+use reqwest::Url;
+use scraper::Html;
+use std::fmt::Write;
+
+use crate::error::AppError;
+
+use super::readability;
+
+const MAX_FEED_ITEMS: usize = 20;
+const MAX_FEED_SUMMARY_CHARS: usize = 300;
+
+lazy_static! {
+    // <meta charset="..."> or <meta http-equiv="Content-Type" content="...; charset=...">
+    static ref META_CHARSET: Regex =
+        Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?\s*([a-zA-Z0-9_\-:.]+)"#).unwrap();
+    // <?xml version="1.0" encoding="..."?>
+    static ref XML_ENCODING: Regex =
+        Regex::new(r#"^\s*<\?xml[^>]+encoding\s*=\s*["']([a-zA-Z0-9_\-:.]+)"#).unwrap();
+}
+
+/// How a fetched body should be turned into text for the model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentKind {
+    Html,
+    Text,
+    Json,
+    Feed,
+    Pdf,
+}
+
+impl ContentKind {
+    /// Classify a body from its Content-Type, sniffing the bytes when the
+    /// header is missing or generic.
+    pub fn detect(content_type: &str, body: &[u8]) -> Self {
+        let mime = mime_essence(content_type);
+
+        match mime.as_str() {
+            "text/html" | "application/xhtml+xml" => ContentKind::Html,
+            "application/pdf" => ContentKind::Pdf,
+            "application/rss+xml" | "application/atom+xml" | "application/rdf+xml" => {
+                ContentKind::Feed
+            }
+            "application/json" => ContentKind::Json,
+            m if m.ends_with("+json") => ContentKind::Json,
+            "text/xml" | "application/xml" => {
+                if looks_like_feed(body) {
+                    ContentKind::Feed
+                } else {
+                    ContentKind::Text
+                }
+            }
+            m if m.starts_with("text/") => ContentKind::Text,
+            _ => sniff(body),
+        }
+    }
+}
+
+/// Convert a fetched body into text according to its content type. `url` is
+/// the final URL after redirects, used to resolve relative links.
+pub async fn extract(body: Vec<u8>, content_type: &str, url: &Url) -> Result<String, AppError> {
+    match ContentKind::detect(content_type, &body) {
+        ContentKind::Html => Ok(readability::extract_markdown(
+            &decode(&body, content_type, true),
+            Some(url),
+        )),
+        ContentKind::Text => Ok(decode(&body, content_type, false)),
+        ContentKind::Json => Ok(format_json(&decode(&body, content_type, false))),
+        ContentKind::Feed => format_feed(&body),
+        ContentKind::Pdf => extract_pdf(body).await,
+    }
+}
+
+/// Decode bytes using, in order of preference, a byte-order mark, the
+/// Content-Type charset, an in-document declaration, then UTF-8.
+pub fn decode(body: &[u8], content_type: &str, sniff_meta: bool) -> String {
+    let declared = charset_param(content_type)
+        .or_else(|| {
+            // Declarations must appear near the start of the document
+            let head = &body[..body.len().min(1024)];
+            let pattern: &Regex = if sniff_meta {
+                &META_CHARSET
+            } else {
+                &XML_ENCODING
+            };
+            pattern
+                .captures(head)
+                .and_then(|c| c.get(1))
+                .map(|m| String::from_utf8_lossy(m.as_bytes()).to_string())
+        })
+        .and_then(|label| Encoding::for_label(label.trim().as_bytes()))
+        .unwrap_or(UTF_8);
+
+    // `decode` lets a BOM override the declared encoding
+    let (text, _, _) = declared.decode(body);
+    text.into_owned()
+}
+
+fn mime_essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+fn charset_param(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn looks_like_feed(body: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&body[..body.len().min(512)]);
+    head.contains("<rss") || head.contains("<feed") || head.contains("<rdf:RDF")
+}
+
+fn sniff(body: &[u8]) -> ContentKind {
+    if body.starts_with(b"%PDF-") {
+        return ContentKind::Pdf;
+    }
+
+    let head = String::from_utf8_lossy(&body[..body.len().min(512)]).to_ascii_lowercase();
+    let trimmed = head.trim_start_matches('\u{feff}').trim_start();
+
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        ContentKind::Json
+    } else if looks_like_feed(body) {
+        ContentKind::Feed
+    } else if trimmed.starts_with("<!doctype html") || head.contains("<html") {
+        ContentKind::Html
+    } else {
+        ContentKind::Text
+    }
+}
+
+fn format_json(text: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(value) => serde_json::to_string_pretty(&value).unwrap_or_else(|_| text.to_string()),
+        // Not valid JSON after all; pass it through as text
+        Err(_) => text.to_string(),
+    }
+}
+
+fn format_feed(body: &[u8]) -> Result<String, AppError> {
+    let feed = feed_rs::parser::parse(body)
+        .map_err(|e| AppError::PageFetch(format!("Could not parse feed: {}", e)))?;
+
+    let mut output = String::new();
+    if let Some(title) = &feed.title {
+        let _ = writeln!(output, "Feed: {}", title.content.trim());
+    }
+    if let Some(description) = &feed.description {
+        let _ = writeln!(output, "{}", html_to_text(&description.content));
+    }
+    let _ = writeln!(
+        output,
+        "Items: {} (showing up to {})\n",
+        feed.entries.len(),
+        MAX_FEED_ITEMS
+    );
+
+    for (i, entry) in feed.entries.iter().take(MAX_FEED_ITEMS).enumerate() {
+        let title = entry
+            .title
+            .as_ref()
+            .map(|t| t.content.trim().to_string())
+            .unwrap_or_else(|| "(untitled)".to_string());
+        let _ = writeln!(output, "{}. {}", i + 1, title);
+
+        if let Some(link) = entry.links.first() {
+            let _ = writeln!(output, "   URL: {}", link.href);
+        }
+        if let Some(date) = entry.published.or(entry.updated) {
+            let _ = writeln!(output, "   Date: {}", date.format("%Y-%m-%d"));
+        }
+        if let Some(summary) = entry
+            .summary
+            .as_ref()
+            .map(|s| s.content.as_str())
+            .or_else(|| entry.content.as_ref().and_then(|c| c.body.as_deref()))
+        {
+            let summary = truncate_chars(&html_to_text(summary), MAX_FEED_SUMMARY_CHARS);
+            if !summary.is_empty() {
+                let _ = writeln!(output, "   Summary: {}", summary);
+            }
+        }
+        output.push('\n');
+    }
+
+    Ok(output.trim_end().to_string())
+}
+
+async fn extract_pdf(body: Vec<u8>) -> Result<String, AppError> {
+    // Extraction is CPU-bound, and the parser can panic on malformed files
+    let result = tokio::task::spawn_blocking(move || {
+        std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(&body))
+    })
+    .await
+    .map_err(|e| AppError::PageFetch(format!("PDF extraction failed: {}", e)))?;
+
+    match result {
+        Ok(Ok(text)) => Ok(tidy_pdf_text(&text)),
+        Ok(Err(e)) => Err(AppError::PageFetch(format!("Could not read PDF: {}", e))),
+        Err(_) => Err(AppError::PageFetch(
+            "Could not read PDF: malformed file".to_string(),
+        )),
+    }
+}
+
+/// PDF text comes out with ragged spacing; collapse runs of blank lines.
+fn tidy_pdf_text(text: &str) -> String {
+    let mut output = String::new();
+    let mut blank_run = 0;
+
+    for line in text.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            blank_run += 1;
+            if blank_run > 1 {
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    output.trim().to_string()
+}
+
+fn html_to_text(html: &str) -> String {
+    let fragment = Html::parse_fragment(html);
+    fragment
+        .root_element()
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_kind() {
+        assert_eq!(
+            ContentKind::detect("text/html; charset=utf-8", b""),
+            ContentKind::Html
+        );
+        assert_eq!(
+            ContentKind::detect("text/markdown", b"# Hi"),
+            ContentKind::Text
+        );
+        assert_eq!(
+            ContentKind::detect("application/ld+json", b"{}"),
+            ContentKind::Json
+        );
+        assert_eq!(
+            ContentKind::detect("application/xml", b"<?xml?><rss>"),
+            ContentKind::Feed
+        );
+        assert_eq!(
+            ContentKind::detect("unknown", b"%PDF-1.7"),
+            ContentKind::Pdf
+        );
+        assert_eq!(
+            ContentKind::detect("", b"<!DOCTYPE html><html>"),
+            ContentKind::Html
+        );
+    }
+
+    #[test]
+    fn test_decode_uses_header_then_meta() {
+        // "café" in Latin-1
+        let latin1 = b"caf\xe9";
+        assert_eq!(
+            decode(latin1, "text/plain; charset=ISO-8859-1", false),
+            "café"
+        );
+
+        let html = b"<html><head><meta charset=\"windows-1252\"></head><body>caf\xe9</body></html>";
+        assert!(decode(html, "text/html", true).contains("café"));
+
+        assert_eq!(decode("café".as_bytes(), "text/plain", false), "café");
+    }
+
+    #[test]
+    fn test_format_json() {
+        assert_eq!(
+            format_json(r#"{"a":[1,2]}"#),
+            "{\n  \"a\": [\n    1,\n    2\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn test_format_feed() {
+        let rss = br#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+              <title>Rust Blog</title>
+              <item>
+                <title>Announcing Rust 1.80</title>
+                <link>https://blog.rust-lang.org/2024/07/25/Rust-1.80.0.html</link>
+                <description>&lt;p&gt;The Rust team is happy to announce...&lt;/p&gt;</description>
+              </item>
+            </channel></rss>"#;
+
+        let text = format_feed(rss).unwrap();
+        assert!(text.starts_with("Feed: Rust Blog"));
+        assert!(text.contains("1. Announcing Rust 1.80"));
+        assert!(text.contains("URL: https://blog.rust-lang.org/2024/07/25/Rust-1.80.0.html"));
+        assert!(text.contains("Summary: The Rust team is happy to announce..."));
+    }
+}
@@ -1,13 +1,26 @@
-use tracing::{debug, error};
+use std::sync::Arc;
+use tracing::{debug, error, warn};
 
-use super::fetch::PageFetcher;
-use super::parser::ToolCall;
-use super::search::BraveClient;
+use crate::config::AppConfig;
 
+use super::external;
+use super::fetch::{FetchTool, PageFetcher};
+use super::mcp::{McpServer, ServerHealth};
+use super::mcp_service::SERVED_TOOLS;
+use super::parser::MalformedCall;
+use super::search::{self, SearchTool};
+use super::ssrf::UrlGuard;
+use super::tool::{result_block, Tool, ToolCall};
+
+/// The tools this server offers, and runs calls to them.
 #[derive(Clone)]
 pub struct ToolExecutor {
-    brave: BraveClient,
-    fetcher: PageFetcher,
+    tools: Vec<Arc<dyn Tool>>,
+    mcp_servers: Vec<Arc<McpServer>>,
+    /// `ENABLED_TOOLS`; tool or MCP server names, empty for everything
+    enabled: Vec<String>,
+    search_provider: Option<&'static str>,
+    max_parallel: usize,
 }
 
 pub struct ToolResult {
@@ -16,59 +29,172 @@ pub struct ToolResult {
     pub content: String,
 }
 
+impl ToolResult {
+    /// Answer to a call for a tool the conversation isn't allowed to use.
+    pub fn disabled(tool: &str) -> Self {
+        Self {
+            tool: tool.to_string(),
+            success: false,
+            content: result_block(tool, "Error: This tool is not enabled in this conversation"),
+        }
+    }
+
+    /// Answer to a call the model wrote incorrectly; nothing was run.
+    pub fn malformed(call: &MalformedCall) -> Self {
+        Self {
+            tool: call.tool.clone(),
+            success: false,
+            content: result_block(
+                &call.tool,
+                &format!("Error: Malformed tool call, nothing was run: {}", call.reason),
+            ),
+        }
+    }
+}
+
 impl ToolExecutor {
-    pub fn new(brave_api_key: &str) -> Self {
+    pub fn new(tools: Vec<Arc<dyn Tool>>, max_parallel: usize) -> Self {
         Self {
-            brave: BraveClient::new(brave_api_key),
-            fetcher: PageFetcher::new(),
+            tools,
+            mcp_servers: Vec::new(),
+            enabled: Vec::new(),
+            search_provider: None,
+            max_parallel: max_parallel.max(1),
         }
     }
 
-    pub async fn execute(&self, call: &ToolCall) -> ToolResult {
-        match call {
-            ToolCall::Search { query } => self.execute_search(query).await,
-            ToolCall::Fetch { url } => self.execute_fetch(url).await,
+    /// The built-in tools that `config` sets up and those declared in the
+    /// tools file, limited to `ENABLED_TOOLS` when it is set. Search needs
+    /// a provider. MCP servers are only started by `start_mcp`.
+    pub fn from_config(config: &AppConfig) -> Result<Self, String> {
+        let mut tools: Vec<Arc<dyn Tool>> = Vec::new();
+        let mut search_provider = None;
+
+        if let Some(provider) = search::provider_from_config(config) {
+            let tool = SearchTool::new(provider);
+            search_provider = Some(tool.provider_name());
+            tools.push(Arc::new(tool));
+        }
+        tools.push(Arc::new(FetchTool::new(PageFetcher::new(UrlGuard::new(
+            config.fetch_allow_hosts.clone(),
+            config.fetch_deny_hosts.clone(),
+        )))));
+
+        let file = external::load(std::path::Path::new(&config.tools_file))?;
+        for tool in file.tools {
+            if tools.iter().any(|t| t.name() == tool.name()) {
+                return Err(format!(
+                    "Tool '{}' in {} has the name of a built-in tool",
+                    tool.name(),
+                    config.tools_file
+                ));
+            }
+            tools.push(Arc::new(tool));
+        }
+
+        let mcp_servers: Vec<Arc<McpServer>> =
+            file.mcp_servers.into_iter().map(Arc::new).collect();
+
+        // MCP tools are only known once their servers start, so a name
+        // under a server's prefix may be one of them
+        let enabled = &config.enabled_tools;
+        for name in enabled {
+            let known = tools.iter().any(|tool| tool.name() == name)
+                || mcp_servers.iter().any(|server| {
+                    name == server.name() || name.starts_with(&format!("{}_", server.name()))
+                });
+            if !known {
+                warn!("ENABLED_TOOLS names {}, which is not configured", name);
+            }
+        }
+        if !enabled.is_empty() {
+            tools.retain(|tool| enabled.iter().any(|name| name == tool.name()));
         }
+        if !tools.iter().any(|tool| tool.name() == "search") {
+            search_provider = None;
+        }
+
+        Ok(Self {
+            mcp_servers,
+            enabled: enabled.clone(),
+            search_provider,
+            ..Self::new(tools, config.tool_parallelism)
+        })
     }
 
-    async fn execute_search(&self, query: &str) -> ToolResult {
-        debug!("Executing search: {}", query);
+    /// Start the MCP servers, waiting for each to connect or fail once.
+    /// Servers that fail keep retrying in the background.
+    pub async fn start_mcp(&self) {
+        let started: Vec<_> = self.mcp_servers.iter().map(|server| server.start()).collect();
+        for started in started {
+            let _ = started.await;
+        }
+    }
 
-        match self.brave.search(query, 10).await {
-            Ok(results) => {
-                let content = BraveClient::format_results(query, &results);
-                ToolResult {
-                    tool: "search".to_string(),
-                    success: true,
-                    content,
+    /// The state of each MCP server.
+    pub fn mcp_health(&self) -> Vec<ServerHealth> {
+        self.mcp_servers.iter().map(|server| server.health()).collect()
+    }
+
+    /// How many tool calls from one model turn may run at once.
+    pub fn max_parallel(&self) -> usize {
+        self.max_parallel
+    }
+
+    /// Name of the configured search provider, if search is enabled.
+    pub fn search_provider(&self) -> Option<&'static str> {
+        self.search_provider
+    }
+
+    /// Every tool this server offers right now. MCP servers' tools come
+    /// and go as the servers restart or change them, so callers take one
+    /// snapshot per turn.
+    pub fn registry(&self) -> Vec<Arc<dyn Tool>> {
+        let mut tools = self.tools.clone();
+        for server in &self.mcp_servers {
+            let server_enabled = self.enabled.is_empty()
+                || self.enabled.iter().any(|name| name == server.name());
+            for tool in server.tools() {
+                if tools.iter().any(|t| t.name() == tool.name()) {
+                    continue;
                 }
-            }
-            Err(e) => {
-                error!("Search failed: {}", e);
-                ToolResult {
-                    tool: "search".to_string(),
-                    success: false,
-                    content: BraveClient::format_error(&e.to_string()),
+                if server_enabled || self.enabled.iter().any(|name| name == tool.name()) {
+                    tools.push(tool);
                 }
             }
         }
+        tools
     }
 
-    async fn execute_fetch(&self, url: &str) -> ToolResult {
-        debug!("Executing fetch: {}", url);
+    /// The built-in tools that are served to MCP clients, when enabled.
+    pub fn served_tools(&self) -> Vec<Arc<dyn Tool>> {
+        self.tools
+            .iter()
+            .filter(|tool| SERVED_TOOLS.contains(&tool.name()))
+            .cloned()
+            .collect()
+    }
+
+    pub async fn execute(&self, call: &ToolCall) -> ToolResult {
+        let name = call.name();
+        let Some(tool) = self.registry().into_iter().find(|tool| tool.name() == name) else {
+            return ToolResult::disabled(name);
+        };
+
+        debug!("Executing {}: {}", name, call.query());
 
-        match self.fetcher.fetch(url).await {
-            Ok(content) => ToolResult {
-                tool: "fetch".to_string(),
+        match tool.execute(call).await {
+            Ok(output) => ToolResult {
+                tool: name.to_string(),
                 success: true,
-                content,
+                content: tool.format_result(call, &output),
             },
             Err(e) => {
-                error!("Fetch failed: {}", e);
+                error!("{} failed: {}", name, e);
                 ToolResult {
-                    tool: "fetch".to_string(),
+                    tool: name.to_string(),
                     success: false,
-                    content: PageFetcher::format_error(url, &e.to_string()),
+                    content: tool.format_error(call, &e.to_string()),
                 }
             }
         }
@@ -0,0 +1,768 @@
+//! Tools declared in a TOML file rather than written in Rust.
+//!
+//! Each `[[tool]]` entry names the tool, describes it and its arguments for
+//! the model, and says how to run it: an HTTP endpoint or a command.
+//!
+//! ```toml
+//! [[tool]]
+//! name = "ticket"
+//! title = "Look up a ticket"
+//! description = "Fetch a support ticket's status, assignee and latest update."
+//! example = "OPS-1234"
+//! timeout_secs = 5
+//! http = { url = "https://tickets.internal/api/tickets/{id}", headers = { Authorization = "Bearer ${TICKETS_TOKEN}" } }
+//!
+//! [[tool.argument]]
+//! name = "id"
+//! type = "token"
+//! description = "Ticket id, e.g. OPS-1234"
+//!
+//! [[tool]]
+//! name = "oncall"
+//! description = "Who is on call for a team this week."
+//! command = ["/usr/local/bin/oncall", "--json"]
+//!
+//! [[tool.argument]]
+//! name = "team"
+//! type = "choice"
+//! choices = ["platform", "payments", "support"]
+//! description = "The team"
+//! ```
+//!
+//! HTTP tools substitute `{argument}` placeholders in the URL, percent
+//! encoded. A GET sends the other arguments as query parameters; a POST
+//! sends every argument as a JSON object. Commands get that JSON object on
+//! stdin and answer on stdout; a non-zero exit is a failure. `${VAR}` in
+//! header values is read from the environment at startup. Endpoints are
+//! not checked against the fetch tool's host lists, since reaching
+//! internal services is the point.
+//!
+//! `[[mcp]]` entries declare MCP servers whose tools are offered too; see
+//! the `mcp` module.
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use regex::{Captures, Regex};
+use reqwest::{Client, Method, Url};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tracing::{debug, info};
+
+use crate::error::AppError;
+
+use super::tool::{truncate, Argument, Tool, ToolCall};
+use super::mcp::{McpServer, McpServerSpec};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 16 * 1024;
+/// How much of a failing command's stderr goes into the error
+const MAX_STDERR_BYTES: usize = 1024;
+/// Everything but the characters URLs leave unescaped
+const PATH_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+lazy_static! {
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{([A-Za-z0-9_-]+)\}").unwrap();
+    static ref ENV_REFERENCE: Regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    static ref TOOL_NAME: Regex = Regex::new(r"^[a-z][a-z0-9_-]*$").unwrap();
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToolsFile {
+    #[serde(default)]
+    tool: Vec<ToolSpec>,
+    #[serde(default)]
+    mcp: Vec<McpServerSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToolSpec {
+    name: String,
+    title: Option<String>,
+    description: String,
+    example: Option<String>,
+    #[serde(default)]
+    argument: Vec<ArgumentSpec>,
+    timeout_secs: Option<u64>,
+    max_output_bytes: Option<usize>,
+    http: Option<HttpSpec>,
+    command: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArgumentSpec {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(rename = "type", default)]
+    kind: ArgumentType,
+    #[serde(default)]
+    required: bool,
+    min: Option<i64>,
+    max: Option<i64>,
+    choices: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ArgumentType {
+    #[default]
+    Text,
+    Token,
+    Integer,
+    Choice,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HttpSpec {
+    url: String,
+    #[serde(default = "default_method")]
+    method: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// How an external tool is run.
+enum Runner {
+    Http {
+        client: Client,
+        method: Method,
+        url: String,
+        headers: Vec<(String, String)>,
+    },
+    Command { program: String, args: Vec<String> },
+}
+
+/// A tool declared in the tools file.
+pub struct ExternalTool {
+    name: String,
+    title: String,
+    description: String,
+    example: String,
+    arguments: Vec<Argument>,
+    timeout: Duration,
+    max_output_bytes: usize,
+    runner: Runner,
+}
+
+/// What a tools file declares.
+#[derive(Default)]
+pub struct ToolsConfig {
+    pub tools: Vec<ExternalTool>,
+    pub mcp_servers: Vec<McpServer>,
+}
+
+/// Load the tools declared in `path`. A missing file declares none.
+pub fn load(path: &Path) -> Result<ToolsConfig, String> {
+    if !path.exists() {
+        debug!("No tools file at {}", path.display());
+        return Ok(ToolsConfig::default());
+    }
+
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read tools file {}: {}", path.display(), e))?;
+    let config = parse(&source).map_err(|e| format!("{}: {}", path.display(), e))?;
+    info!(
+        "Loaded {} external tool(s) and {} MCP server(s) from {}",
+        config.tools.len(),
+        config.mcp_servers.len(),
+        path.display()
+    );
+    Ok(config)
+}
+
+/// Build the tools declared in a tools file's contents.
+pub fn parse(source: &str) -> Result<ToolsConfig, String> {
+    let file: ToolsFile = toml::from_str(source).map_err(|e| e.to_string())?;
+
+    let mut tools: Vec<ExternalTool> = Vec::new();
+    for spec in file.tool {
+        let name = spec.name.clone();
+        let tool = ExternalTool::from_spec(spec).map_err(|e| format!("Tool '{}': {}", name, e))?;
+        if tools.iter().any(|t| t.name == tool.name) {
+            return Err(format!("Tool '{}' is declared more than once", name));
+        }
+        tools.push(tool);
+    }
+
+    let mut mcp_servers: Vec<McpServer> = Vec::new();
+    for spec in file.mcp {
+        let name = spec.name.clone();
+        let server =
+            McpServer::from_spec(spec).map_err(|e| format!("MCP server '{}': {}", name, e))?;
+        if mcp_servers.iter().any(|s| s.name() == server.name()) {
+            return Err(format!("MCP server '{}' is declared more than once", name));
+        }
+        mcp_servers.push(server);
+    }
+    Ok(ToolsConfig { tools, mcp_servers })
+}
+
+impl ExternalTool {
+    fn from_spec(spec: ToolSpec) -> Result<Self, String> {
+        if !is_valid_name(&spec.name) {
+            return Err(
+                "names must be lowercase letters, digits, '-' or '_', starting with a letter"
+                    .to_string(),
+            );
+        }
+        if spec.description.trim().is_empty() {
+            return Err("description is empty".to_string());
+        }
+
+        let mut arguments = Vec::new();
+        for (i, arg) in spec.argument.into_iter().enumerate() {
+            let main = i == 0;
+            if main && matches!(arg.kind, ArgumentType::Integer) {
+                return Err(format!(
+                    "argument '{}' comes first, so it can't be an integer",
+                    arg.name
+                ));
+            }
+            // Arguments after the first are written as tag attributes
+            if !TOOL_NAME.is_match(&arg.name) {
+                return Err(format!(
+                    "argument '{}' must be named with lowercase letters, digits, '-' or '_'",
+                    arg.name
+                ));
+            }
+            if arguments.iter().any(|a: &Argument| a.name == arg.name) {
+                return Err(format!("argument '{}' is declared more than once", arg.name));
+            }
+            let argument = argument_from_spec(arg)?;
+            // The main argument is the tag body, so it can't be left out
+            arguments.push(if main || argument.required {
+                argument.required()
+            } else {
+                argument
+            });
+        }
+        if arguments.is_empty() {
+            return Err("it needs at least one argument".to_string());
+        }
+
+        let runner = match (spec.http, spec.command) {
+            (Some(http), None) => http_runner(http, &arguments)?,
+            (None, Some(mut command)) => {
+                if command.is_empty() || command[0].trim().is_empty() {
+                    return Err("command is empty".to_string());
+                }
+                let program = command.remove(0);
+                Runner::Command {
+                    program,
+                    args: command,
+                }
+            }
+            _ => return Err("give exactly one of `http` or `command`".to_string()),
+        };
+
+        let timeout_secs = spec.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS);
+        let max_output_bytes = spec.max_output_bytes.unwrap_or(DEFAULT_MAX_OUTPUT_BYTES);
+        if timeout_secs == 0 || max_output_bytes == 0 {
+            return Err("timeout_secs and max_output_bytes must be positive".to_string());
+        }
+
+        Ok(Self {
+            title: spec.title.unwrap_or_else(|| spec.name.clone()),
+            example: spec.example.unwrap_or_else(|| arguments[0].name.clone()),
+            name: spec.name,
+            description: spec.description,
+            arguments,
+            timeout: Duration::from_secs(timeout_secs),
+            max_output_bytes,
+            runner,
+        })
+    }
+
+    /// The call's arguments as the JSON object sent to the tool.
+    fn input(call: &ToolCall) -> Value {
+        let object: Map<String, Value> = call.arguments.iter().cloned().collect();
+        Value::Object(object)
+    }
+
+    async fn run(&self, call: &ToolCall) -> Result<Output, AppError> {
+        match &self.runner {
+            Runner::Http {
+                client,
+                method,
+                url,
+                headers,
+            } => {
+                let get = method == Method::GET;
+                let url = request_url(url, call, get)?;
+                let mut request = client.request(method.clone(), url.clone());
+                for (name, value) in headers {
+                    request = request.header(name, value);
+                }
+                if !get {
+                    request = request.json(&Self::input(call));
+                }
+
+                debug!("Calling {} {}", method, url);
+                let mut response = request
+                    .send()
+                    .await
+                    .map_err(|e| AppError::Tool(format!("Request failed: {}", e)))?;
+                let status = response.status();
+
+                let mut body = Output::new(self.max_output_bytes);
+                while let Some(chunk) = response
+                    .chunk()
+                    .await
+                    .map_err(|e| AppError::Tool(format!("Reading the response failed: {}", e)))?
+                {
+                    if !body.push(&chunk) {
+                        break;
+                    }
+                }
+
+                if !status.is_success() {
+                    return Err(AppError::Tool(format!(
+                        "HTTP {}: {}",
+                        status,
+                        truncate(&body.text(), MAX_STDERR_BYTES)
+                    )));
+                }
+                Ok(body)
+            }
+            Runner::Command { program, args } => {
+                let mut child = Command::new(program)
+                    .args(args)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| AppError::Tool(format!("Cannot run {}: {}", program, e)))?;
+
+                let input = Self::input(call).to_string();
+                let mut stdin = child.stdin.take().expect("stdin is piped");
+                let stdout = child.stdout.take().expect("stdout is piped");
+                let stderr = child.stderr.take().expect("stderr is piped");
+
+                // Write and read together so a command that answers before
+                // reading all of its input can't deadlock
+                let write = async move {
+                    // The command may not read its input at all
+                    let _ = stdin.write_all(input.as_bytes()).await;
+                };
+                let (_, stdout, stderr) = tokio::join!(
+                    write,
+                    read_capped(stdout, self.max_output_bytes),
+                    read_capped(stderr, MAX_STDERR_BYTES),
+                );
+                let status = child
+                    .wait()
+                    .await
+                    .map_err(|e| AppError::Tool(format!("Waiting for {} failed: {}", program, e)))?;
+                let stdout = stdout.map_err(|e| AppError::Tool(e.to_string()))?;
+
+                if !status.success() {
+                    let stderr = stderr.map(|s| s.text()).unwrap_or_default();
+                    let detail = if stderr.is_empty() {
+                        String::new()
+                    } else {
+                        format!(": {}", stderr)
+                    };
+                    return Err(AppError::Tool(format!("{} {}{}", program, status, detail)));
+                }
+                Ok(stdout)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for ExternalTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn arguments(&self) -> &[Argument] {
+        &self.arguments
+    }
+
+    fn example(&self) -> &str {
+        &self.example
+    }
+
+    async fn execute(&self, call: &ToolCall) -> Result<String, AppError> {
+        let output = tokio::time::timeout(self.timeout, self.run(call))
+            .await
+            .map_err(|_| {
+                AppError::Tool(format!("Timed out after {}s", self.timeout.as_secs()))
+            })??;
+
+        let mut text = output.text();
+        if output.truncated {
+            text.push_str(&format!(
+                "\n[Output truncated at {} bytes]",
+                self.max_output_bytes
+            ));
+        }
+        if text.is_empty() {
+            text.push_str("(no output)");
+        }
+        Ok(text)
+    }
+}
+
+fn argument_from_spec(spec: ArgumentSpec) -> Result<Argument, String> {
+    let argument = match spec.kind {
+        ArgumentType::Text => Argument::text(&spec.name, &spec.description),
+        ArgumentType::Token => Argument::token(&spec.name, &spec.description),
+        ArgumentType::Integer => {
+            let (Some(min), Some(max)) = (spec.min, spec.max) else {
+                return Err(format!("integer argument '{}' needs `min` and `max`", spec.name));
+            };
+            if min > max {
+                return Err(format!("argument '{}' has `min` above `max`", spec.name));
+            }
+            Argument::integer(&spec.name, &spec.description, min, max)
+        }
+        ArgumentType::Choice => {
+            let choices: Vec<String> = spec
+                .choices
+                .unwrap_or_default()
+                .iter()
+                .map(|c| c.trim().to_string())
+                .collect();
+            if choices.is_empty() || choices.iter().any(String::is_empty) {
+                return Err(format!("choice argument '{}' needs `choices`", spec.name));
+            }
+            let choices: Vec<&str> = choices.iter().map(String::as_str).collect();
+            Argument::choice(&spec.name, &spec.description, &choices)
+        }
+    };
+    Ok(if spec.required {
+        argument.required()
+    } else {
+        argument
+    })
+}
+
+fn http_runner(spec: HttpSpec, arguments: &[Argument]) -> Result<Runner, String> {
+    let method = match spec.method.to_ascii_uppercase().as_str() {
+        "GET" => Method::GET,
+        "POST" => Method::POST,
+        other => return Err(format!("method must be GET or POST, not {}", other)),
+    };
+
+    for caps in PLACEHOLDER.captures_iter(&spec.url) {
+        if !arguments.iter().any(|arg| arg.name == caps[1]) {
+            return Err(format!("the URL uses {{{}}}, which is not an argument", &caps[1]));
+        }
+    }
+    // Check the URL with every placeholder filled in
+    let sample = PLACEHOLDER.replace_all(&spec.url, "x");
+    let parsed = Url::parse(&sample).map_err(|e| format!("invalid URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err("the URL must be http or https".to_string());
+    }
+
+    let mut headers = Vec::new();
+    for (name, value) in spec.headers {
+        headers.push((name, expand_env(&value)?));
+    }
+
+    let client = Client::builder()
+        .user_agent("Ferret/0.1 (Web research assistant)")
+        .build()
+        .map_err(|e| format!("cannot create HTTP client: {}", e))?;
+
+    Ok(Runner::Http {
+        client,
+        method,
+        url: spec.url,
+        headers,
+    })
+}
+
+/// Whether `name` can name a tool in the tools file.
+pub(super) fn is_valid_name(name: &str) -> bool {
+    TOOL_NAME.is_match(name)
+}
+
+/// Replace `${VAR}` with the environment variable's value.
+pub(super) fn expand_env(value: &str) -> Result<String, String> {
+    let mut missing = None;
+    let expanded = ENV_REFERENCE.replace_all(value, |caps: &Captures| {
+        std::env::var(&caps[1]).unwrap_or_else(|_| {
+            missing.get_or_insert_with(|| caps[1].to_string());
+            String::new()
+        })
+    });
+    match missing {
+        Some(var) => Err(format!("environment variable {} is not set", var)),
+        None => Ok(expanded.into_owned()),
+    }
+}
+
+/// The URL with placeholders filled in. With `query`, arguments the
+/// template doesn't use are added as query parameters.
+fn request_url(template: &str, call: &ToolCall, query: bool) -> Result<Url, AppError> {
+    let url = PLACEHOLDER.replace_all(template, |caps: &Captures| {
+        call.arg(&caps[1])
+            .map(|value| utf8_percent_encode(&plain(value), PATH_VALUE).to_string())
+            .unwrap_or_default()
+    });
+    let mut url = Url::parse(&url).map_err(|e| AppError::Tool(format!("Invalid URL: {}", e)))?;
+
+    let unused: Vec<_> = call
+        .arguments
+        .iter()
+        .filter(|(key, _)| !template.contains(&format!("{{{}}}", key)))
+        .collect();
+    if query && !unused.is_empty() {
+        let mut pairs = url.query_pairs_mut();
+        for (key, value) in unused {
+            pairs.append_pair(key, &plain(value));
+        }
+    }
+    Ok(url)
+}
+
+/// An argument value as text, without JSON quoting.
+fn plain(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Output kept up to a size limit.
+struct Output {
+    bytes: Vec<u8>,
+    limit: usize,
+    truncated: bool,
+}
+
+impl Output {
+    fn new(limit: usize) -> Self {
+        Self {
+            bytes: Vec::new(),
+            limit,
+            truncated: false,
+        }
+    }
+
+    /// Keep what fits; returns false once the limit is reached.
+    fn push(&mut self, chunk: &[u8]) -> bool {
+        let room = self.limit - self.bytes.len();
+        if chunk.len() > room {
+            self.bytes.extend_from_slice(&chunk[..room]);
+            self.truncated = true;
+            return false;
+        }
+        self.bytes.extend_from_slice(chunk);
+        true
+    }
+
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes).trim().to_string()
+    }
+}
+
+/// Read `reader` to the end, keeping the first `limit` bytes. The rest is
+/// drained so the writer never blocks on a full pipe.
+async fn read_capped(mut reader: impl AsyncRead + Unpin, limit: usize) -> std::io::Result<Output> {
+    let mut output = Output::new(limit);
+    let mut buffer = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(output);
+        }
+        if !output.truncated {
+            output.push(&buffer[..n]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(tool: &ExternalTool, arguments: Value) -> ToolCall {
+        ToolCall {
+            tool: tool.name.clone(),
+            arguments: tool
+                .arguments
+                .iter()
+                .filter_map(|arg| Some((arg.name.clone(), arguments.get(&arg.name)?.clone())))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_tools_file_is_checked() {
+        let tools = parse(
+            r#"
+            [[tool]]
+            name = "ticket"
+            description = "Look up a ticket"
+            http = { url = "https://tickets.internal/api/{id}", method = "get" }
+
+            [[tool.argument]]
+            name = "id"
+            type = "token"
+
+            [[tool.argument]]
+            name = "fields"
+            type = "choice"
+            choices = ["Summary", "full"]
+            "#,
+        )
+        .unwrap()
+        .tools;
+        let ticket = &tools[0];
+        assert_eq!(ticket.title(), "ticket");
+        assert!(ticket.arguments[0].required);
+        assert!(!ticket.arguments[1].required);
+        assert!(ticket.arguments[1].parse("SUMMARY").is_ok());
+
+        let errors = [
+            ("name = \"Bad Name\"\ndescription = \"x\"\ncommand = [\"true\"]\n[[tool.argument]]\nname = \"q\"", "names must be"),
+            ("name = \"t\"\ndescription = \"x\"\n[[tool.argument]]\nname = \"q\"", "exactly one of"),
+            ("name = \"t\"\ndescription = \"x\"\ncommand = [\"true\"]", "at least one argument"),
+            ("name = \"t\"\ndescription = \"x\"\ncommand = [\"true\"]\n[[tool.argument]]\nname = \"n\"\ntype = \"integer\"\nmin = 1\nmax = 5", "can't be an integer"),
+            ("name = \"t\"\ndescription = \"x\"\nhttp = { url = \"http://svc/{other}\" }\n[[tool.argument]]\nname = \"q\"", "{other}"),
+            ("name = \"t\"\ndescription = \"x\"\nhttp = { url = \"http://svc/\", headers = { A = \"${FERRET_TEST_UNSET_VAR}\" } }\n[[tool.argument]]\nname = \"q\"", "FERRET_TEST_UNSET_VAR is not set"),
+            ("name = \"t\"\ndescription = \"x\"\ncommand = [\"true\"]\nshell = true\n[[tool.argument]]\nname = \"q\"", "unknown field"),
+        ];
+        for (spec, expected) in errors {
+            let err = parse(&format!("[[tool]]\n{}", spec)).err().unwrap();
+            assert!(err.contains(expected), "{} does not mention {}", err, expected);
+        }
+
+        let servers = parse("[[mcp]]\nname = \"git\"\ncommand = [\"mcp-server-git\"]")
+            .unwrap()
+            .mcp_servers;
+        assert_eq!(servers[0].name(), "git");
+
+        let errors = [
+            ("name = \"git\"", "exactly one of"),
+            ("name = \"git\"\ncommand = []", "command is empty"),
+            ("name = \"wiki\"\nurl = \"ftp://wiki/\"", "http or https"),
+            ("name = \"wiki\"\nurl = \"http://wiki/\"\nenv = { A = \"b\" }", "only applies"),
+            ("name = \"git\"\ncommand = [\"a\"]\n[[mcp]]\nname = \"git\"\ncommand = [\"b\"]", "more than once"),
+        ];
+        for (spec, expected) in errors {
+            let err = parse(&format!("[[mcp]]\n{}", spec)).err().unwrap();
+            assert!(err.contains(expected), "{} does not mention {}", err, expected);
+        }
+    }
+
+    #[test]
+    fn test_url_placeholders_are_encoded() {
+        let tools = parse(
+            r#"
+            [[tool]]
+            name = "inventory"
+            description = "Stock levels"
+            http = { url = "http://inventory.internal/items/{item}" }
+
+            [[tool.argument]]
+            name = "item"
+
+            [[tool.argument]]
+            name = "limit"
+            type = "integer"
+            min = 1
+            max = 50
+            "#,
+        )
+        .unwrap()
+        .tools;
+        let call = call(&tools[0], json!({ "item": "blue widget/2", "limit": 5 }));
+        let template = "http://inventory.internal/items/{item}?db=main";
+        assert_eq!(
+            request_url(template, &call, true).unwrap().as_str(),
+            "http://inventory.internal/items/blue%20widget%2F2?db=main&limit=5"
+        );
+        assert_eq!(
+            request_url(template, &call, false).unwrap().as_str(),
+            "http://inventory.internal/items/blue%20widget%2F2?db=main"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_command_tools() {
+        let tools = parse(
+            r#"
+            [[tool]]
+            name = "echo"
+            description = "Echo the input"
+            command = ["cat"]
+            [[tool.argument]]
+            name = "text"
+
+            [[tool]]
+            name = "chatty"
+            description = "Too much output"
+            command = ["sh", "-c", "yes ferret | head -c 100000"]
+            max_output_bytes = 12
+            [[tool.argument]]
+            name = "text"
+
+            [[tool]]
+            name = "broken"
+            description = "Fails"
+            command = ["sh", "-c", "echo 'no such team' >&2; exit 3"]
+            [[tool.argument]]
+            name = "text"
+
+            [[tool]]
+            name = "slow"
+            description = "Never answers"
+            command = ["sleep", "30"]
+            timeout_secs = 1
+            [[tool.argument]]
+            name = "text"
+            "#,
+        )
+        .unwrap()
+        .tools;
+        let run = |i: usize| {
+            let tool = &tools[i];
+            let call = call(tool, json!({ "text": "hello" }));
+            async move { tool.execute(&call).await }
+        };
+
+        assert_eq!(run(0).await.unwrap(), r#"{"text":"hello"}"#);
+        assert_eq!(
+            run(1).await.unwrap(),
+            "ferret\nferre\n[Output truncated at 12 bytes]"
+        );
+        let err = run(2).await.unwrap_err().to_string();
+        assert!(err.contains("exit status: 3") && err.contains("no such team"), "{}", err);
+        assert_eq!(run(3).await.unwrap_err().to_string(), "Tool error: Timed out after 1s");
+    }
+}
@@ -1,28 +1,41 @@
-use reqwest::Client;
-use scraper::{Html, Selector};
+use async_trait::async_trait;
+use reqwest::{redirect, Client, Url};
+use std::sync::Arc;
 use std::time::Duration;
 use tracing::debug;
 
 use crate::error::AppError;
 
+use super::content::{self, ContentKind};
+use super::ssrf::{GuardedResolver, UrlGuard};
+use super::tool::{result_block, Argument, Tool, ToolCall};
+
 const FETCH_TIMEOUT_SECS: u64 = 10;
 const MAX_CONTENT_SIZE: usize = 1_000_000; // 1MB
+const MAX_PDF_SIZE: usize = 10_000_000; // 10MB; PDFs carry fonts and images
 const MAX_OUTPUT_CHARS: usize = 4000;
+const MAX_REDIRECTS: usize = 5;
 
 #[derive(Clone)]
 pub struct PageFetcher {
     client: Client,
+    guard: Arc<UrlGuard>,
 }
 
 impl PageFetcher {
-    pub fn new() -> Self {
+    pub fn new(guard: UrlGuard) -> Self {
+        let guard = Arc::new(guard);
+
+        // Redirects are followed by hand so every hop goes through the guard
         let client = Client::builder()
             .timeout(Duration::from_secs(FETCH_TIMEOUT_SECS))
             .user_agent("Ferret/0.1 (Web research assistant)")
+            .redirect(redirect::Policy::none())
+            .dns_resolver(Arc::new(GuardedResolver::new(guard.clone())))
             .build()
             .expect("Failed to create HTTP client");
 
-        Self { client }
+        Self { client, guard }
     }
 
     pub async fn fetch(&self, url: &str) -> Result<String, AppError> {
@@ -33,18 +46,49 @@ impl PageFetcher {
             return Err(AppError::PageFetch("Invalid URL: must start with http:// or https://".to_string()));
         }
 
-        let response = self
-            .client
-            .get(url)
-            .send()
-            .await
-            .map_err(|e| {
-                if e.is_timeout() {
-                    AppError::PageFetch(format!("Connection timeout after {} seconds", FETCH_TIMEOUT_SECS))
-                } else {
-                    AppError::PageFetch(e.to_string())
-                }
-            })?;
+        let mut current = Url::parse(url)
+            .map_err(|e| AppError::PageFetch(format!("Invalid URL: {}", e)))?;
+        let mut hops = 0;
+
+        let mut response = loop {
+            self.guard.check(&current).await?;
+
+            let response = self
+                .client
+                .get(current.clone())
+                .send()
+                .await
+                .map_err(|e| {
+                    if e.is_timeout() {
+                        AppError::PageFetch(format!("Connection timeout after {} seconds", FETCH_TIMEOUT_SECS))
+                    } else {
+                        AppError::PageFetch(error_chain(&e))
+                    }
+                })?;
+
+            if !response.status().is_redirection() {
+                break response;
+            }
+
+            hops += 1;
+            if hops > MAX_REDIRECTS {
+                return Err(AppError::PageFetch(format!(
+                    "Too many redirects (max {})",
+                    MAX_REDIRECTS
+                )));
+            }
+
+            let location = response
+                .headers()
+                .get("location")
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| AppError::PageFetch("Redirect without Location header".to_string()))?;
+
+            current = current
+                .join(location)
+                .map_err(|e| AppError::PageFetch(format!("Invalid redirect target: {}", e)))?;
+            debug!("Following redirect to {}", current);
+        };
 
         if !response.status().is_success() {
             return Err(AppError::PageFetch(format!(
@@ -60,145 +104,134 @@ impl PageFetcher {
             .unwrap_or("unknown")
             .to_string();
 
-        // Check content length if available
+        // Until the first bytes have been sniffed the body could still be a
+        // PDF, so a declared length only has to fit the larger cap
         if let Some(len) = response.content_length() {
-            if len as usize > MAX_CONTENT_SIZE {
-                return Err(AppError::PageFetch(format!(
-                    "Content too large: {} bytes (max {})",
-                    len, MAX_CONTENT_SIZE
-                )));
-            }
+            check_size(len as usize, MAX_PDF_SIZE)?;
         }
 
-        let bytes = response
-            .bytes()
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
             .await
-            .map_err(|e| AppError::PageFetch(e.to_string()))?;
-
-        if bytes.len() > MAX_CONTENT_SIZE {
-            return Err(AppError::PageFetch(format!(
-                "Content too large: {} bytes (max {})",
-                bytes.len(),
-                MAX_CONTENT_SIZE
-            )));
+            .map_err(|e| AppError::PageFetch(e.to_string()))?
+        {
+            body.extend_from_slice(&chunk);
+            check_size(body.len(), size_limit(&content_type, &body))?;
         }
 
-        let html = String::from_utf8_lossy(&bytes).to_string();
-        let text = extract_text(&html);
+        let text = content::extract(body, &content_type, &current).await?;
 
         Ok(Self::format_result(url, &content_type, &text))
     }
 
     fn format_result(url: &str, content_type: &str, text: &str) -> String {
-        let truncated = if text.len() > MAX_OUTPUT_CHARS {
-            format!(
+        // Cut on a character boundary; decoded pages are rarely pure ASCII
+        let truncated = match text.char_indices().nth(MAX_OUTPUT_CHARS) {
+            Some((end, _)) => format!(
                 "{}\n\n[Content truncated at {} characters]",
-                &text[..MAX_OUTPUT_CHARS],
+                &text[..end],
                 MAX_OUTPUT_CHARS
-            )
-        } else {
-            text.to_string()
+            ),
+            None => text.to_string(),
         };
 
         format!(
-            "[Tool Result: fetch]\nURL: {}\nContent-Type: {}\nLength: {} characters\n\n{}\n[End Tool Result]",
+            "URL: {}\nContent-Type: {}\nLength: {} characters\n\n{}",
             url,
             content_type,
-            text.len(),
+            text.chars().count(),
             truncated
         )
     }
+}
 
-    pub fn format_error(url: &str, error: &str) -> String {
-        format!(
-            "[Tool Result: fetch]\nURL: {}\nError: {}\n[End Tool Result]",
-            url, error
-        )
+/// Largest body accepted for what has been read of it so far. PDFs are
+/// mostly non-text bytes, so they get more room.
+fn size_limit(content_type: &str, body: &[u8]) -> usize {
+    if ContentKind::detect(content_type, body) == ContentKind::Pdf {
+        MAX_PDF_SIZE
+    } else {
+        MAX_CONTENT_SIZE
+    }
+}
+
+fn check_size(size: usize, max_size: usize) -> Result<(), AppError> {
+    if size > max_size {
+        return Err(AppError::PageFetch(format!(
+            "Content too large: {} bytes (max {})",
+            size, max_size
+        )));
     }
+    Ok(())
 }
 
 impl Default for PageFetcher {
     fn default() -> Self {
-        Self::new()
+        Self::new(UrlGuard::default())
     }
 }
 
-fn extract_text(html: &str) -> String {
-    let document = Html::parse_document(html);
-
-    // Remove script and style elements
-    let _script_selector = Selector::parse("script, style, noscript, nav, footer, header").ok();
-
-    let mut text_parts = Vec::new();
-
-    // Try to find main content areas first
-    let content_selectors = [
-        "article",
-        "main",
-        "[role=\"main\"]",
-        ".content",
-        "#content",
-        ".post-content",
-        ".article-content",
-    ];
-
-    let mut found_main_content = false;
-
-    for selector_str in content_selectors {
-        if let Ok(selector) = Selector::parse(selector_str) {
-            for element in document.select(&selector) {
-                let text = element.text().collect::<Vec<_>>().join(" ");
-                let cleaned = clean_text(&text);
-                if !cleaned.is_empty() && cleaned.len() > 100 {
-                    text_parts.push(cleaned);
-                    found_main_content = true;
-                }
-            }
-        }
-        if found_main_content {
-            break;
+/// The `fetch` tool.
+pub struct FetchTool {
+    fetcher: PageFetcher,
+    arguments: Vec<Argument>,
+}
+
+impl FetchTool {
+    pub fn new(fetcher: PageFetcher) -> Self {
+        Self {
+            fetcher,
+            arguments: vec![Argument::token("url", "Absolute http(s) URL to fetch").required()],
         }
     }
+}
 
-    // Fallback to body if no main content found
-    if !found_main_content {
-        if let Ok(body_selector) = Selector::parse("body") {
-            for element in document.select(&body_selector) {
-                let text = element.text().collect::<Vec<_>>().join(" ");
-                text_parts.push(clean_text(&text));
-            }
-        }
+#[async_trait]
+impl Tool for FetchTool {
+    fn name(&self) -> &str {
+        "fetch"
     }
 
-    // Also try to get the title
-    if let Ok(title_selector) = Selector::parse("title") {
-        if let Some(title_el) = document.select(&title_selector).next() {
-            let title = title_el.text().collect::<String>();
-            if !title.is_empty() {
-                text_parts.insert(0, format!("Title: {}\n", title.trim()));
-            }
-        }
+    fn title(&self) -> &str {
+        "Fetch a web page"
     }
 
-    text_parts.join("\n\n")
-}
+    fn description(&self) -> &str {
+        "Fetch a web page and return its text content, to read a specific URL in full when snippets aren't enough."
+    }
 
-fn clean_text(text: &str) -> String {
-    // Replace multiple whitespace with single space
-    let mut result = String::new();
-    let mut prev_whitespace = false;
+    fn arguments(&self) -> &[Argument] {
+        &self.arguments
+    }
 
-    for c in text.chars() {
-        if c.is_whitespace() {
-            if !prev_whitespace {
-                result.push(' ');
-            }
-            prev_whitespace = true;
-        } else {
-            result.push(c);
-            prev_whitespace = false;
-        }
+    fn example(&self) -> &str {
+        "https://example.com/page"
+    }
+
+    async fn execute(&self, call: &ToolCall) -> Result<String, AppError> {
+        self.fetcher.fetch(call.query()).await
     }
 
-    result.trim().to_string()
+    fn format_error(&self, call: &ToolCall, error: &str) -> String {
+        result_block(
+            self.name(),
+            &format!("URL: {}\nError: {}", call.query(), error),
+        )
+    }
+
+    fn describe(&self, call: &ToolCall) -> String {
+        format!("Read: <{}>", call.query())
+    }
+}
+
+/// Include the underlying cause, e.g. a resolver refusal, in the message.
+fn error_chain(error: &reqwest::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
 }
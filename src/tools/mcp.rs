@@ -0,0 +1,654 @@
+//! Tools from Model Context Protocol servers.
+//!
+//! Servers are declared in the tools file as `[[mcp]]` entries, launched
+//! as a command speaking MCP over stdio or reached over streamable HTTP:
+//!
+//! ```toml
+//! [[mcp]]
+//! name = "git"
+//! command = ["uvx", "mcp-server-git", "--repository", "/srv/repo"]
+//!
+//! [[mcp]]
+//! name = "wiki"
+//! url = "http://wiki.internal:8000/mcp"
+//! headers = { Authorization = "Bearer ${WIKI_TOKEN}" }
+//! tools = ["search_pages", "read_page"]   # optional; all when omitted
+//! ```
+//!
+//! Each server's tools are offered as `<server>_<tool>`, such as
+//! `git_git_status`. A supervisor task per server connects, lists its tools,
+//! follows `tools/list_changed` notifications, pings it, and restarts it
+//! with backoff if it exits or stops answering. While a server is down its
+//! tools are withdrawn.
+
+use async_trait::async_trait;
+use rmcp::model::{
+    CallToolRequestParams, ClientInfo, ClientRequest, Implementation, PingRequest, RawContent,
+    ResourceContents,
+};
+use rmcp::service::{NotificationContext, Peer, RunningService};
+use rmcp::transport::streamable_http_client::StreamableHttpClientTransportConfig;
+use rmcp::transport::{StreamableHttpClientTransport, TokioChildProcess};
+use rmcp::{ClientHandler, RoleClient, ServiceExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{ChildStderr, Command};
+use tokio::sync::oneshot;
+use tracing::{debug, info, warn};
+
+use crate::error::AppError;
+
+use super::external::{expand_env, is_valid_name};
+use super::tool::{truncate, Argument, ArgumentKind, Tool, ToolCall};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 16 * 1024;
+/// How long connecting and listing tools may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+/// A server that ran this long before stopping restarts without backoff
+const STABLE_AFTER: Duration = Duration::from_secs(120);
+
+/// An `[[mcp]]` entry in the tools file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct McpServerSpec {
+    pub(super) name: String,
+    command: Option<Vec<String>>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    url: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    tools: Option<Vec<String>>,
+    timeout_secs: Option<u64>,
+    max_output_bytes: Option<usize>,
+}
+
+enum Transport {
+    Command {
+        program: String,
+        args: Vec<String>,
+        env: Vec<(String, String)>,
+    },
+    Http {
+        url: String,
+        headers: Vec<(String, String)>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerStatus {
+    Starting,
+    Running,
+    /// Stopped or failed to start; waiting to try again
+    Restarting,
+}
+
+/// A server's state as reported by `/health`.
+#[derive(Debug, Serialize)]
+pub struct ServerHealth {
+    pub name: String,
+    pub status: ServerStatus,
+    pub tools: usize,
+    pub restarts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+struct ServerState {
+    status: ServerStatus,
+    tools: Vec<Arc<dyn Tool>>,
+    restarts: u32,
+    last_error: Option<String>,
+    /// Last line the process wrote to stderr
+    last_stderr: Option<String>,
+}
+
+/// A configured MCP server and the tools it currently offers.
+pub struct McpServer {
+    name: String,
+    transport: Transport,
+    allowed: Option<Vec<String>>,
+    timeout: Duration,
+    max_output_bytes: usize,
+    state: Mutex<ServerState>,
+}
+
+impl McpServer {
+    pub fn from_spec(spec: McpServerSpec) -> Result<Self, String> {
+        if !is_valid_name(&spec.name) {
+            return Err(
+                "names must be lowercase letters, digits, '-' or '_', starting with a letter"
+                    .to_string(),
+            );
+        }
+
+        let transport = match (spec.command, spec.url) {
+            (Some(mut command), None) => {
+                if command.is_empty() || command[0].trim().is_empty() {
+                    return Err("command is empty".to_string());
+                }
+                if !spec.headers.is_empty() {
+                    return Err("`headers` only applies to `url` servers".to_string());
+                }
+                let mut env = Vec::new();
+                for (key, value) in spec.env {
+                    env.push((key, expand_env(&value)?));
+                }
+                Transport::Command {
+                    program: command.remove(0),
+                    args: command,
+                    env,
+                }
+            }
+            (None, Some(url)) => {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err("the URL must be http or https".to_string());
+                }
+                if !spec.env.is_empty() {
+                    return Err("`env` only applies to `command` servers".to_string());
+                }
+                let mut headers = Vec::new();
+                for (name, value) in spec.headers {
+                    headers.push((name, expand_env(&value)?));
+                }
+                Transport::Http { url, headers }
+            }
+            _ => return Err("give exactly one of `command` or `url`".to_string()),
+        };
+
+        let timeout_secs = spec.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS);
+        let max_output_bytes = spec.max_output_bytes.unwrap_or(DEFAULT_MAX_OUTPUT_BYTES);
+        if timeout_secs == 0 || max_output_bytes == 0 {
+            return Err("timeout_secs and max_output_bytes must be positive".to_string());
+        }
+
+        Ok(Self {
+            name: spec.name,
+            transport,
+            allowed: spec.tools,
+            timeout: Duration::from_secs(timeout_secs),
+            max_output_bytes,
+            state: Mutex::new(ServerState {
+                status: ServerStatus::Starting,
+                tools: Vec::new(),
+                restarts: 0,
+                last_error: None,
+                last_stderr: None,
+            }),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The tools the server offers now; none while it is down.
+    pub fn tools(&self) -> Vec<Arc<dyn Tool>> {
+        self.state.lock().unwrap().tools.clone()
+    }
+
+    pub fn health(&self) -> ServerHealth {
+        let state = self.state.lock().unwrap();
+        ServerHealth {
+            name: self.name.clone(),
+            status: state.status,
+            tools: state.tools.len(),
+            restarts: state.restarts,
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    /// Start supervising the server. The receiver fires once the first
+    /// attempt to connect has succeeded or failed.
+    pub fn start(self: &Arc<Self>) -> oneshot::Receiver<()> {
+        let (ready, started) = oneshot::channel();
+        tokio::spawn(self.clone().supervise(ready));
+        started
+    }
+
+    async fn supervise(self: Arc<Self>, ready: oneshot::Sender<()>) {
+        let mut ready = Some(ready);
+        let mut delay = MIN_RESTART_DELAY;
+
+        loop {
+            let started = Instant::now();
+            let stopped = match self.connect().await {
+                Ok(service) => {
+                    if let Some(ready) = ready.take() {
+                        let _ = ready.send(());
+                    }
+                    self.run(service).await
+                }
+                Err(e) => e,
+            };
+            if let Some(ready) = ready.take() {
+                let _ = ready.send(());
+            }
+
+            {
+                let mut state = self.state.lock().unwrap();
+                let stopped = match state.last_stderr.take() {
+                    Some(line) => format!("{} ({})", stopped, line),
+                    None => stopped,
+                };
+                warn!("MCP server {}: {}", self.name, stopped);
+                state.status = ServerStatus::Restarting;
+                state.tools.clear();
+                state.last_error = Some(stopped);
+            }
+
+            if started.elapsed() > STABLE_AFTER {
+                delay = MIN_RESTART_DELAY;
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RESTART_DELAY);
+
+            let mut state = self.state.lock().unwrap();
+            state.status = ServerStatus::Starting;
+            state.restarts += 1;
+        }
+    }
+
+    /// Launch or reach the server, initialise the session and list tools.
+    async fn connect(self: &Arc<Self>) -> Result<RunningService<RoleClient, McpClient>, String> {
+        let client = McpClient {
+            server: self.clone(),
+        };
+
+        let connecting = async {
+            let service = match &self.transport {
+                Transport::Command { program, args, env } => {
+                    let mut command = Command::new(program);
+                    command.args(args).envs(env.iter().map(|(k, v)| (k, v)));
+                    let (process, stderr) = TokioChildProcess::builder(command)
+                        .stderr(Stdio::piped())
+                        .spawn()
+                        .map_err(|e| format!("cannot run {}: {}", program, e))?;
+                    if let Some(stderr) = stderr {
+                        tokio::spawn(self.clone().follow_stderr(stderr));
+                    }
+                    client.serve(process).await
+                }
+                Transport::Http { url, headers } => {
+                    let mut custom = HashMap::new();
+                    for (name, value) in headers {
+                        let name = name.parse().map_err(|_| format!("invalid header {}", name))?;
+                        let value = value
+                            .parse()
+                            .map_err(|_| format!("invalid value for header {}", name))?;
+                        custom.insert(name, value);
+                    }
+                    let config =
+                        StreamableHttpClientTransportConfig::with_uri(url.as_str()).custom_headers(custom);
+                    let transport = StreamableHttpClientTransport::from_config(config);
+                    client.serve(transport).await
+                }
+            }
+            .map_err(|e| format!("initialisation failed: {}", e))?;
+
+            self.refresh(service.peer()).await?;
+            Ok(service)
+        };
+
+        tokio::time::timeout(CONNECT_TIMEOUT, connecting)
+            .await
+            .map_err(|_| format!("no answer within {}s", CONNECT_TIMEOUT.as_secs()))?
+    }
+
+    /// Serve until the connection ends or the server stops answering pings,
+    /// returning why it stopped.
+    async fn run(&self, service: RunningService<RoleClient, McpClient>) -> String {
+        let peer = service.peer().clone();
+        {
+            let mut state = self.state.lock().unwrap();
+            state.status = ServerStatus::Running;
+            state.last_error = None;
+        }
+
+        let watchdog = async {
+            loop {
+                tokio::time::sleep(PING_INTERVAL).await;
+                let ping = peer.send_request(ClientRequest::PingRequest(PingRequest {
+                    method: Default::default(),
+                    extensions: Default::default(),
+                }));
+                match tokio::time::timeout(PING_TIMEOUT, ping).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => return format!("ping failed: {}", e),
+                    Err(_) => return "stopped answering pings".to_string(),
+                }
+            }
+        };
+
+        // Dropping the service, when the watchdog gives up, ends the session
+        // and kills a child process
+        tokio::select! {
+            quit = service.waiting() => match quit {
+                Ok(reason) => format!("connection ended ({:?})", reason),
+                Err(e) => format!("connection failed: {}", e),
+            },
+            reason = watchdog => reason,
+        }
+    }
+
+    /// Re-list the server's tools.
+    async fn refresh(&self, peer: &Peer<RoleClient>) -> Result<(), String> {
+        let listed = peer
+            .list_all_tools()
+            .await
+            .map_err(|e| format!("listing tools failed: {}", e))?;
+
+        let tools: Vec<Arc<dyn Tool>> = listed
+            .into_iter()
+            .filter(|tool| {
+                self.allowed
+                    .as_ref()
+                    .is_none_or(|allowed| allowed.iter().any(|name| name == tool.name.as_ref()))
+            })
+            .map(|tool| Arc::new(self.tool(tool, peer.clone())) as Arc<dyn Tool>)
+            .collect();
+
+        info!("MCP server {} offers {} tool(s)", self.name, tools.len());
+        self.state.lock().unwrap().tools = tools;
+        Ok(())
+    }
+
+    fn tool(&self, tool: rmcp::model::Tool, peer: Peer<RoleClient>) -> McpTool {
+        let remote = tool.name.to_string();
+        let name = format!("{}_{}", self.name, tool_name(&remote));
+        let description = tool
+            .description
+            .as_deref()
+            .or(tool.title.as_deref())
+            .unwrap_or(&remote)
+            .trim()
+            .to_string();
+        let schema = Value::Object(tool.input_schema.as_ref().clone());
+
+        McpTool {
+            title: tool.title.clone().unwrap_or_else(|| name.clone()),
+            name,
+            remote,
+            description,
+            arguments: arguments_from_schema(&schema),
+            schema,
+            peer,
+            timeout: self.timeout,
+            max_output_bytes: self.max_output_bytes,
+        }
+    }
+
+    /// Log what the process writes to stderr, keeping the last line for
+    /// when it exits.
+    async fn follow_stderr(self: Arc<Self>, stderr: ChildStderr) {
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            debug!("MCP server {}: {}", self.name, line);
+            if !line.trim().is_empty() {
+                self.state.lock().unwrap().last_stderr = Some(truncate(line.trim(), 500).to_string());
+            }
+        }
+    }
+}
+
+/// Our side of an MCP session.
+struct McpClient {
+    server: Arc<McpServer>,
+}
+
+impl ClientHandler for McpClient {
+    async fn on_tool_list_changed(&self, context: NotificationContext<RoleClient>) {
+        if let Err(e) = self.server.refresh(&context.peer).await {
+            warn!("MCP server {}: {}", self.server.name, e);
+        }
+    }
+
+    fn get_info(&self) -> ClientInfo {
+        ClientInfo {
+            client_info: Implementation {
+                name: "ferret".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                ..Implementation::default()
+            },
+            ..ClientInfo::default()
+        }
+    }
+}
+
+/// A tool offered by an MCP server.
+pub struct McpTool {
+    name: String,
+    /// The name the server knows it by
+    remote: String,
+    title: String,
+    description: String,
+    arguments: Vec<Argument>,
+    schema: Value,
+    peer: Peer<RoleClient>,
+    timeout: Duration,
+    max_output_bytes: usize,
+}
+
+#[async_trait]
+impl Tool for McpTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn arguments(&self) -> &[Argument] {
+        &self.arguments
+    }
+
+    fn example(&self) -> &str {
+        self.arguments.first().map_or("", |arg| arg.name.as_str())
+    }
+
+    fn schema(&self) -> Value {
+        self.schema.clone()
+    }
+
+    async fn execute(&self, call: &ToolCall) -> Result<String, AppError> {
+        let arguments: Map<String, Value> = call.arguments.iter().cloned().collect();
+        let request = CallToolRequestParams {
+            meta: None,
+            name: self.remote.clone().into(),
+            arguments: Some(arguments),
+            task: None,
+        };
+
+        let result = tokio::time::timeout(self.timeout, self.peer.call_tool(request))
+            .await
+            .map_err(|_| AppError::Tool(format!("Timed out after {}s", self.timeout.as_secs())))?
+            .map_err(|e| AppError::Tool(e.to_string()))?;
+
+        let mut parts: Vec<String> = result
+            .content
+            .iter()
+            .map(|content| match &content.raw {
+                RawContent::Text(text) => text.text.clone(),
+                RawContent::Image(image) => format!("[image: {}]", image.mime_type),
+                RawContent::Audio(audio) => format!("[audio: {}]", audio.mime_type),
+                RawContent::Resource(resource) => match &resource.resource {
+                    ResourceContents::TextResourceContents { text, .. } => text.clone(),
+                    ResourceContents::BlobResourceContents { uri, .. } => {
+                        format!("[resource: {}]", uri)
+                    }
+                },
+                RawContent::ResourceLink(link) => format!("[{}]({})", link.name, link.uri),
+            })
+            .collect();
+        if parts.is_empty() {
+            if let Some(structured) = &result.structured_content {
+                parts.push(structured.to_string());
+            }
+        }
+
+        let output = parts.join("\n\n");
+        let mut text = truncate(output.trim(), self.max_output_bytes).to_string();
+        if text.len() < output.trim().len() {
+            text.push_str(&format!(
+                "\n[Output truncated at {} bytes]",
+                self.max_output_bytes
+            ));
+        }
+
+        if result.is_error == Some(true) {
+            return Err(AppError::Tool(text));
+        }
+        if text.is_empty() {
+            text.push_str("(no output)");
+        }
+        Ok(text)
+    }
+}
+
+/// Tool names as they can be written in tags.
+fn tool_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Arguments for an MCP tool's input schema. The main argument, written as
+/// the tag body, is the first required string, else the first required
+/// argument, else the first string.
+fn arguments_from_schema(schema: &Value) -> Vec<Argument> {
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+
+    let mut arguments: Vec<Argument> = properties
+        .iter()
+        .map(|(name, property)| {
+            let argument = argument_from_property(name, property);
+            if required.contains(&name.as_str()) {
+                argument.required()
+            } else {
+                argument
+            }
+        })
+        .collect();
+
+    let is_string = |arg: &Argument| !matches!(arg.kind, ArgumentKind::Integer { .. } | ArgumentKind::Json(_));
+    let main = arguments
+        .iter()
+        .position(|arg| arg.required && is_string(arg))
+        .or_else(|| arguments.iter().position(|arg| arg.required))
+        .or_else(|| arguments.iter().position(is_string));
+    if let Some(main) = main {
+        let argument = arguments.remove(main);
+        arguments.insert(0, argument);
+    }
+    arguments
+}
+
+fn argument_from_property(name: &str, property: &Value) -> Argument {
+    let description = property
+        .get("description")
+        .or_else(|| property.get("title"))
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    // `"type": ["string", "null"]` is an optional string
+    let kind = match property.get("type") {
+        Some(Value::String(kind)) => Some(kind.as_str()),
+        Some(Value::Array(kinds)) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|kind| *kind != "null"),
+        _ => None,
+    };
+    let choices: Option<Vec<&str>> = property
+        .get("enum")
+        .and_then(Value::as_array)
+        .and_then(|values| values.iter().map(Value::as_str).collect());
+
+    match (kind, choices) {
+        (Some("string"), Some(choices)) if !choices.is_empty() => {
+            Argument::choice(name, description, &choices)
+        }
+        (Some("string"), _) => Argument::verbatim(name, description),
+        (Some("integer"), _) => Argument::integer(
+            name,
+            description,
+            property.get("minimum").and_then(Value::as_i64).unwrap_or(i64::MIN),
+            property.get("maximum").and_then(Value::as_i64).unwrap_or(i64::MAX),
+        ),
+        _ => Argument::json(name, description, property.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_arguments_from_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "max_count": { "type": "integer", "minimum": 1, "description": "How many commits" },
+                "branch": { "type": ["string", "null"] },
+                "repo_path": { "type": "string", "description": "Path to the repository" },
+                "order": { "type": "string", "enum": ["Newest", "Oldest"] },
+                "paths": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["max_count", "repo_path"]
+        });
+        let arguments = arguments_from_schema(&schema);
+        let names: Vec<&str> = arguments.iter().map(|arg| arg.name.as_str()).collect();
+        assert_eq!(names, ["repo_path", "branch", "max_count", "order", "paths"]);
+        assert!(arguments[0].required && arguments[2].required && !arguments[1].required);
+
+        // Strings are passed on as written; other values are checked
+        assert_eq!(arguments[0].parse(" /srv/my repo\n").unwrap(), json!(" /srv/my repo\n"));
+        assert_eq!(arguments[2].parse("0").unwrap_err(), "max_count must be a whole number from 1 to 9223372036854775807, not \"0\"");
+        assert_eq!(arguments[3].parse("oldest").unwrap(), json!("Oldest"));
+        assert_eq!(arguments[4].parse(r#"["src", "docs"]"#).unwrap(), json!(["src", "docs"]));
+        assert!(arguments[4].parse("src").is_err());
+        assert_eq!(arguments[4].tag_hint(), "paths=\"…\"");
+
+        // Only optional numbers: the first number becomes the tag body
+        let arguments = arguments_from_schema(&json!({
+            "properties": { "limit": { "type": "integer" }, "verbose": { "type": "boolean" } }
+        }));
+        assert_eq!(arguments[0].name, "limit");
+        assert!(arguments_from_schema(&json!({ "type": "object" })).is_empty());
+    }
+
+    #[test]
+    fn test_tool_names_fit_tags() {
+        assert_eq!(tool_name("git.status"), "git_status");
+        assert_eq!(tool_name("read-file"), "read-file");
+    }
+}
@@ -0,0 +1,177 @@
+//! Ferret's own web tools served over the Model Context Protocol, so other
+//! agents can search and read pages through the same provider, SSRF guard
+//! and text extraction as the chat.
+//!
+//! `ferret mcp` serves them over stdio; with `MCP_ENDPOINT=true` the web
+//! server also serves them over streamable HTTP at `/mcp`.
+//!
+//! Results are not cached; each call searches or fetches afresh, as in chat.
+//! `/mcp` is unauthenticated like the rest of the web server, so it should
+//! only be enabled where the bind address is trusted.
+
+use rmcp::model::{
+    CallToolRequestParams, CallToolResult, Content, Implementation, ListToolsResult,
+    PaginatedRequestParams, ServerCapabilities, ServerInfo,
+};
+use rmcp::service::RequestContext;
+use rmcp::transport::streamable_http_server::session::local::LocalSessionManager;
+use rmcp::transport::streamable_http_server::{StreamableHttpServerConfig, StreamableHttpService};
+use rmcp::{ErrorData as McpError, RoleServer, ServerHandler, ServiceExt};
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{debug, error, info};
+
+use crate::ollama::types::{OllamaFunctionCall, OllamaToolCall};
+
+use super::parser::parse_native_call;
+use super::Tool;
+
+/// Built-in tools offered to MCP clients.
+pub const SERVED_TOOLS: &[&str] = &["search", "fetch"];
+
+/// The MCP server side of Ferret.
+#[derive(Clone)]
+pub struct WebToolsService {
+    tools: Arc<Vec<Arc<dyn Tool>>>,
+}
+
+impl WebToolsService {
+    pub fn new(tools: Vec<Arc<dyn Tool>>) -> Self {
+        Self {
+            tools: Arc::new(tools),
+        }
+    }
+
+    /// Serve MCP on stdin and stdout until the client disconnects.
+    pub async fn serve_stdio(self) -> Result<(), String> {
+        let service = self
+            .serve(rmcp::transport::stdio())
+            .await
+            .map_err(|e| format!("MCP initialisation failed: {}", e))?;
+        info!("Serving MCP on stdio");
+        service
+            .waiting()
+            .await
+            .map(|reason| debug!("MCP client left: {:?}", reason))
+            .map_err(|e| e.to_string())
+    }
+
+    /// A tower service answering MCP's streamable HTTP transport.
+    pub fn http_service(self) -> StreamableHttpService<Self, LocalSessionManager> {
+        StreamableHttpService::new(
+            move || Ok(self.clone()),
+            Arc::new(LocalSessionManager::default()),
+            StreamableHttpServerConfig::default(),
+        )
+    }
+
+    fn describe(tool: &dyn Tool) -> rmcp::model::Tool {
+        let schema = match tool.schema() {
+            Value::Object(schema) => schema,
+            _ => Default::default(),
+        };
+        rmcp::model::Tool {
+            title: Some(tool.title().to_string()),
+            ..rmcp::model::Tool::new(tool.name().to_string(), tool.description().to_string(), schema)
+        }
+    }
+}
+
+impl ServerHandler for WebToolsService {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            server_info: Implementation {
+                name: "ferret".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                ..Implementation::default()
+            },
+            instructions: Some(
+                "Search the web and read pages as text. Private and internal addresses are refused."
+                    .to_string(),
+            ),
+            ..ServerInfo::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult {
+            tools: self.tools.iter().map(|tool| Self::describe(tool.as_ref())).collect(),
+            ..ListToolsResult::default()
+        })
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let name = request.name.as_ref();
+        let Some(tool) = self.tools.iter().find(|tool| tool.name() == name) else {
+            return Err(McpError::invalid_params(format!("There is no `{}` tool", name), None));
+        };
+
+        // Arguments are checked exactly as for a native call from the model
+        let call = OllamaToolCall {
+            function: OllamaFunctionCall {
+                name: name.to_string(),
+                arguments: Value::Object(request.arguments.unwrap_or_default()),
+            },
+        };
+        let call = match parse_native_call(&call, &self.tools) {
+            Ok(call) => call,
+            Err(malformed) => {
+                return Ok(CallToolResult::error(vec![Content::text(malformed.reason)]));
+            }
+        };
+
+        debug!("MCP client called {}: {}", name, call.query());
+        match tool.execute(&call).await {
+            Ok(output) => Ok(CallToolResult::success(vec![Content::text(output)])),
+            Err(e) => {
+                error!("{} failed for an MCP client: {}", name, e);
+                Ok(CallToolResult::error(vec![Content::text(e.to_string())]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::RawContent;
+
+    #[tokio::test]
+    async fn test_client_sees_tools_behind_ssrf_guard() {
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        let service = WebToolsService::new(crate::tools::test_tools());
+        let (server, client) = tokio::join!(service.serve(server_io), ().serve(client_io));
+        let (_server, client) = (server.unwrap(), client.unwrap());
+
+        let tools = client.list_all_tools().await.unwrap();
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_ref()).collect();
+        assert_eq!(names, SERVED_TOOLS);
+
+        let mut arguments = serde_json::Map::new();
+        arguments.insert("url".to_string(), "http://127.0.0.1:9/admin".into());
+        let result = client
+            .call_tool(CallToolRequestParams {
+                meta: None,
+                name: "fetch".into(),
+                arguments: Some(arguments),
+                task: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result.is_error, Some(true));
+        let RawContent::Text(text) = &result.content[0].raw else {
+            panic!("expected text content");
+        };
+        assert!(text.text.contains("Blocked: 127.0.0.1"), "{}", text.text);
+    }
+}
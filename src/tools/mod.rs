@@ -1,7 +1,32 @@
+pub mod content;
 pub mod executor;
+pub mod external;
 pub mod fetch;
+pub mod mcp;
+pub mod mcp_service;
 pub mod parser;
+pub mod readability;
 pub mod search;
+pub mod ssrf;
+pub mod tool;
 
 pub use executor::ToolExecutor;
 pub use parser::parse_tool_calls;
+pub use tool::{Tool, ToolCall};
+
+/// The built-in tools with default settings, for tests. Built once, as
+/// each search client loads its TLS roots.
+#[cfg(test)]
+pub fn test_tools() -> Vec<std::sync::Arc<dyn Tool>> {
+    use std::sync::{Arc, OnceLock};
+
+    static TOOLS: OnceLock<Vec<Arc<dyn Tool>>> = OnceLock::new();
+    TOOLS
+        .get_or_init(|| {
+            vec![
+                Arc::new(search::SearchTool::new(Arc::new(search::DuckDuckGoClient::new()))),
+                Arc::new(fetch::FetchTool::new(fetch::PageFetcher::default())),
+            ]
+        })
+        .clone()
+}
@@ -1,104 +1,648 @@
+//! Tool calls written into the reply text in tag mode.
+//!
+//! A call is `<name attr="value">body</name>` for any registered tool, and
+//! the body may wrap across lines. The reply is scanned once from the
+//! start, so calls come back in the order they were written whichever tool
+//! they use. Markdown code (fenced blocks and inline spans) and injected tool
+//! results are skipped, so examples of the syntax aren't run. Markup that
+//! opens a call but can't be used, such as a tag that is never closed, comes
+//! back as a `MalformedCall` so the model can be told what went wrong.
+
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde_json::Value;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::ollama::types::OllamaToolCall;
+
+use super::tool::{Tool, ToolCall};
+
+const RESULT_START: &str = "[Tool Result: ";
+const RESULT_END: &str = "[End Tool Result]";
 
 lazy_static! {
-    static ref SEARCH_PATTERN: Regex =
-        Regex::new(r"<search>(.*?)</search>").unwrap();
-    static ref FETCH_PATTERN: Regex =
-        Regex::new(r"<fetch>(.*?)</fetch>").unwrap();
+    /// Tool output injected into the conversation in tag mode
+    pub static ref TOOL_RESULT_BLOCK: Regex =
+        Regex::new(r"(?s)\[Tool Result: [^\]]+\].*?\[End Tool Result\]").unwrap();
+}
+
+/// Markup that opened a tool call but can't be run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MalformedCall {
+    pub tool: String,
+    /// What is wrong, phrased for the model
+    pub reason: String,
+}
+
+pub type ParsedCall = Result<ToolCall, MalformedCall>;
+
+/// Every tool call in `text`, in the order written.
+pub fn parse_tool_calls(text: &str, tools: &[Arc<dyn Tool>]) -> Vec<ParsedCall> {
+    scan(text, tools).into_iter().map(|(_, call)| call).collect()
+}
+
+/// Check a structured call from native tool mode. Arguments the tool
+/// doesn't take are ignored.
+pub fn parse_native_call(call: &OllamaToolCall, tools: &[Arc<dyn Tool>]) -> ParsedCall {
+    let name = call.function.name.as_str();
+    let malformed = |reason: String| MalformedCall {
+        tool: name.to_string(),
+        reason,
+    };
+    let tool = find_tool(tools, name)
+        .ok_or_else(|| malformed(format!("there is no `{}` tool", name)))?;
+
+    // Some models send the arguments object JSON-encoded as a string
+    let arguments = match &call.function.arguments {
+        Value::String(s) => serde_json::from_str(s).unwrap_or(Value::Null),
+        other => other.clone(),
+    };
+    // Values are checked as written in a tag, so numbers are accepted as
+    // strings and the other way round
+    let values: Vec<(String, String)> = tool
+        .arguments()
+        .iter()
+        .filter_map(|arg| {
+            let value = match arguments.get(&arg.name)? {
+                Value::Null => return None,
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            Some((arg.name.clone(), value)).filter(|(_, v)| !v.trim().is_empty())
+        })
+        .collect();
+
+    check_arguments(tool.as_ref(), &values).map_err(malformed)
 }
 
-#[derive(Debug, Clone)]
-pub enum ToolCall {
-    Search { query: String },
-    Fetch { url: String },
+/// Text meant for the reader: tool tags and injected tool results removed.
+pub fn strip_tool_markup(text: &str, tools: &[Arc<dyn Tool>]) -> String {
+    let mut kept = String::new();
+    let mut last = 0;
+    for (span, _) in scan(text, tools) {
+        kept.push_str(&text[last..span.start]);
+        last = span.end;
+    }
+    kept.push_str(&text[last..]);
+
+    TOOL_RESULT_BLOCK.replace_all(&kept, "").trim().to_string()
 }
 
-impl ToolCall {
-    pub fn name(&self) -> &'static str {
-        match self {
-            ToolCall::Search { .. } => "search",
-            ToolCall::Fetch { .. } => "fetch",
+/// Whether `c` can follow a tool name in an opening tag.
+pub fn ends_tag_name(c: char) -> bool {
+    c.is_whitespace() || c == '>' || c == '/'
+}
+
+fn find_tool<'a>(tools: &'a [Arc<dyn Tool>], name: &str) -> Option<&'a Arc<dyn Tool>> {
+    tools.iter().find(|tool| tool.name() == name)
+}
+
+/// Tool calls in `text` with the byte range of their markup.
+fn scan(text: &str, tools: &[Arc<dyn Tool>]) -> Vec<(Range<usize>, ParsedCall)> {
+    let mut calls = Vec::new();
+    walk_prose(text, |pos| {
+        let (end, call) = parse_call_at(text, pos, tools)?;
+        calls.push((pos..end, call));
+        Some(end)
+    });
+    calls
+}
+
+/// Walk `text` outside Markdown code and injected tool results, calling
+/// `visit` at each `<` with its position. `visit` returns where to carry
+/// on from, or `None` to step past the `<`.
+///
+/// Returns where the first inline code span opens that is still unclosed
+/// at the end of `text`, within a paragraph that hasn't ended: whether it
+/// is code depends on text still to come.
+pub fn walk_prose(text: &str, mut visit: impl FnMut(usize) -> Option<usize>) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut open_span = None;
+    // Marker character and length of the open code fence
+    let mut fence: Option<(char, usize)> = None;
+    let mut pos = 0;
+
+    while pos < text.len() {
+        if pos == 0 || bytes[pos - 1] == b'\n' {
+            let line_end = text[pos..].find('\n').map_or(text.len(), |i| pos + i);
+            let marker = fence_at(&text[pos..line_end]);
+            match (fence, marker) {
+                (None, Some((marker, len, _))) => fence = Some((marker, len)),
+                (Some((open, open_len)), Some((marker, len, rest)))
+                    if marker == open && len >= open_len && rest.trim().is_empty() =>
+                {
+                    fence = None
+                }
+                _ => {}
+            }
+            if fence.is_some() || marker.is_some() {
+                pos = (line_end + 1).min(text.len());
+                continue;
+            }
         }
+
+        match bytes[pos] {
+            b'`' => {
+                let after = pos + backtick_run(&text[pos..]);
+                let next = skip_code_span(text, pos);
+                if next == after && !text[after..].contains("\n\n") {
+                    open_span.get_or_insert(pos);
+                }
+                pos = next;
+            }
+            b'[' if text[pos..].starts_with(RESULT_START) => {
+                pos = text[pos..]
+                    .find(RESULT_END)
+                    .map_or(text.len(), |i| pos + i + RESULT_END.len());
+            }
+            b'<' => pos = visit(pos).unwrap_or(pos + 1),
+            _ => pos += text[pos..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    open_span
+}
+
+/// A code fence line: its marker, the marker's length and what follows.
+fn fence_at(line: &str) -> Option<(char, usize, &str)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
     }
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.len() - trimmed.trim_start_matches(marker).len();
+    (len >= 3).then(|| (marker, len, &trimmed[len..]))
+}
 
-    pub fn query(&self) -> &str {
-        match self {
-            ToolCall::Search { query } => query,
-            ToolCall::Fetch { url } => url,
+/// Position after the inline code span opening at `pos`. A span must close
+/// within its paragraph; otherwise only the backticks are skipped.
+fn skip_code_span(text: &str, pos: usize) -> usize {
+    let ticks = backtick_run(&text[pos..]);
+    let after = pos + ticks;
+    let paragraph_end = text[after..].find("\n\n").map_or(text.len(), |i| after + i);
+
+    let mut search = after;
+    while let Some(i) = text[search..paragraph_end].find('`') {
+        let start = search + i;
+        let run = backtick_run(&text[start..]);
+        if run == ticks {
+            return start + run;
         }
+        search = start + run;
     }
+    after
 }
 
-pub fn parse_tool_calls(text: &str) -> Vec<ToolCall> {
-    let mut calls = Vec::new();
+fn backtick_run(text: &str) -> usize {
+    text.len() - text.trim_start_matches('`').len()
+}
+
+/// The tool whose opening tag starts at `pos`.
+fn tag_tool_at<'a>(
+    text: &str,
+    pos: usize,
+    tools: &'a [Arc<dyn Tool>],
+) -> Option<&'a Arc<dyn Tool>> {
+    let rest = text[pos..].strip_prefix('<')?;
+    tools.iter().find(|tool| {
+        rest.strip_prefix(tool.name())
+            .is_some_and(|after| after.chars().next().is_none_or(ends_tag_name))
+    })
+}
+
+/// Parse the call whose opening tag starts at `pos`, returning where its
+/// markup ends. An unclosed call runs up to the next opening tag.
+fn parse_call_at(
+    text: &str,
+    pos: usize,
+    tools: &[Arc<dyn Tool>],
+) -> Option<(usize, ParsedCall)> {
+    let tool = tag_tool_at(text, pos, tools)?;
+    let name = tool.name();
+
+    let opened = parse_attributes(text, pos + 1 + name.len(), name);
+    let body_start = match &opened {
+        Ok((end, _)) => *end,
+        Err(_) => text[pos..].find('>').map_or(text.len(), |i| pos + i + 1),
+    };
+    let next_tag = next_opening_tag(text, body_start, tools);
+    let close = closing_tag(text, body_start, name)
+        .filter(|(start, _)| next_tag.is_none_or(|next| *start < next));
+    let end = match close {
+        Some((_, end)) => end,
+        None => next_tag.unwrap_or(text.len()),
+    };
+
+    let call = opened
+        .and_then(|(_, attributes)| {
+            let (body_end, _) =
+                close.ok_or_else(|| format!("<{}> is never closed; end it with </{}>", name, name))?;
+            check_tag(tool.as_ref(), &text[body_start..body_end], attributes)
+        })
+        .map_err(|reason| MalformedCall {
+            tool: name.to_string(),
+            reason,
+        });
 
-    for cap in SEARCH_PATTERN.captures_iter(text) {
-        if let Some(query) = cap.get(1) {
-            let query_str = query.as_str().trim();
-            if !query_str.is_empty() {
-                calls.push(ToolCall::Search {
-                    query: query_str.to_string(),
-                });
+    Some((end, call))
+}
+
+/// Attributes of an opening tag from `from` up to its `>`, and the
+/// position after it.
+fn parse_attributes(
+    text: &str,
+    from: usize,
+    name: &str,
+) -> Result<(usize, Vec<(String, String)>), String> {
+    let mut attributes = Vec::new();
+    let mut rest = &text[from..];
+
+    loop {
+        rest = rest.trim_start();
+        let Some(c) = rest.chars().next() else {
+            return Err(format!("the <{}> tag is never closed with `>`", name));
+        };
+        match c {
+            '>' => return Ok((text.len() - rest.len() + 1, attributes)),
+            '/' => return Err(format!("<{}/> has no content", name)),
+            c if is_attribute_char(c) => {
+                let key_len = rest.find(|c| !is_attribute_char(c)).unwrap_or(rest.len());
+                let (key, after) = rest.split_at(key_len);
+                let Some(after) = after.trim_start().strip_prefix('=') else {
+                    return Err(format!("attribute `{}` has no value", key));
+                };
+                let after = after.trim_start();
+                let (value, after) = match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let close = after[1..].find(quote).ok_or_else(|| {
+                            format!("the value of `{}` is missing its closing quote", key)
+                        })?;
+                        (&after[1..1 + close], &after[close + 2..])
+                    }
+                    _ => {
+                        let len = after
+                            .find(|c: char| c.is_whitespace() || c == '>')
+                            .unwrap_or(after.len());
+                        after.split_at(len)
+                    }
+                };
+                attributes.push((key.to_ascii_lowercase(), value.to_string()));
+                rest = after;
             }
+            other => return Err(format!("unexpected `{}` in the <{}> tag", other, name)),
         }
     }
+}
 
-    for cap in FETCH_PATTERN.captures_iter(text) {
-        if let Some(url) = cap.get(1) {
-            let url_str = url.as_str().trim();
-            if !url_str.is_empty() {
-                calls.push(ToolCall::Fetch {
-                    url: url_str.to_string(),
-                });
-            }
+fn is_attribute_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+fn next_opening_tag(text: &str, from: usize, tools: &[Arc<dyn Tool>]) -> Option<usize> {
+    text[from..]
+        .match_indices('<')
+        .map(|(i, _)| from + i)
+        .find(|&i| tag_tool_at(text, i, tools).is_some())
+}
+
+/// Start and end of the first `</name>` from `from`.
+fn closing_tag(text: &str, from: usize, name: &str) -> Option<(usize, usize)> {
+    let opening = format!("</{}", name);
+    let mut search = from;
+
+    while let Some(i) = text[search..].find(&opening) {
+        let start = search + i;
+        let after = start + opening.len();
+        let rest = text[after..].trim_start();
+        if rest.starts_with('>') {
+            return Some((start, text.len() - rest.len() + 1));
         }
+        search = after;
     }
+    None
+}
 
-    calls
+/// Check a tag's body, which is the tool's main argument, and attributes,
+/// which are the others.
+fn check_tag(
+    tool: &dyn Tool,
+    body: &str,
+    attributes: Vec<(String, String)>,
+) -> Result<ToolCall, String> {
+    let name = tool.name();
+    let Some((main, others)) = tool.arguments().split_first() else {
+        if !body.trim().is_empty() || !attributes.is_empty() {
+            return Err(format!("<{}> takes no arguments; write <{}></{}>", name, name, name));
+        }
+        return check_arguments(tool, &[]);
+    };
+
+    for (i, (key, _)) in attributes.iter().enumerate() {
+        if !others.iter().any(|arg| arg.name == *key) {
+            let accepted: Vec<&str> = others.iter().map(|arg| arg.name.as_str()).collect();
+            return Err(if accepted.is_empty() {
+                format!("<{}> has no `{}` attribute; it takes none", name, key)
+            } else {
+                format!(
+                    "<{}> has no `{}` attribute; it accepts {}",
+                    name,
+                    key,
+                    accepted.join(", ")
+                )
+            });
+        }
+        if attributes[..i].iter().any(|(earlier, _)| earlier == key) {
+            return Err(format!("`{}` is given more than once", key));
+        }
+    }
+
+    // An optional main argument is left out with an empty body
+    let mut values = Vec::new();
+    if main.required || !body.trim().is_empty() {
+        values.push((main.name.clone(), body.to_string()));
+    }
+    values.extend(attributes);
+    check_arguments(tool, &values)
 }
 
-pub fn has_tool_calls(text: &str) -> bool {
-    SEARCH_PATTERN.is_match(text) || FETCH_PATTERN.is_match(text)
+/// Check raw argument values against the tool's arguments. Errors are
+/// phrased for the model.
+fn check_arguments(tool: &dyn Tool, values: &[(String, String)]) -> Result<ToolCall, String> {
+    let mut arguments = Vec::new();
+    for arg in tool.arguments() {
+        match values.iter().find(|(key, _)| *key == arg.name) {
+            Some((_, raw)) => arguments.push((arg.name.clone(), arg.parse(raw)?)),
+            None if arg.required => return Err(format!("the `{}` argument is missing", arg.name)),
+            None => {}
+        }
+    }
+
+    Ok(ToolCall {
+        tool: tool.name().to_string(),
+        arguments,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tools::test_tools;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    const MAX_COUNT: i64 = 20;
+
+    fn search(query: &str) -> ParsedCall {
+        Ok(ToolCall {
+            tool: "search".to_string(),
+            arguments: vec![("query".to_string(), json!(query))],
+        })
+    }
+
+    fn fetch(url: &str) -> ParsedCall {
+        Ok(ToolCall {
+            tool: "fetch".to_string(),
+            arguments: vec![("url".to_string(), json!(url))],
+        })
+    }
+
+    fn parse(text: &str) -> Vec<ParsedCall> {
+        parse_tool_calls(text, &test_tools())
+    }
+
+    fn reasons(text: &str) -> Vec<String> {
+        parse(text)
+            .into_iter()
+            .filter_map(|call| call.err().map(|m| format!("{}: {}", m.tool, m.reason)))
+            .collect()
+    }
 
     #[test]
     fn test_parse_search() {
         let text = "Let me search for that. <search>rust async streams</search>";
-        let calls = parse_tool_calls(text);
-        assert_eq!(calls.len(), 1);
-        match &calls[0] {
-            ToolCall::Search { query } => assert_eq!(query, "rust async streams"),
-            _ => panic!("Expected search call"),
-        }
+        assert_eq!(parse(text), vec![search("rust async streams")]);
     }
 
     #[test]
     fn test_parse_fetch() {
         let text = "<fetch>https://example.com/page</fetch>";
-        let calls = parse_tool_calls(text);
-        assert_eq!(calls.len(), 1);
-        match &calls[0] {
-            ToolCall::Fetch { url } => assert_eq!(url, "https://example.com/page"),
-            _ => panic!("Expected fetch call"),
-        }
+        assert_eq!(parse(text), vec![fetch("https://example.com/page")]);
     }
 
     #[test]
     fn test_parse_multiple() {
         let text = "Let me search and fetch.\n<search>query</search>\n<fetch>https://example.com</fetch>";
-        let calls = parse_tool_calls(text);
+        let calls = parse_tool_calls(text, &test_tools());
         assert_eq!(calls.len(), 2);
     }
 
+    #[test]
+    fn test_calls_keep_written_order() {
+        let text = "Let me fetch and search.\n<fetch>https://example.com</fetch>\n<search>query</search>";
+        assert_eq!(
+            parse(text),
+            vec![fetch("https://example.com"), search("query")]
+        );
+    }
+
+    #[test]
+    fn test_multi_line_body_and_attributes() {
+        let text = "<search count=\"5\" freshness='Week'>\n  rust 1.80\n  release notes\n</search >";
+        let calls = parse(text);
+        let call = calls[0].as_ref().unwrap();
+        assert_eq!(call.query(), "rust 1.80 release notes");
+        assert_eq!(call.arg("count"), Some(&json!(5)));
+        assert_eq!(call.arg("freshness"), Some(&json!("week")));
+
+        let calls = parse("<search count=3>x</search>");
+        assert_eq!(calls[0].as_ref().unwrap().arg("count"), Some(&json!(3)));
+    }
+
+    #[test]
+    fn test_code_is_not_run() {
+        let text = "Write it like `<search>query</search>`, or:\n\n```html\n<fetch>https://example.com</fetch>\n```\n\n~~~\n<search>also code</search>\n~~~\nNow for real: <search>real</search>";
+        assert_eq!(parse(text), vec![search("real")]);
+
+        // Results quoting tags aren't calls either
+        let text = "<fetch>https://a.example</fetch>\n\n[Tool Result: fetch]\nSay <search>x</search>\n[End Tool Result]";
+        assert_eq!(parse(text), vec![fetch("https://a.example")]);
+    }
+
+    #[test]
+    fn test_malformed_calls_are_reported() {
+        assert_eq!(
+            reasons("<search>rust news"),
+            vec!["search: <search> is never closed; end it with </search>"]
+        );
+        assert_eq!(
+            reasons("<search count=\"50\">x</search>"),
+            vec!["search: count must be a whole number from 1 to 20, not \"50\""]
+        );
+        assert_eq!(
+            reasons("<search when=\"today\">x</search>"),
+            vec!["search: <search> has no `when` attribute; it accepts count, freshness"]
+        );
+        assert_eq!(
+            reasons("<search count=1 count=2>x</search>"),
+            vec!["search: `count` is given more than once"]
+        );
+        assert_eq!(
+            reasons("<fetch>https://example.com/a b</fetch>"),
+            vec!["fetch: the url \"https://example.com/a b\" contains whitespace"]
+        );
+
+        // An unclosed call doesn't swallow the next one
+        let calls = parse("<search>first <fetch>https://example.com</fetch>");
+        assert!(calls[0].is_err());
+        assert_eq!(calls[1], fetch("https://example.com"));
+
+        // Lookalikes are just text
+        assert!(parse("<searching> for </fetched>").is_empty());
+    }
+
+    #[test]
+    fn test_strip_tool_markup() {
+        let text = "Checking `<search>`.\n<search freshness=\"day\">\nnews\n</search>\n\n[Tool Result: search]\n1. News\n[End Tool Result]";
+        assert_eq!(strip_tool_markup(text, &test_tools()), "Checking `<search>`.");
+    }
+
+    #[test]
+    fn test_native_calls() {
+        let tools = test_tools();
+        let native = |value| parse_native_call(&serde_json::from_value(value).unwrap(), &tools);
+
+        let call = native(json!({
+            "function": { "name": "search", "arguments": { "query": "rust 2024 edition", "count": 3 } }
+        }))
+        .unwrap();
+        assert_eq!(call.query(), "rust 2024 edition");
+        assert_eq!(call.arg("count"), Some(&json!(3)));
+
+        // Some models send the arguments JSON-encoded
+        let call = native(json!({
+            "function": { "name": "fetch", "arguments": "{\"url\": \"https://example.com\"}" }
+        }));
+        assert_eq!(call, fetch("https://example.com"));
+
+        let missing = native(json!({ "function": { "name": "search", "arguments": {} } }));
+        assert_eq!(missing.unwrap_err().reason, "the `query` argument is missing");
+        let unknown = native(json!({ "function": { "name": "weather", "arguments": {} } }));
+        assert_eq!(unknown.unwrap_err().reason, "there is no `weather` tool");
+    }
+
     #[test]
     fn test_empty_query() {
-        let text = "<search></search>";
-        let calls = parse_tool_calls(text);
-        assert_eq!(calls.len(), 0);
+        assert_eq!(reasons("<search></search>"), vec!["search: the query is empty"]);
+    }
+
+    fn word() -> impl Strategy<Value = String> {
+        "[a-z0-9.:/-]{1,12}"
+    }
+
+    /// Text with nothing that can start a call, code or result.
+    fn prose() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9 ,.!?'\"\n>/=-]{0,40}"
+    }
+
+    fn call() -> impl Strategy<Value = ToolCall> {
+        prop_oneof![
+            (
+                prop::collection::vec(word(), 1..5),
+                prop::option::of(1..=MAX_COUNT),
+                prop::option::of(prop::sample::select(vec!["day", "week", "month", "year"])),
+            )
+                .prop_map(|(words, count, freshness)| {
+                    let mut arguments = vec![("query".to_string(), json!(words.join(" ")))];
+                    arguments.extend(count.map(|c| ("count".to_string(), json!(c))));
+                    arguments.extend(freshness.map(|f| ("freshness".to_string(), json!(f))));
+                    ToolCall {
+                        tool: "search".to_string(),
+                        arguments,
+                    }
+                }),
+            word().prop_map(|path| ToolCall {
+                tool: "fetch".to_string(),
+                arguments: vec![(
+                    "url".to_string(),
+                    json!(format!("https://example.com/{}", path))
+                )],
+            }),
+        ]
+    }
+
+    /// Write a call as a model might, with `gap` as the whitespace inside it.
+    fn render(call: &ToolCall, gap: &str) -> String {
+        let attributes: String = call.arguments[1..]
+            .iter()
+            .map(|(key, value)| match value {
+                Value::String(s) => format!("{} {}='{}'", gap, key, s),
+                other => format!("{} {}=\"{}\"", gap, key, other),
+            })
+            .collect();
+        format!(
+            "<{}{}>{}{}{}</{}>",
+            call.tool,
+            attributes,
+            gap,
+            call.query().replace(' ', gap),
+            gap,
+            call.tool
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn prop_never_panics_and_spans_are_ordered(text in any::<String>()) {
+            let spans = scan(&text, &test_tools());
+            let mut last = 0;
+            for (span, _) in &spans {
+                prop_assert!(span.start >= last && span.start < span.end);
+                prop_assert!(text.is_char_boundary(span.start) && text.is_char_boundary(span.end));
+                last = span.end;
+            }
+            strip_tool_markup(&text, &test_tools());
+        }
+
+        #[test]
+        fn prop_calls_round_trip_in_order(
+            parts in prop::collection::vec((prose(), call()), 0..6),
+            tail in prose(),
+            gap in "[ \n\t]{1,3}",
+        ) {
+            let mut text = String::new();
+            for (before, call) in &parts {
+                text.push_str(before);
+                text.push_str(&render(call, &gap));
+            }
+            text.push_str(&tail);
+
+            let expected: Vec<ParsedCall> = parts.iter().map(|(_, call)| Ok(call.clone())).collect();
+            prop_assert_eq!(parse(&text), expected);
+
+            let stripped = strip_tool_markup(&text, &test_tools());
+            prop_assert!(!stripped.contains("<search") && !stripped.contains("<fetch"));
+        }
+
+        #[test]
+        fn prop_code_is_never_run(call in call(), ticks in 1usize..4, fence in "(```|~~~~)[a-z]{0,5}") {
+            let rendered = render(&call, " ");
+            let ticks = "`".repeat(ticks);
+            let inline = format!("Try {}{}{} yourself.", ticks, rendered, ticks);
+            prop_assert!(parse(&inline).is_empty());
+
+            let marker = &fence[..fence.find(|c: char| c.is_ascii_lowercase()).unwrap_or(fence.len())];
+            let block = format!("Example:\n{}\n{}\n{}\nDone.", fence, rendered, marker);
+            prop_assert!(parse(&block).is_empty());
+        }
+
+        #[test]
+        fn prop_truncated_calls_are_never_run(call in call(), cut in any::<prop::sample::Index>()) {
+            let rendered = render(&call, "\n");
+            let text = &rendered[..cut.index(rendered.len())];
+            let calls = parse(text);
+            prop_assert!(calls.len() <= 1);
+            prop_assert!(calls.iter().all(Result::is_err));
+        }
     }
 }
@@ -0,0 +1,710 @@
+//! Main-content extraction for fetched HTML.
+//!
+//! A simplified take on Mozilla's Readability: boilerplate subtrees
+//! (navigation, sidebars, scripts, comment sections...) are skipped, text
+//! blocks are scored and the score is propagated to their ancestors, and the
+//! best-scoring container is rendered as Markdown so the model keeps the
+//! headings, lists, tables, code and link targets.
+
+use ego_tree::{NodeId, NodeRef};
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Node, Selector};
+use std::collections::HashMap;
+
+lazy_static! {
+    static ref TITLE: Selector = Selector::parse("title").unwrap();
+    static ref BODY: Selector = Selector::parse("body").unwrap();
+    static ref ROWS: Selector = Selector::parse("tr").unwrap();
+    static ref NESTED_TABLE: Selector = Selector::parse("table table").unwrap();
+    static ref UNLIKELY: Regex = Regex::new(
+        r"(?i)-ad-|ad-break|advert|banner|breadcrumb|combx|comment|community|cookie|cover-wrap|disqus|extra|footer|gdpr|header|legends|menu|modal|nav|newsletter|pager|pagination|popup|promo|related|remark|replies|rss|share|shoutbox|sidebar|skyscraper|social|sponsor|subscribe|supplemental|tweet|twitter"
+    )
+    .unwrap();
+    static ref MAYBE_CANDIDATE: Regex =
+        Regex::new(r"(?i)and|article|body|column|content|main|shadow").unwrap();
+    static ref POSITIVE: Regex = Regex::new(
+        r"(?i)article|body|content|entry|hentry|h-entry|main|page|post|text|blog|story"
+    )
+    .unwrap();
+    static ref NEGATIVE: Regex = Regex::new(
+        r"(?i)-ad-|hidden|banner|combx|comment|com-|contact|foot|footer|footnote|masthead|media|meta|outbrain|promo|related|scroll|share|shoutbox|sidebar|skyscraper|sponsor|shopping|tags|tool|widget"
+    )
+    .unwrap();
+}
+
+/// Elements that never hold main content.
+const BOILERPLATE_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "nav", "footer", "aside", "form", "iframe",
+    "svg", "canvas", "button", "input", "select", "textarea", "dialog", "menu", "object",
+    "embed",
+];
+
+const BOILERPLATE_ROLES: &[&str] = &[
+    "navigation",
+    "banner",
+    "contentinfo",
+    "complementary",
+    "dialog",
+    "search",
+    "menu",
+    "menubar",
+];
+
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "blockquote",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+/// Blocks shorter than this are too small to say anything about content.
+const MIN_SCORABLE_CHARS: usize = 25;
+
+/// Extract the main content of an HTML document as Markdown, prefixed with
+/// the page title. Relative links are resolved against `base_url`.
+pub fn extract_markdown(html: &str, base_url: Option<&Url>) -> String {
+    let document = Html::parse_document(html);
+    let body = document
+        .select(&BODY)
+        .next()
+        .unwrap_or_else(|| document.root_element());
+
+    let renderer = Renderer { base: base_url };
+    let blocks: Vec<String> = select_content(body)
+        .into_iter()
+        .flat_map(|node| renderer.element_blocks(node))
+        .collect();
+
+    let mut output = String::new();
+    if let Some(title) = document.select(&TITLE).next() {
+        let title = normalise(&title.text().collect::<String>());
+        if !title.is_empty() {
+            output.push_str(&format!("Title: {}\n\n", title));
+        }
+    }
+    output.push_str(&blocks.join("\n\n"));
+    output.trim().to_string()
+}
+
+// ---------------------------------------------------------------------------
+// Boilerplate detection
+// ---------------------------------------------------------------------------
+
+fn is_boilerplate(element: ElementRef) -> bool {
+    let el = element.value();
+    let name = el.name();
+
+    if BOILERPLATE_TAGS.contains(&name) {
+        return true;
+    }
+
+    // Page headers are chrome; an article's own header carries its title
+    if name == "header" && !has_ancestor(element, &["article", "main"]) {
+        return true;
+    }
+
+    if el.attr("hidden").is_some() || el.attr("aria-hidden") == Some("true") {
+        return true;
+    }
+
+    if let Some(style) = el.attr("style") {
+        let style = style.replace(' ', "").to_ascii_lowercase();
+        if style.contains("display:none") || style.contains("visibility:hidden") {
+            return true;
+        }
+    }
+
+    if el
+        .attr("role")
+        .is_some_and(|role| BOILERPLATE_ROLES.contains(&role))
+    {
+        return true;
+    }
+
+    if matches!(name, "body" | "article" | "main" | "a" | "table" | "tbody" | "tr" | "td" | "th") {
+        return false;
+    }
+
+    let class_and_id = format!(
+        "{} {}",
+        el.attr("class").unwrap_or(""),
+        el.attr("id").unwrap_or("")
+    );
+    UNLIKELY.is_match(&class_and_id) && !MAYBE_CANDIDATE.is_match(&class_and_id)
+}
+
+fn has_ancestor(element: ElementRef, names: &[&str]) -> bool {
+    element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .any(|a| names.contains(&a.value().name()))
+}
+
+/// Visit every element below `node`, skipping boilerplate subtrees.
+fn walk<'a>(node: NodeRef<'a, Node>, visit: &mut impl FnMut(ElementRef<'a>)) {
+    for child in node.children() {
+        if let Some(element) = ElementRef::wrap(child) {
+            if is_boilerplate(element) {
+                continue;
+            }
+            visit(element);
+            walk(child, visit);
+        }
+    }
+}
+
+/// Text length and the share of it inside links, ignoring boilerplate.
+fn text_stats(node: NodeRef<Node>) -> (usize, usize) {
+    fn collect(node: NodeRef<Node>, in_link: bool, total: &mut usize, linked: &mut usize) {
+        for child in node.children() {
+            match child.value() {
+                Node::Text(text) => {
+                    let len = text.split_whitespace().map(|w| w.chars().count() + 1).sum::<usize>();
+                    *total += len;
+                    if in_link {
+                        *linked += len;
+                    }
+                }
+                Node::Element(el) => {
+                    let element = ElementRef::wrap(child).unwrap();
+                    if !is_boilerplate(element) {
+                        collect(child, in_link || el.name() == "a", total, linked);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let (mut total, mut linked) = (0, 0);
+    collect(node, false, &mut total, &mut linked);
+    (total, linked)
+}
+
+fn link_density(node: NodeRef<Node>) -> f64 {
+    let (total, linked) = text_stats(node);
+    if total == 0 {
+        0.0
+    } else {
+        linked as f64 / total as f64
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Scoring
+// ---------------------------------------------------------------------------
+
+fn class_weight(element: ElementRef) -> f64 {
+    let mut weight = 0.0;
+    for value in [element.value().attr("class"), element.value().attr("id")]
+        .into_iter()
+        .flatten()
+    {
+        if NEGATIVE.is_match(value) {
+            weight -= 25.0;
+        }
+        if POSITIVE.is_match(value) {
+            weight += 25.0;
+        }
+    }
+    weight
+}
+
+fn tag_weight(name: &str) -> f64 {
+    match name {
+        "div" | "article" | "main" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    }
+}
+
+fn is_scorable(element: ElementRef) -> bool {
+    match element.value().name() {
+        "p" | "pre" | "td" => true,
+        // Text-only divs are paragraphs in all but name
+        "div" | "section" => !element
+            .children()
+            .filter_map(ElementRef::wrap)
+            .any(|c| BLOCK_TAGS.contains(&c.value().name())),
+        _ => false,
+    }
+}
+
+/// Choose the element(s) holding the main content, in document order.
+fn select_content(body: ElementRef) -> Vec<ElementRef> {
+    let mut scores: HashMap<NodeId, f64> = HashMap::new();
+
+    walk(*body, &mut |element| {
+        if !is_scorable(element) {
+            return;
+        }
+
+        let text = element.text().collect::<String>();
+        let len = text.trim().chars().count();
+        if len < MIN_SCORABLE_CHARS {
+            return;
+        }
+
+        let score = 1.0 + text.matches(',').count() as f64 + (len as f64 / 100.0).min(3.0);
+
+        // Parent gets full credit, grandparent half, then tapering off
+        for (level, ancestor) in element
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(3)
+            .enumerate()
+        {
+            let entry = scores.entry(ancestor.id()).or_insert_with(|| {
+                tag_weight(ancestor.value().name()) + class_weight(ancestor)
+            });
+            *entry += match level {
+                0 => score,
+                1 => score / 2.0,
+                _ => score / (level as f64 * 3.0),
+            };
+        }
+    });
+
+    let final_score = |element: ElementRef| {
+        scores
+            .get(&element.id())
+            .map(|score| score * (1.0 - link_density(*element)))
+    };
+
+    let mut top: Option<(ElementRef, f64)> = None;
+    for &id in scores.keys() {
+        let Some(element) = body.tree().get(id).and_then(ElementRef::wrap) else {
+            continue;
+        };
+        let Some(score) = final_score(element) else {
+            continue;
+        };
+        if top.is_none_or(|(_, best)| score > best) {
+            top = Some((element, score));
+        }
+    }
+
+    let Some((top, top_score)) = top else {
+        return vec![body];
+    };
+
+    let Some(parent) = top.parent().and_then(ElementRef::wrap) else {
+        return vec![top];
+    };
+    if top.id() == body.id() {
+        return vec![top];
+    }
+
+    // Content is often split across siblings, e.g. an intro div followed by
+    // the article body, so pull in siblings that look like content too
+    let threshold = (top_score * 0.2).max(10.0);
+    parent
+        .children()
+        .filter_map(ElementRef::wrap)
+        .filter(|sibling| !is_boilerplate(*sibling))
+        .filter(|sibling| {
+            if sibling.id() == top.id() {
+                return true;
+            }
+            if final_score(*sibling).is_some_and(|score| score >= threshold) {
+                return true;
+            }
+            if sibling.value().name() == "p" {
+                let (len, _) = text_stats(**sibling);
+                let density = link_density(**sibling);
+                let text = sibling.text().collect::<String>();
+                return (len > 80 && density < 0.25)
+                    || (len > 0 && density == 0.0 && text.contains(". "));
+            }
+            false
+        })
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+// Markdown rendering
+// ---------------------------------------------------------------------------
+
+struct Renderer<'a> {
+    base: Option<&'a Url>,
+}
+
+impl Renderer<'_> {
+    /// Render an element as zero or more Markdown blocks.
+    fn element_blocks(&self, element: ElementRef) -> Vec<String> {
+        if is_boilerplate(element) {
+            return Vec::new();
+        }
+
+        let name = element.value().name();
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                let text = normalise(&self.inline(*element));
+                non_empty_block(format!("{} {}", "#".repeat(level), text), text.is_empty())
+            }
+            "p" | "summary" | "figcaption" | "dt" | "dd" | "address" => {
+                non_empty_block(normalise_lines(&self.inline(*element)), false)
+            }
+            "pre" => vec![self.code_block(element)],
+            "ul" | "ol" => non_empty_block(self.list(element, 0), false),
+            "table" => self.table(element),
+            "blockquote" => {
+                let inner = self.child_blocks(*element).join("\n\n");
+                non_empty_block(
+                    inner
+                        .lines()
+                        .map(|line| format!("> {}", line).trim_end().to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    inner.is_empty(),
+                )
+            }
+            "hr" => vec!["---".to_string()],
+            "img" | "br" => Vec::new(),
+            _ if BLOCK_TAGS.contains(&name) || name == "body" => self.child_blocks(*element),
+            // Inline element at block level, e.g. a lone <span> of text
+            _ => non_empty_block(normalise_lines(&self.inline(*element)), false),
+        }
+    }
+
+    /// Render children, gathering runs of inline content into paragraphs.
+    fn child_blocks(&self, node: NodeRef<Node>) -> Vec<String> {
+        let mut blocks = Vec::new();
+        let mut inline = String::new();
+
+        let flush = |inline: &mut String, blocks: &mut Vec<String>| {
+            let paragraph = normalise_lines(inline);
+            if !paragraph.is_empty() {
+                blocks.push(paragraph);
+            }
+            inline.clear();
+        };
+
+        for child in node.children() {
+            match child.value() {
+                Node::Text(text) => inline.push_str(&collapse_whitespace(text)),
+                Node::Element(el) => {
+                    let element = ElementRef::wrap(child).unwrap();
+                    if BLOCK_TAGS.contains(&el.name()) {
+                        flush(&mut inline, &mut blocks);
+                        blocks.extend(self.element_blocks(element));
+                    } else if !is_boilerplate(element) {
+                        inline.push_str(&self.inline_element(element));
+                    }
+                }
+                _ => {}
+            }
+        }
+        flush(&mut inline, &mut blocks);
+
+        blocks
+    }
+
+    /// Render inline content. Whitespace is collapsed; `<br>` becomes `\n`.
+    fn inline(&self, node: NodeRef<Node>) -> String {
+        let mut output = String::new();
+
+        for child in node.children() {
+            match child.value() {
+                Node::Text(text) => output.push_str(&collapse_whitespace(text)),
+                Node::Element(_) => {
+                    let element = ElementRef::wrap(child).unwrap();
+                    if !is_boilerplate(element) {
+                        output.push_str(&self.inline_element(element));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        output
+    }
+
+    fn link(&self, element: ElementRef) -> String {
+        let text = normalise(&self.inline(*element));
+        if text.is_empty() {
+            return String::new();
+        }
+
+        let href = element.value().attr("href").map(str::trim).unwrap_or("");
+        if href.is_empty() || href.starts_with('#') || href.starts_with("javascript:") {
+            return text;
+        }
+
+        let target = match self.base {
+            Some(base) => base
+                .join(href)
+                .map(|u| u.to_string())
+                .unwrap_or_else(|_| href.to_string()),
+            None => href.to_string(),
+        };
+
+        format!("[{}]({})", text, target)
+    }
+
+    fn list(&self, element: ElementRef, depth: usize) -> String {
+        let ordered = element.value().name() == "ol";
+        let mut number: usize = element
+            .value()
+            .attr("start")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1);
+        let indent = "  ".repeat(depth);
+        let mut lines = Vec::new();
+
+        for item in element.children().filter_map(ElementRef::wrap) {
+            if item.value().name() != "li" || is_boilerplate(item) {
+                continue;
+            }
+
+            let mut text = String::new();
+            let mut nested = Vec::new();
+            for child in item.children() {
+                match ElementRef::wrap(child) {
+                    Some(el) if matches!(el.value().name(), "ul" | "ol") => {
+                        nested.push(self.list(el, depth + 1));
+                    }
+                    Some(el) if el.value().name() == "pre" => {
+                        nested.push(indent_lines(&self.code_block(el), &"  ".repeat(depth + 1)));
+                    }
+                    Some(el) if !is_boilerplate(el) => text.push_str(&self.inline_element(el)),
+                    Some(_) => {}
+                    None => {
+                        if let Node::Text(t) = child.value() {
+                            text.push_str(&collapse_whitespace(t));
+                        }
+                    }
+                }
+            }
+
+            let marker = if ordered {
+                let m = format!("{}.", number);
+                number += 1;
+                m
+            } else {
+                "-".to_string()
+            };
+
+            let text = normalise(&text);
+            if !text.is_empty() || !nested.is_empty() {
+                lines.push(format!("{}{} {}", indent, marker, text).trim_end().to_string());
+            }
+            lines.extend(nested);
+        }
+
+        lines.join("\n")
+    }
+
+    /// Render a single element inline, including its own markup.
+    fn inline_element(&self, element: ElementRef) -> String {
+        match element.value().name() {
+            "a" => self.link(element),
+            "strong" | "b" => wrap("**", &self.inline(*element)),
+            "em" | "i" => wrap("_", &self.inline(*element)),
+            "code" | "kbd" | "samp" => wrap(
+                "`",
+                &collapse_whitespace(&element.text().collect::<String>()),
+            ),
+            "br" => "\n".to_string(),
+            "img" => String::new(),
+            // Block elements nested in inline context, e.g. <p> in <li>
+            name if BLOCK_TAGS.contains(&name) => format!(" {} ", self.inline(*element)),
+            _ => self.inline(*element),
+        }
+    }
+
+    fn code_block(&self, element: ElementRef) -> String {
+        let language = std::iter::once(element)
+            .chain(element.children().filter_map(ElementRef::wrap))
+            .filter_map(|el| el.value().attr("class"))
+            .flat_map(str::split_whitespace)
+            .find_map(|class| {
+                class
+                    .strip_prefix("language-")
+                    .or_else(|| class.strip_prefix("lang-"))
+            })
+            .unwrap_or("");
+
+        let code = element.text().collect::<String>();
+        let code = code.trim_start_matches('\n').trim_end();
+        format!("```{}\n{}\n```", language, code)
+    }
+
+    fn table(&self, element: ElementRef) -> Vec<String> {
+        // Layout tables just hold other content
+        if element.select(&NESTED_TABLE).next().is_some() {
+            return self.child_blocks(*element);
+        }
+
+        let rows: Vec<Vec<String>> = element
+            .select(&ROWS)
+            .map(|row| {
+                row.children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|cell| matches!(cell.value().name(), "th" | "td"))
+                    .map(|cell| normalise(&self.inline(*cell)).replace('|', "\\|"))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|row| !row.is_empty())
+            .collect();
+
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if width == 0 {
+            return Vec::new();
+        }
+        if width == 1 {
+            return rows.into_iter().flatten().filter(|c| !c.is_empty()).collect();
+        }
+
+        let format_row = |row: &Vec<String>| {
+            let mut cells = row.clone();
+            cells.resize(width, String::new());
+            format!("| {} |", cells.join(" | "))
+        };
+
+        let mut lines = vec![format_row(&rows[0])];
+        lines.push(format!("|{}", " --- |".repeat(width)));
+        lines.extend(rows[1..].iter().map(format_row));
+
+        vec![lines.join("\n")]
+    }
+}
+
+fn non_empty_block(block: String, empty: bool) -> Vec<String> {
+    if empty || block.trim().is_empty() {
+        Vec::new()
+    } else {
+        vec![block]
+    }
+}
+
+fn wrap(marker: &str, text: &str) -> String {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return text.to_string();
+    }
+
+    // Keep surrounding spaces outside the markers so Markdown still parses
+    let leading = if text.starts_with(char::is_whitespace) { " " } else { "" };
+    let trailing = if text.ends_with(char::is_whitespace) { " " } else { "" };
+    format!("{}{}{}{}{}", leading, marker, trimmed, marker, trailing)
+}
+
+fn indent_lines(text: &str, indent: &str) -> String {
+    text.lines()
+        .map(|line| format!("{}{}", indent, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut prev_whitespace = false;
+
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !prev_whitespace {
+                result.push(' ');
+            }
+            prev_whitespace = true;
+        } else {
+            result.push(c);
+            prev_whitespace = false;
+        }
+    }
+
+    result
+}
+
+fn normalise(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Like `normalise` but keeps the line breaks produced by `<br>`.
+fn normalise_lines(text: &str) -> String {
+    text.split('\n')
+        .map(normalise)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_url() -> Url {
+        Url::parse("https://example.com/blog/post.html").unwrap()
+    }
+
+    #[test]
+    fn test_news_article_fixture() {
+        let html = include_str!("../../tests/fixtures/readability/news_article.html");
+        let expected = include_str!("../../tests/fixtures/readability/news_article.md");
+        assert_eq!(
+            extract_markdown(html, Some(&fixture_url())),
+            expected.trim_end()
+        );
+    }
+
+    #[test]
+    fn test_docs_page_fixture() {
+        let html = include_str!("../../tests/fixtures/readability/docs_page.html");
+        let expected = include_str!("../../tests/fixtures/readability/docs_page.md");
+        assert_eq!(
+            extract_markdown(html, Some(&fixture_url())),
+            expected.trim_end()
+        );
+    }
+
+    #[test]
+    fn test_blog_with_comments_fixture() {
+        let html = include_str!("../../tests/fixtures/readability/blog_with_comments.html");
+        let output = extract_markdown(html, Some(&fixture_url()));
+
+        assert!(output.contains("## Why we moved to SQLite"));
+        assert!(output.contains("[WAL mode](https://www.sqlite.org/wal.html)"));
+        assert!(!output.contains("Great post!"), "comments leaked:\n{}", output);
+        assert!(!output.contains("Subscribe"), "newsletter leaked:\n{}", output);
+        assert!(!output.contains("trackPageView"), "script leaked:\n{}", output);
+    }
+
+    #[test]
+    fn test_inline_markup() {
+        let html = r#"<html><body><article><p>Use <code>cargo   build</code> with
+            <strong>care</strong>, see <a href="/docs">the docs</a> and
+            <a href="javascript:void(0)">this</a>.<br>Next line.</p></article></body></html>"#;
+        assert_eq!(
+            extract_markdown(html, Some(&fixture_url())),
+            "Use `cargo build` with **care**, see [the docs](https://example.com/docs) and this.\nNext line."
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::{debug, error};
+
+use crate::error::AppError;
+
+use super::{Freshness, SearchProvider, SearchResult};
+
+#[derive(Clone)]
+pub struct BraveClient {
+    client: Client,
+    api_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BraveSearchResponse {
+    pub web: Option<WebResults>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebResults {
+    pub results: Vec<SearchResult>,
+}
+
+impl BraveClient {
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            client: Client::new(),
+            api_key: api_key.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for BraveClient {
+    fn name(&self) -> &'static str {
+        "brave"
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        count: u8,
+        freshness: Option<Freshness>,
+    ) -> Result<Vec<SearchResult>, AppError> {
+        let url = "https://api.search.brave.com/res/v1/web/search";
+
+        debug!("Searching Brave for: {}", query);
+
+        let response = self
+            .client
+            .get(url)
+            .header("X-Subscription-Token", &self.api_key)
+            .query(&[("q", query), ("count", &count.to_string())])
+            .query(&[("freshness", freshness.map(brave_freshness))])
+            .send()
+            .await
+            .map_err(|e| AppError::BraveSearch(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            error!("Brave search failed: {} - {}", status, body);
+            return Err(AppError::BraveSearch(format!(
+                "Status {}: {}",
+                status, body
+            )));
+        }
+
+        let search_response: BraveSearchResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::BraveSearch(e.to_string()))?;
+
+        Ok(search_response.web.map(|w| w.results).unwrap_or_default())
+    }
+}
+
+/// Brave's `freshness` codes: past day, week, month or year.
+fn brave_freshness(freshness: Freshness) -> &'static str {
+    match freshness {
+        Freshness::Day => "pd",
+        Freshness::Week => "pw",
+        Freshness::Month => "pm",
+        Freshness::Year => "py",
+    }
+}
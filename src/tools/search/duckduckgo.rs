@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use reqwest::{Client, Url};
+use scraper::{Html, Selector};
+use tracing::{debug, error};
+
+use crate::error::AppError;
+
+use super::{Freshness, SearchProvider, SearchResult};
+
+const DDG_HTML_URL: &str = "https://html.duckduckgo.com/html/";
+
+/// Scrapes DuckDuckGo's no-JavaScript results page. Needs no API key, but
+/// is subject to rate limiting and markup changes.
+#[derive(Clone)]
+pub struct DuckDuckGoClient {
+    client: Client,
+}
+
+impl DuckDuckGoClient {
+    pub fn new() -> Self {
+        let client = Client::builder()
+            .user_agent("Mozilla/5.0 (compatible; Ferret/0.1)")
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self { client }
+    }
+}
+
+impl Default for DuckDuckGoClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl SearchProvider for DuckDuckGoClient {
+    fn name(&self) -> &'static str {
+        "duckduckgo"
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        count: u8,
+        freshness: Option<Freshness>,
+    ) -> Result<Vec<SearchResult>, AppError> {
+        debug!("Searching DuckDuckGo for: {}", query);
+
+        let response = self
+            .client
+            .post(DDG_HTML_URL)
+            .form(&[("q", Some(query)), ("df", freshness.map(ddg_freshness))])
+            .send()
+            .await
+            .map_err(|e| AppError::Search(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            error!("DuckDuckGo search failed: {}", status);
+            return Err(AppError::Search(format!("Status {}", status)));
+        }
+
+        let html = response
+            .text()
+            .await
+            .map_err(|e| AppError::Search(e.to_string()))?;
+
+        Ok(parse_results(&html, count as usize))
+    }
+}
+
+fn parse_results(html: &str, count: usize) -> Vec<SearchResult> {
+    let document = Html::parse_document(html);
+    let result_selector = Selector::parse(".result").unwrap();
+    let link_selector = Selector::parse("a.result__a").unwrap();
+    let snippet_selector = Selector::parse(".result__snippet").unwrap();
+
+    document
+        .select(&result_selector)
+        // Sponsored results link through an ad redirect
+        .filter(|result| !result.value().classes().any(|c| c == "result--ad"))
+        .filter_map(|result| {
+            let link = result.select(&link_selector).next()?;
+            let url = resolve_link(link.value().attr("href")?)?;
+            let title = link.text().collect::<String>().trim().to_string();
+            let description = result
+                .select(&snippet_selector)
+                .next()
+                .map(|s| s.text().collect::<String>().trim().to_string())
+                .unwrap_or_default();
+
+            Some(SearchResult {
+                title,
+                url,
+                description,
+                age: None,
+            })
+        })
+        .take(count)
+        .collect()
+}
+
+/// Result links usually go through `//duckduckgo.com/l/?uddg=<target>`.
+fn resolve_link(href: &str) -> Option<String> {
+    let absolute = if href.starts_with("//") {
+        format!("https:{}", href)
+    } else {
+        href.to_string()
+    };
+    let url = Url::parse(&absolute).ok()?;
+
+    if url.domain() == Some("duckduckgo.com") && url.path() == "/l/" {
+        return url
+            .query_pairs()
+            .find(|(key, _)| key == "uddg")
+            .map(|(_, target)| target.into_owned());
+    }
+
+    Some(absolute)
+}
+
+/// DuckDuckGo's `df` date filter codes.
+fn ddg_freshness(freshness: Freshness) -> &'static str {
+    match freshness {
+        Freshness::Day => "d",
+        Freshness::Week => "w",
+        Freshness::Month => "m",
+        Freshness::Year => "y",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESULTS_PAGE: &str = r##"
+        <div class="results">
+          <div class="result results_links result--ad">
+            <a class="result__a" href="https://duckduckgo.com/y.js?ad=1">Sponsored</a>
+          </div>
+          <div class="result results_links">
+            <h2><a class="result__a" href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fwww.rust-lang.org%2F&amp;rut=abc">Rust Programming Language</a></h2>
+            <a class="result__snippet" href="#">A language empowering everyone.</a>
+          </div>
+          <div class="result results_links">
+            <h2><a class="result__a" href="https://doc.rust-lang.org/book/">The Book</a></h2>
+          </div>
+        </div>
+    "##;
+
+    #[test]
+    fn test_parse_results() {
+        let results = parse_results(RESULTS_PAGE, 10);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].title, "Rust Programming Language");
+        assert_eq!(results[0].url, "https://www.rust-lang.org/");
+        assert_eq!(results[0].description, "A language empowering everyone.");
+        assert_eq!(results[1].url, "https://doc.rust-lang.org/book/");
+        assert_eq!(results[1].description, "");
+    }
+
+    #[test]
+    fn test_parse_results_respects_count() {
+        assert_eq!(parse_results(RESULTS_PAGE, 1).len(), 1);
+    }
+}
@@ -0,0 +1,199 @@
+mod brave;
+mod duckduckgo;
+mod searxng;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::{AppConfig, SearchBackend};
+use crate::error::AppError;
+
+use super::tool::{Argument, Tool, ToolCall};
+
+pub use brave::BraveClient;
+pub use duckduckgo::DuckDuckGoClient;
+pub use searxng::SearxngClient;
+
+/// A web search backend.
+#[async_trait]
+pub trait SearchProvider: Send + Sync {
+    /// Short identifier used in logs and `/health`.
+    fn name(&self) -> &'static str;
+
+    async fn search(
+        &self,
+        query: &str,
+        count: u8,
+        freshness: Option<Freshness>,
+    ) -> Result<Vec<SearchResult>, AppError>;
+}
+
+/// How recent search results must be.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Freshness {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl FromStr for Freshness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "day" => Ok(Freshness::Day),
+            "week" => Ok(Freshness::Week),
+            "month" => Ok(Freshness::Month),
+            "year" => Ok(Freshness::Year),
+            other => Err(format!(
+                "Unknown freshness: {}; expected day, week, month or year",
+                other
+            )),
+        }
+    }
+}
+
+impl Freshness {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Freshness::Day => "day",
+            Freshness::Week => "week",
+            Freshness::Month => "month",
+            Freshness::Year => "year",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub description: String,
+    #[serde(default)]
+    pub age: Option<String>,
+}
+
+impl SearchResult {
+    pub fn format_for_context(&self) -> String {
+        format!(
+            "Title: {}\nURL: {}\nSnippet: {}",
+            self.title, self.url, self.description
+        )
+    }
+}
+
+/// Build the search provider selected by configuration, if any.
+pub fn provider_from_config(config: &AppConfig) -> Option<Arc<dyn SearchProvider>> {
+    match config.search_backend {
+        SearchBackend::Brave => config
+            .brave_api_key
+            .as_deref()
+            .map(|key| Arc::new(BraveClient::new(key)) as Arc<dyn SearchProvider>),
+        SearchBackend::Searxng => config
+            .searxng_url
+            .as_deref()
+            .map(|url| Arc::new(SearxngClient::new(url)) as Arc<dyn SearchProvider>),
+        SearchBackend::DuckDuckGo => Some(Arc::new(DuckDuckGoClient::new())),
+        SearchBackend::None => None,
+    }
+}
+
+/// Results per search when the call doesn't ask for a number.
+const DEFAULT_RESULTS: u8 = 10;
+const MAX_RESULTS: i64 = 20;
+
+/// The `search` tool, backed by the configured provider.
+pub struct SearchTool {
+    provider: Arc<dyn SearchProvider>,
+    arguments: Vec<Argument>,
+}
+
+impl SearchTool {
+    pub fn new(provider: Arc<dyn SearchProvider>) -> Self {
+        Self {
+            provider,
+            arguments: vec![
+                Argument::text("query", "The search query").required(),
+                Argument::integer(
+                    "count",
+                    "How many results to return (default 10)",
+                    1,
+                    MAX_RESULTS,
+                ),
+                Argument::choice(
+                    "freshness",
+                    "Only return results from the past day, week, month or year",
+                    &["day", "week", "month", "year"],
+                ),
+            ],
+        }
+    }
+
+    /// Name of the provider behind the tool.
+    pub fn provider_name(&self) -> &'static str {
+        self.provider.name()
+    }
+}
+
+#[async_trait]
+impl Tool for SearchTool {
+    fn name(&self) -> &str {
+        "search"
+    }
+
+    fn title(&self) -> &str {
+        "Search the web"
+    }
+
+    fn description(&self) -> &str {
+        "Search the web to find current information, verify facts, or research topics."
+    }
+
+    fn arguments(&self) -> &[Argument] {
+        &self.arguments
+    }
+
+    fn example(&self) -> &str {
+        "your query"
+    }
+
+    async fn execute(&self, call: &ToolCall) -> Result<String, AppError> {
+        let count = call
+            .arg("count")
+            .and_then(Value::as_u64)
+            .and_then(|n| u8::try_from(n).ok())
+            .unwrap_or(DEFAULT_RESULTS);
+        let freshness = call
+            .arg("freshness")
+            .and_then(Value::as_str)
+            .and_then(|f| f.parse().ok());
+
+        let results = self.provider.search(call.query(), count, freshness).await?;
+        Ok(format_results(call.query(), &results))
+    }
+
+    fn describe(&self, call: &ToolCall) -> String {
+        format!("Searched: {}", call.query())
+    }
+}
+
+fn format_results(query: &str, results: &[SearchResult]) -> String {
+    let mut output = format!("Query: \"{}\"\n\n", query);
+
+    if results.is_empty() {
+        output.push_str("No results found.");
+    } else {
+        for (i, result) in results.iter().enumerate() {
+            output.push_str(&format!("{}. {}\n", i + 1, result.format_for_context()));
+            output.push('\n');
+        }
+        // The result block adds the final line break
+        output.pop();
+    }
+
+    output
+}
@@ -0,0 +1,92 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::{debug, error};
+
+use crate::error::AppError;
+
+use super::{Freshness, SearchProvider, SearchResult};
+
+/// Client for a SearXNG instance's JSON API. The instance must have `json`
+/// enabled under `search.formats` in its settings.
+#[derive(Clone)]
+pub struct SearxngClient {
+    client: Client,
+    base_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearxngResponse {
+    #[serde(default)]
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearxngResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default, rename = "publishedDate")]
+    published_date: Option<String>,
+}
+
+impl SearxngClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for SearxngClient {
+    fn name(&self) -> &'static str {
+        "searxng"
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        count: u8,
+        freshness: Option<Freshness>,
+    ) -> Result<Vec<SearchResult>, AppError> {
+        let url = format!("{}/search", self.base_url);
+
+        debug!("Searching SearXNG for: {}", query);
+
+        let response = self
+            .client
+            .get(&url)
+            .query(&[("q", query), ("format", "json")])
+            .query(&[("time_range", freshness.map(Freshness::as_str))])
+            .send()
+            .await
+            .map_err(|e| AppError::Search(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            error!("SearXNG search failed: {} - {}", status, body);
+            return Err(AppError::Search(format!("Status {}: {}", status, body)));
+        }
+
+        let search_response: SearxngResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Search(e.to_string()))?;
+
+        Ok(search_response
+            .results
+            .into_iter()
+            .take(count as usize)
+            .map(|r| SearchResult {
+                title: r.title,
+                url: r.url,
+                description: r.content,
+                age: r.published_date,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,324 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::error::AppError;
+
+/// Decides which URLs the fetch tool may reach.
+///
+/// The model chooses fetch URLs, so without this anyone chatting with Ferret
+/// could make the server request cloud metadata endpoints, the Ollama API or
+/// other internal services. Hosts that resolve to private, loopback,
+/// link-local or otherwise non-public addresses are refused unless they are
+/// explicitly allowlisted.
+#[derive(Debug, Clone, Default)]
+pub struct UrlGuard {
+    allow_hosts: Vec<String>,
+    deny_hosts: Vec<String>,
+}
+
+impl UrlGuard {
+    /// Host patterns match the host itself and any subdomain of it. A
+    /// non-empty allowlist restricts fetching to those hosts only.
+    pub fn new(allow_hosts: Vec<String>, deny_hosts: Vec<String>) -> Self {
+        let normalise = |hosts: Vec<String>| {
+            hosts
+                .into_iter()
+                .map(|h| h.trim().trim_start_matches("*.").to_ascii_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect()
+        };
+
+        Self {
+            allow_hosts: normalise(allow_hosts),
+            deny_hosts: normalise(deny_hosts),
+        }
+    }
+
+    /// Validate a URL before requesting it, resolving its host to check the
+    /// addresses it points at.
+    pub async fn check(&self, url: &Url) -> Result<(), AppError> {
+        let host = self.check_host(url)?;
+
+        if self.is_allowlisted(&host) {
+            return Ok(());
+        }
+
+        // IP literals need no lookup
+        if let Some(ip) = literal_ip(url) {
+            return check_ip(&host, ip);
+        }
+
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addrs = tokio::net::lookup_host((host.as_str(), port))
+            .await
+            .map_err(|e| AppError::PageFetch(format!("Could not resolve {}: {}", host, e)))?;
+
+        for addr in addrs {
+            check_ip(&host, addr.ip())?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that need no DNS: scheme, host lists and IP literals.
+    fn check_host(&self, url: &Url) -> Result<String, AppError> {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(AppError::PageFetch(
+                "Invalid URL: must start with http:// or https://".to_string(),
+            ));
+        }
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| AppError::PageFetch("Invalid URL: no host".to_string()))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim_end_matches('.')
+            .to_ascii_lowercase();
+
+        if self.deny_hosts.iter().any(|p| host_matches(p, &host)) {
+            return Err(blocked(&host, "host is on the deny list"));
+        }
+
+        if !self.allow_hosts.is_empty() && !self.is_allowlisted(&host) {
+            return Err(blocked(&host, "host is not on the allow list"));
+        }
+
+        Ok(host)
+    }
+
+    fn is_allowlisted(&self, host: &str) -> bool {
+        self.allow_hosts.iter().any(|p| host_matches(p, host))
+    }
+}
+
+/// DNS resolver for the fetch client that drops non-public addresses.
+///
+/// `UrlGuard::check` validates each URL up front; this closes the gap where
+/// a hostname resolves differently by the time the connection is made.
+pub struct GuardedResolver {
+    guard: Arc<UrlGuard>,
+}
+
+impl GuardedResolver {
+    pub fn new(guard: Arc<UrlGuard>) -> Self {
+        Self { guard }
+    }
+}
+
+impl Resolve for GuardedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let guard = self.guard.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_ascii_lowercase();
+            let resolved: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+
+            if guard.is_allowlisted(&host) {
+                return Ok(Box::new(resolved.into_iter()) as Addrs);
+            }
+
+            let public: Vec<SocketAddr> = resolved
+                .into_iter()
+                .filter(|addr| !is_blocked_ip(addr.ip()))
+                .collect();
+
+            if public.is_empty() {
+                warn!("Refusing to connect to {}: no public addresses", host);
+                return Err(Box::new(blocked(&host, "resolves to a non-public address"))
+                    as Box<dyn std::error::Error + Send + Sync>);
+            }
+
+            Ok(Box::new(public.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn literal_ip(url: &Url) -> Option<IpAddr> {
+    match url.host()? {
+        url::Host::Ipv4(ip) => Some(IpAddr::V4(ip)),
+        url::Host::Ipv6(ip) => Some(IpAddr::V6(ip)),
+        url::Host::Domain(_) => None,
+    }
+}
+
+fn check_ip(host: &str, ip: IpAddr) -> Result<(), AppError> {
+    if is_blocked_ip(ip) {
+        warn!("Blocked fetch of {} ({})", host, ip);
+        return Err(blocked(
+            host,
+            &format!("resolves to non-public address {}", ip),
+        ));
+    }
+    Ok(())
+}
+
+fn blocked(host: &str, reason: &str) -> AppError {
+    AppError::PageFetch(format!("Blocked: {} {}", host, reason))
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    host == pattern
+        || host
+            .strip_suffix(pattern)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Whether an address is anything other than ordinary public unicast.
+pub fn is_blocked_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_blocked_ipv4(v4),
+        IpAddr::V6(v6) => is_blocked_ipv6(v6),
+    }
+}
+
+fn is_blocked_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local() // includes 169.254.169.254 cloud metadata
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0 // "this network"
+        || (a == 100 && (64..128).contains(&b)) // carrier-grade NAT
+        || (a == 192 && b == 0 && c == 0) // IETF protocol assignments
+        || (a == 198 && (18..20).contains(&b)) // benchmarking
+        || a >= 240 // reserved
+}
+
+fn is_blocked_ipv6(ip: Ipv6Addr) -> bool {
+    // Addresses that embed an IPv4 address are judged by that address
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_blocked_ipv4(v4);
+    }
+
+    let segments = ip.segments();
+    let embedded = match segments {
+        // NAT64 well-known prefix 64:ff9b::/96
+        [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => Some(ipv4_from(hi, lo)),
+        // Deprecated IPv4-compatible ::a.b.c.d, which also covers :: and ::1
+        [0, 0, 0, 0, 0, 0, hi, lo] => Some(ipv4_from(hi, lo)),
+        // 6to4 2002::/16 carries the IPv4 address right after the prefix
+        [0x2002, hi, lo, ..] => Some(ipv4_from(hi, lo)),
+        // Teredo 2001::/32 ends with the client's IPv4 address, inverted
+        [0x2001, 0, .., hi, lo] => Some(ipv4_from(!hi, !lo)),
+        _ => None,
+    };
+    if let Some(v4) = embedded {
+        return is_blocked_ipv4(v4);
+    }
+
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || (segments[0] & 0xfe00) == 0xfc00 // unique local, includes fd00:ec2::254 metadata
+        || (segments[0] & 0xffc0) == 0xfe80 // link-local
+        || (segments[0] & 0xffc0) == 0xfec0 // deprecated site-local
+        || (segments[0] == 0x2001 && segments[1] == 0xdb8) // documentation
+}
+
+fn ipv4_from(hi: u16, lo: u16) -> Ipv4Addr {
+    Ipv4Addr::from((u32::from(hi) << 16) | u32::from(lo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_blocked_ranges() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "::127.0.0.1",
+            "::a00:1",
+            "2002:7f00:1::",
+            "2002:a9fe:a9fe::1",
+            "2001:0:4136:e378:8000:63bf:80ff:fffe",
+            "2001:0:4136:e378:8000:63bf:3fff:fdd2",
+        ] {
+            assert!(
+                is_blocked_ip(ip.parse().unwrap()),
+                "{} should be blocked",
+                ip
+            );
+        }
+
+        for ip in [
+            "93.184.216.34",
+            "1.1.1.1",
+            "2606:4700:4700::1111",
+            "2002:5db8:d822::1",
+            "2001:0:4136:e378:8000:63bf:a247:27dd",
+        ] {
+            assert!(
+                !is_blocked_ip(ip.parse().unwrap()),
+                "{} should be allowed",
+                ip
+            );
+        }
+    }
+
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches("example.com", "example.com"));
+        assert!(host_matches("example.com", "docs.example.com"));
+        assert!(!host_matches("example.com", "badexample.com"));
+    }
+
+    #[tokio::test]
+    async fn test_check_blocks_internal_targets() {
+        let guard = UrlGuard::default();
+        for target in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://127.0.0.1:11434/api/tags",
+            "http://2130706433/",
+            "http://[::1]/",
+            "http://localhost:11434/",
+            "file:///etc/passwd",
+        ] {
+            assert!(
+                guard.check(&url(target)).await.is_err(),
+                "{} should be blocked",
+                target
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_allow_and_deny_lists() {
+        let guard = UrlGuard::new(vec!["localhost".to_string()], vec![]);
+        assert!(guard
+            .check(&url("http://localhost:8080/wiki"))
+            .await
+            .is_ok());
+        assert!(guard.check(&url("http://93.184.216.34/")).await.is_err());
+
+        let guard = UrlGuard::new(vec![], vec!["*.example.com".to_string()]);
+        let err = guard
+            .check(&url("https://www.example.com/"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("deny list"));
+    }
+}
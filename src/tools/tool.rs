@@ -0,0 +1,281 @@
+//! The `Tool` trait that every tool the model can call implements.
+//!
+//! A tool describes its arguments once; the tag parser, the prompt's tool
+//! documentation and the native tool schemas are all generated from that.
+//! In tag mode the first argument is the body of the tag and the others are
+//! attributes, so `<search count="5">rust news</search>` calls `search` with
+//! `query` and `count`. A tool without arguments is called with an empty
+//! tag, such as `<now></now>`.
+
+use async_trait::async_trait;
+use serde_json::{json, Map, Value};
+
+use crate::error::AppError;
+use crate::ollama::types::OllamaTool;
+
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// Name used in tags, native calls and persona tool lists.
+    fn name(&self) -> &str;
+
+    /// Heading for the tool in the prompt, e.g. "Search the web".
+    fn title(&self) -> &str;
+
+    /// What the tool does, for the prompt and the native schema.
+    fn description(&self) -> &str;
+
+    /// The tool's arguments. The first, if any, is the main one and is
+    /// written as the tag body; it should be text, and is normally required.
+    fn arguments(&self) -> &[Argument];
+
+    /// Sample main argument for the prompt's example call.
+    fn example(&self) -> &str;
+
+    /// JSON schema of the arguments object, for native tool mode.
+    fn schema(&self) -> Value {
+        let properties: Map<String, Value> = self
+            .arguments()
+            .iter()
+            .map(|arg| (arg.name.clone(), arg.schema()))
+            .collect();
+        let required: Vec<&str> = self
+            .arguments()
+            .iter()
+            .filter(|arg| arg.required)
+            .map(|arg| arg.name.as_str())
+            .collect();
+
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required
+        })
+    }
+
+    /// Run a call whose arguments have been checked, returning the output
+    /// for the model.
+    async fn execute(&self, call: &ToolCall) -> Result<String, AppError>;
+
+    /// The result block the model sees after a successful call.
+    fn format_result(&self, _call: &ToolCall, output: &str) -> String {
+        result_block(self.name(), output)
+    }
+
+    /// The result block the model sees after a failed call.
+    fn format_error(&self, _call: &ToolCall, error: &str) -> String {
+        result_block(self.name(), &format!("Error: {}", error))
+    }
+
+    /// What a call consulted, as listed under answers in exports.
+    fn describe(&self, call: &ToolCall) -> String {
+        format!("Used {}: {}", self.name(), call.query())
+    }
+}
+
+/// Wrap tool output the way results are injected in tag mode.
+pub fn result_block(tool: &str, body: &str) -> String {
+    format!("[Tool Result: {}]\n{}\n[End Tool Result]", tool, body)
+}
+
+/// At most `max_bytes` of `text`, cut at a character boundary.
+pub fn truncate(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// A call with its arguments checked against the tool's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub tool: String,
+    /// Values in the order of the tool's arguments, main argument first
+    pub arguments: Vec<(String, Value)>,
+}
+
+impl ToolCall {
+    pub fn name(&self) -> &str {
+        &self.tool
+    }
+
+    /// The main argument, such as the query or URL.
+    pub fn query(&self) -> &str {
+        self.arguments
+            .first()
+            .and_then(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    }
+
+    pub fn arg(&self, name: &str) -> Option<&Value> {
+        self.arguments
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Argument {
+    pub name: String,
+    pub description: String,
+    pub kind: ArgumentKind,
+    pub required: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum ArgumentKind {
+    /// Free text; runs of whitespace, such as line wraps, become one space
+    Text,
+    /// A single word with no whitespace, such as a URL
+    Token,
+    /// Text passed on exactly as written, such as file contents
+    Verbatim,
+    Integer { min: i64, max: i64 },
+    Choice(Vec<String>),
+    /// Any other JSON value, described by its schema; written as JSON in tags
+    Json(Value),
+}
+
+impl Argument {
+    fn new(name: &str, description: &str, kind: ArgumentKind) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            kind,
+            required: false,
+        }
+    }
+
+    pub fn text(name: &str, description: &str) -> Self {
+        Self::new(name, description, ArgumentKind::Text)
+    }
+
+    pub fn token(name: &str, description: &str) -> Self {
+        Self::new(name, description, ArgumentKind::Token)
+    }
+
+    pub fn verbatim(name: &str, description: &str) -> Self {
+        Self::new(name, description, ArgumentKind::Verbatim)
+    }
+
+    pub fn integer(name: &str, description: &str, min: i64, max: i64) -> Self {
+        Self::new(name, description, ArgumentKind::Integer { min, max })
+    }
+
+    pub fn choice(name: &str, description: &str, choices: &[&str]) -> Self {
+        let choices = choices.iter().map(|c| c.to_string()).collect();
+        Self::new(name, description, ArgumentKind::Choice(choices))
+    }
+
+    pub fn json(name: &str, description: &str, schema: Value) -> Self {
+        Self::new(name, description, ArgumentKind::Json(schema))
+    }
+
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Check a value as written in a tag or sent by the model. Errors are
+    /// phrased for the model.
+    pub fn parse(&self, raw: &str) -> Result<Value, String> {
+        let name = &self.name;
+        match &self.kind {
+            ArgumentKind::Text => {
+                let text = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+                if text.is_empty() {
+                    return Err(format!("the {} is empty", name));
+                }
+                Ok(Value::String(text))
+            }
+            ArgumentKind::Token => {
+                let token = raw.trim();
+                if token.is_empty() {
+                    return Err(format!("the {} is empty", name));
+                }
+                if token.contains(char::is_whitespace) {
+                    return Err(format!("the {} \"{}\" contains whitespace", name, token));
+                }
+                Ok(Value::String(token.to_string()))
+            }
+            ArgumentKind::Verbatim => Ok(Value::String(raw.to_string())),
+            ArgumentKind::Integer { min, max } => raw
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .filter(|n| (min..=max).contains(&n))
+                .map(Value::from)
+                .ok_or_else(|| match (*min, *max) {
+                    (i64::MIN, i64::MAX) => {
+                        format!("{} must be a whole number, not \"{}\"", name, raw)
+                    }
+                    _ => format!(
+                        "{} must be a whole number from {} to {}, not \"{}\"",
+                        name, min, max, raw
+                    ),
+                }),
+            ArgumentKind::Choice(choices) => {
+                let raw_choice = raw.trim();
+                if let Some(choice) = choices.iter().find(|c| c.eq_ignore_ascii_case(raw_choice)) {
+                    Ok(Value::String(choice.clone()))
+                } else {
+                    Err(format!(
+                        "{} must be one of {}, not \"{}\"",
+                        name,
+                        choices.join(", "),
+                        raw
+                    ))
+                }
+            }
+            ArgumentKind::Json(_) => serde_json::from_str(raw.trim())
+                .map_err(|_| format!("{} must be written as JSON, not \"{}\"", name, raw)),
+        }
+    }
+
+    /// How to write the attribute in a tag, e.g. `count="1-20"`.
+    pub fn tag_hint(&self) -> String {
+        let value = match &self.kind {
+            ArgumentKind::Integer { min, max } if (*min, *max) != (i64::MIN, i64::MAX) => {
+                format!("{}-{}", min, max)
+            }
+            ArgumentKind::Choice(choices) => choices.join("|"),
+            _ => "…".to_string(),
+        };
+        format!("{}=\"{}\"", self.name, value)
+    }
+
+    fn schema(&self) -> Value {
+        let mut schema = match &self.kind {
+            ArgumentKind::Text | ArgumentKind::Token | ArgumentKind::Verbatim => {
+                json!({ "type": "string" })
+            }
+            ArgumentKind::Integer { min, max } => {
+                let mut schema = json!({ "type": "integer" });
+                if *min != i64::MIN {
+                    schema["minimum"] = json!(min);
+                }
+                if *max != i64::MAX {
+                    schema["maximum"] = json!(max);
+                }
+                schema
+            }
+            ArgumentKind::Choice(choices) => json!({ "type": "string", "enum": choices }),
+            ArgumentKind::Json(schema) => schema.clone(),
+        };
+        if let Some(object) = schema.as_object_mut() {
+            if !self.description.is_empty() {
+                object.insert("description".to_string(), json!(self.description));
+            }
+        }
+        schema
+    }
+}
+
+/// The function schema advertised for `tool` in native tool mode.
+pub fn native_schema(tool: &dyn Tool) -> OllamaTool {
+    OllamaTool::function(tool.name(), tool.description(), tool.schema())
+}